@@ -8,7 +8,7 @@ use lazy_static::lazy_static;
 use qoi::decode_to_vec;
 use toml::Table;
 
-use crate::{effect::effect_data::EffectData, graphics::{material::Material, model::Model, shader::Shader, texture::{Texture, TextureFormat}}, misc::Color32};
+use crate::{audio::sound::Sound, effect::effect_data::EffectData, graphics::{material::Material, model::Model, shader::Shader, texture::{Texture, TextureFormat}}, misc::Color32};
 
 lazy_static! {
     static ref TEXTURE_CACHE: RwLock<TextureCache> = RwLock::new(TextureCache::new());
@@ -17,6 +17,7 @@ lazy_static! {
     static ref MODEL_CACHE: RwLock<ModelCache> = RwLock::new(ModelCache::new());
     static ref EFFECT_CACHE: RwLock<EffectCache> = RwLock::new(EffectCache::new());
     static ref FONT_CACHE: RwLock<FontCache> = RwLock::new(FontCache::new());
+    static ref SOUND_CACHE: RwLock<SoundCache> = RwLock::new(SoundCache::new());
 }
 
 #[macro_export]
@@ -32,6 +33,7 @@ pub type MaterialHandle = Arc<LoadedAsset<Material>>;
 pub type ModelHandle = Arc<LoadedAsset<Model>>;
 pub type EffectHandle = Arc<LoadedAsset<EffectData>>;
 pub type FontHandle = Arc<LoadedAsset<Font>>;
+pub type SoundHandle = Arc<LoadedAsset<Sound>>;
 
 pub fn unload_texture(asset: &TextureHandle) {
     let tex_cache = &mut TEXTURE_CACHE.write().unwrap();
@@ -93,6 +95,16 @@ pub fn load_font(path: &str) -> Result<FontHandle, ResourceError> {
     return font_cache.load(path);
 }
 
+pub fn unload_sound(asset: &SoundHandle) {
+    let sound_cache = &mut SOUND_CACHE.write().unwrap();
+    sound_cache.unload(&asset.loaded_path);
+}
+
+pub fn load_sound(path: &str) -> Result<SoundHandle, ResourceError> {
+    let sound_cache = &mut SOUND_CACHE.write().unwrap();
+    return sound_cache.load(path);
+}
+
 pub fn clear_all() {
     TEXTURE_CACHE.write().unwrap().clear();
     SHADER_CACHE.write().unwrap().clear();
@@ -100,6 +112,7 @@ pub fn clear_all() {
     MODEL_CACHE.write().unwrap().clear();
     EFFECT_CACHE.write().unwrap().clear();
     FONT_CACHE.write().unwrap().clear();
+    SOUND_CACHE.write().unwrap().clear();
 }
 
 #[derive(Debug)]
@@ -343,6 +356,37 @@ impl ResourceLoader<Font> for FontLoader {
     }
 }
 
+pub struct SoundLoader {
+}
+
+impl ResourceLoader<Sound> for SoundLoader {
+    fn load_resource(path: &str) -> Result<Sound, ResourceError> {
+        let sound_data = match fs::read(path) {
+            Ok(v) => v,
+            Err(e) => return Err(ResourceError::IOError(e))
+        };
+
+        let sound = if path.ends_with(".wav") {
+            Sound::decode_wav(&sound_data)
+        }
+        else if path.ends_with(".ogg") {
+            Sound::decode_ogg(&sound_data)
+        }
+        else {
+            error!("Unsupported sound format");
+            return Err(ResourceError::ParseError);
+        };
+
+        match sound {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                error!("Failed decoding sound: {}", e);
+                Err(ResourceError::ParseError)
+            }
+        }
+    }
+}
+
 /// Wrapper around a loaded resource
 /// Provides information about what path a resource was loaded from
 pub struct LoadedAsset<TResource> {
@@ -442,4 +486,5 @@ pub type ShaderCache = ResourceCache<Shader, ShaderLoader>;
 pub type MaterialCache = ResourceCache<Material, MaterialLoader>;
 pub type ModelCache = ResourceCache<Model, ModelLoader>;
 pub type EffectCache = ResourceCache<EffectData, EffectLoader>;
-pub type FontCache = ResourceCache<Font, FontLoader>;
\ No newline at end of file
+pub type FontCache = ResourceCache<Font, FontLoader>;
+pub type SoundCache = ResourceCache<Sound, SoundLoader>;
\ No newline at end of file
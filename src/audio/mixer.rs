@@ -0,0 +1,350 @@
+use std::sync::Arc;
+
+use crate::asset_loader::SoundHandle;
+
+/// Gain applied to voices which are muffled (for example, sounds playing outside of the listener's PVS)
+const MUFFLE_GAIN: f32 = 0.5;
+
+/// Lowpass filter coefficient applied to muffled voices (0.0 = no filtering, closer to 1.0 = more muffled)
+const MUFFLE_LOWPASS: f32 = 0.85;
+
+/// Handle to a voice playing in a Mixer
+pub type VoiceId = u32;
+
+/// Per-voice parameters, which may be changed while the voice is playing
+#[derive(Clone, Copy)]
+pub struct VoiceParams {
+    pub volume: f32,
+    /// Stereo pan, from -1.0 (left) to 1.0 (right)
+    pub pan: f32,
+    pub muffled: bool,
+}
+
+impl Default for VoiceParams {
+    fn default() -> Self {
+        VoiceParams {
+            volume: 1.0,
+            pan: 0.0,
+            muffled: false,
+        }
+    }
+}
+
+struct Voice {
+    id: VoiceId,
+    sound: SoundHandle,
+    looping: bool,
+    params: VoiceParams,
+    /// Position in the sound, in source samples
+    position: f64,
+    /// Gains & filter coefficient used at the end of the previous block, ramped towards the target values to avoid clicks
+    gain_l: f32,
+    gain_r: f32,
+    lowpass: f32,
+    /// Previous filtered sample
+    filter_state: f32,
+    finished: bool,
+}
+
+impl Voice {
+    fn target_gains(self: &Self) -> (f32, f32, f32) {
+        // equal-power panning
+        let angle = (self.params.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        let volume = self.params.volume.max(0.0) * if self.params.muffled { MUFFLE_GAIN } else { 1.0 };
+        let lowpass = if self.params.muffled { MUFFLE_LOWPASS } else { 0.0 };
+
+        (volume * angle.cos(), volume * angle.sin(), lowpass)
+    }
+
+    fn sample_at(self: &Self, index: usize) -> f32 {
+        let samples = &self.sound.samples;
+
+        if index < samples.len() {
+            samples[index]
+        }
+        else if self.looping && !samples.is_empty() {
+            samples[index % samples.len()]
+        }
+        else {
+            0.0
+        }
+    }
+
+    // mix this voice into the interleaved stereo buffer
+    fn mix(self: &mut Self, output_rate: u32, buffer: &mut [f32], master_volume: f32) {
+        let num_frames = buffer.len() / 2;
+        let len = self.sound.samples.len();
+
+        if num_frames == 0 {
+            return;
+        }
+
+        if len == 0 {
+            self.finished = true;
+            return;
+        }
+
+        let step = self.sound.sample_rate as f64 / output_rate as f64;
+        let (target_l, target_r, target_lowpass) = self.target_gains();
+
+        for (i, frame) in buffer.chunks_exact_mut(2).enumerate() {
+            if self.position >= len as f64 {
+                if self.looping {
+                    self.position %= len as f64;
+                }
+                else {
+                    self.finished = true;
+                    break;
+                }
+            }
+
+            // linearly interpolate between source samples
+            let index = self.position as usize;
+            let t = (self.position - index as f64) as f32;
+            let s = self.sample_at(index) * (1.0 - t) + self.sample_at(index + 1) * t;
+
+            let ramp = (i + 1) as f32 / num_frames as f32;
+            let lowpass = self.lowpass + (target_lowpass - self.lowpass) * ramp;
+            self.filter_state = s * (1.0 - lowpass) + self.filter_state * lowpass;
+
+            let gain_l = self.gain_l + (target_l - self.gain_l) * ramp;
+            let gain_r = self.gain_r + (target_r - self.gain_r) * ramp;
+
+            frame[0] += self.filter_state * gain_l * master_volume;
+            frame[1] += self.filter_state * gain_r * master_volume;
+
+            self.position += step;
+        }
+
+        self.gain_l = target_l;
+        self.gain_r = target_r;
+        self.lowpass = target_lowpass;
+    }
+}
+
+/// Software mixer which resamples, pans & mixes mono sounds into an interleaved stereo buffer
+pub struct Mixer {
+    sample_rate: u32,
+    master_volume: f32,
+    voices: Vec<Voice>,
+    next_id: VoiceId,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Mixer {
+        Mixer {
+            sample_rate,
+            master_volume: 1.0,
+            voices: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Output sample rate of the mixer
+    pub fn sample_rate(self: &Self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_master_volume(self: &mut Self, volume: f32) {
+        self.master_volume = volume.max(0.0);
+    }
+
+    /// Start playing a sound, returning a handle which can be used to update or stop the voice
+    pub fn play(self: &mut Self, sound: &SoundHandle, params: VoiceParams, looping: bool) -> VoiceId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        let mut voice = Voice {
+            id,
+            sound: Arc::clone(sound),
+            looping,
+            params,
+            position: 0.0,
+            gain_l: 0.0,
+            gain_r: 0.0,
+            lowpass: 0.0,
+            filter_state: 0.0,
+            finished: false,
+        };
+
+        // start at the target gains, so the first block doesn't fade in
+        (voice.gain_l, voice.gain_r, voice.lowpass) = voice.target_gains();
+
+        self.voices.push(voice);
+        id
+    }
+
+    /// Update the parameters of a playing voice. Changes are ramped over the next rendered block
+    pub fn set_params(self: &mut Self, id: VoiceId, params: VoiceParams) {
+        if let Some(voice) = self.voices.iter_mut().find(|x| x.id == id) {
+            voice.params = params;
+        }
+    }
+
+    pub fn stop(self: &mut Self, id: VoiceId) {
+        self.voices.retain(|x| x.id != id);
+    }
+
+    /// Stop every voice which the given predicate returns false for
+    pub fn retain_voices<F>(self: &mut Self, f: F) where F : Fn(VoiceId) -> bool {
+        self.voices.retain(|x| f(x.id));
+    }
+
+    pub fn stop_all(self: &mut Self) {
+        self.voices.clear();
+    }
+
+    /// Whether the given voice is still playing. One-shot voices stop once they reach the end of their sound
+    pub fn is_playing(self: &Self, id: VoiceId) -> bool {
+        self.voices.iter().any(|x| x.id == id)
+    }
+
+    pub fn num_voices(self: &Self) -> usize {
+        self.voices.len()
+    }
+
+    /// Render the next block of audio into the given interleaved stereo buffer, overwriting its contents
+    pub fn render(self: &mut Self, buffer: &mut [f32]) {
+        buffer.fill(0.0);
+
+        for voice in &mut self.voices {
+            voice.mix(self.sample_rate, buffer, self.master_volume);
+        }
+
+        self.voices.retain(|x| !x.finished);
+
+        for s in buffer.iter_mut() {
+            *s = s.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{asset_loader::{LoadedAsset, SoundHandle}, audio::sound::Sound};
+
+    use super::*;
+
+    const TEST_RATE: u32 = 100;
+
+    fn test_sound(samples: &[f32]) -> SoundHandle {
+        Arc::new(LoadedAsset::new(Sound { sample_rate: TEST_RATE, samples: samples.to_vec() }, "<test>"))
+    }
+
+    fn render_frames(mixer: &mut Mixer, num_frames: usize) -> Vec<f32> {
+        let mut buffer = vec![0.0;num_frames * 2];
+        mixer.render(&mut buffer);
+        buffer
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.0001, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn pan() {
+        let sound = test_sound(&[0.5;16]);
+
+        let mut mixer = Mixer::new(TEST_RATE);
+        mixer.play(&sound, VoiceParams { pan: -1.0, ..Default::default() }, false);
+        let buffer = render_frames(&mut mixer, 4);
+        assert_near(buffer[0], 0.5);
+        assert_near(buffer[1], 0.0);
+
+        let mut mixer = Mixer::new(TEST_RATE);
+        mixer.play(&sound, VoiceParams { pan: 1.0, ..Default::default() }, false);
+        let buffer = render_frames(&mut mixer, 4);
+        assert_near(buffer[0], 0.0);
+        assert_near(buffer[1], 0.5);
+
+        // equal power panning, so centered sounds are quieter in each channel
+        let mut mixer = Mixer::new(TEST_RATE);
+        mixer.play(&sound, VoiceParams::default(), false);
+        let buffer = render_frames(&mut mixer, 4);
+        assert_near(buffer[0], 0.5 * std::f32::consts::FRAC_1_SQRT_2);
+        assert_near(buffer[1], 0.5 * std::f32::consts::FRAC_1_SQRT_2);
+    }
+
+    #[test]
+    fn volume_attenuation() {
+        let sound = test_sound(&[1.0;16]);
+
+        let mut mixer = Mixer::new(TEST_RATE);
+        let voice = mixer.play(&sound, VoiceParams { volume: 0.5, pan: -1.0, muffled: false }, false);
+        let buffer = render_frames(&mut mixer, 4);
+        assert_near(buffer[0], 0.5);
+
+        // volume changes are ramped over the next block
+        mixer.set_params(voice, VoiceParams { volume: 0.0, pan: -1.0, muffled: false });
+        let buffer = render_frames(&mut mixer, 4);
+        assert!(buffer[0] > 0.0 && buffer[0] < 0.5);
+        assert_near(buffer[6], 0.0);
+
+        let buffer = render_frames(&mut mixer, 4);
+        assert!(buffer.iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn one_shot_stops_at_end() {
+        let sound = test_sound(&[0.25, 0.5, 0.75, 1.0]);
+
+        let mut mixer = Mixer::new(TEST_RATE);
+        let voice = mixer.play(&sound, VoiceParams { pan: -1.0, ..Default::default() }, false);
+        let buffer = render_frames(&mut mixer, 8);
+
+        assert_near(buffer[0], 0.25);
+        assert_near(buffer[6], 1.0);
+        assert!(buffer[8..].iter().all(|x| *x == 0.0));
+        assert!(!mixer.is_playing(voice));
+        assert_eq!(mixer.num_voices(), 0);
+    }
+
+    #[test]
+    fn looping_wraps_around() {
+        let sound = test_sound(&[0.25, 0.5, 0.75, 1.0]);
+
+        let mut mixer = Mixer::new(TEST_RATE);
+        let voice = mixer.play(&sound, VoiceParams { pan: -1.0, ..Default::default() }, true);
+        let buffer = render_frames(&mut mixer, 8);
+
+        assert_near(buffer[8], 0.25);
+        assert_near(buffer[14], 1.0);
+        assert!(mixer.is_playing(voice));
+
+        // keeps playing across blocks
+        let buffer = render_frames(&mut mixer, 2);
+        assert_near(buffer[0], 0.25);
+        assert_near(buffer[2], 0.5);
+    }
+
+    #[test]
+    fn output_is_clamped() {
+        let sound = test_sound(&[1.0;4]);
+
+        let mut mixer = Mixer::new(TEST_RATE);
+        mixer.play(&sound, VoiceParams { pan: -1.0, ..Default::default() }, false);
+        mixer.play(&sound, VoiceParams { pan: -1.0, ..Default::default() }, false);
+        let buffer = render_frames(&mut mixer, 2);
+
+        assert_eq!(buffer[0], 1.0);
+    }
+
+    #[test]
+    fn retain_voices() {
+        let sound = test_sound(&[1.0;4]);
+
+        let mut mixer = Mixer::new(TEST_RATE);
+        let a = mixer.play(&sound, VoiceParams::default(), true);
+        let b = mixer.play(&sound, VoiceParams::default(), true);
+        let c = mixer.play(&sound, VoiceParams::default(), true);
+
+        mixer.retain_voices(|x| x == b);
+
+        assert!(!mixer.is_playing(a));
+        assert!(mixer.is_playing(b));
+        assert!(!mixer.is_playing(c));
+        assert_eq!(mixer.num_voices(), 1);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod sound;
+pub mod mixer;
+pub mod output;
\ No newline at end of file
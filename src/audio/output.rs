@@ -0,0 +1,39 @@
+use std::sync::{Arc, Mutex};
+
+use log::info;
+use sdl2::{audio::{AudioCallback, AudioDevice, AudioSpecDesired}, AudioSubsystem};
+
+use super::mixer::Mixer;
+
+pub const OUTPUT_SAMPLE_RATE: i32 = 44100;
+const OUTPUT_BUFFER_SAMPLES: u16 = 1024;
+
+/// SDL audio callback which pulls each block of audio from the shared mixer
+pub struct MixerCallback {
+    mixer: Arc<Mutex<Mixer>>,
+}
+
+impl AudioCallback for MixerCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.mixer.lock().unwrap().render(out);
+    }
+}
+
+/// Open the default audio output device & start playing the given mixer through it
+pub fn open_audio_device(audio: &AudioSubsystem, mixer: &Arc<Mutex<Mixer>>) -> Result<AudioDevice<MixerCallback>, String> {
+    let desired_spec = AudioSpecDesired {
+        freq: Some(OUTPUT_SAMPLE_RATE),
+        channels: Some(2),
+        samples: Some(OUTPUT_BUFFER_SAMPLES),
+    };
+
+    let device = audio.open_playback(None, &desired_spec, |spec| {
+        info!("Opened audio device: {} Hz, {} channels, {} samples", spec.freq, spec.channels, spec.samples);
+        MixerCallback { mixer: mixer.clone() }
+    })?;
+
+    device.resume();
+    Ok(device)
+}
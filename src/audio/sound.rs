@@ -0,0 +1,276 @@
+use std::{fmt::Display, io::{Cursor, Read, Seek, SeekFrom}};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use lewton::{inside_ogg::OggStreamReader, VorbisError};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+#[derive(Debug)]
+pub enum SoundError {
+    IOError(std::io::Error),
+    InvalidWav,
+    UnsupportedWavFormat(u16, u16),
+    OggError(VorbisError),
+    UnsupportedChannelCount(u16),
+}
+
+impl Display for SoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SoundError::IOError(e) => write!(f, "IO error: {}", e),
+            SoundError::InvalidWav => write!(f, "Not a valid WAV file"),
+            SoundError::UnsupportedWavFormat(format, bits) => write!(f, "Unsupported WAV format: {} ({} bits per sample)", format, bits),
+            SoundError::OggError(e) => write!(f, "Failed decoding Ogg Vorbis: {}", e),
+            SoundError::UnsupportedChannelCount(v) => write!(f, "Unsupported channel count: {}", v),
+        }
+    }
+}
+
+impl From<std::io::Error> for SoundError {
+    fn from(value: std::io::Error) -> Self {
+        SoundError::IOError(value)
+    }
+}
+
+impl From<VorbisError> for SoundError {
+    fn from(value: VorbisError) -> Self {
+        SoundError::OggError(value)
+    }
+}
+
+/// Decoded sound data. Sounds are always mixed positionally, so multi-channel sounds are downmixed to mono when loaded
+pub struct Sound {
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+impl Sound {
+    /// Length of the sound in seconds
+    pub fn duration(self: &Self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+
+    // average interleaved samples with the given number of channels down to a single channel
+    fn from_interleaved(sample_rate: u32, channels: u16, samples: Vec<f32>) -> Result<Sound, SoundError> {
+        let samples = match channels {
+            0 => return Err(SoundError::UnsupportedChannelCount(channels)),
+            1 => samples,
+            _ => samples.chunks_exact(channels as usize)
+                .map(|x| x.iter().sum::<f32>() / channels as f32)
+                .collect()
+        };
+
+        Ok(Sound { sample_rate, samples })
+    }
+
+    /// Decode an uncompressed WAV file (8, 16, 24 or 32 bit PCM, or 32 bit float)
+    pub fn decode_wav(data: &[u8]) -> Result<Sound, SoundError> {
+        let mut reader = Cursor::new(data);
+
+        let mut riff = [0;4];
+        reader.read_exact(&mut riff)?;
+        let _riff_size = reader.read_u32::<LittleEndian>()?;
+        let mut wave = [0;4];
+        reader.read_exact(&mut wave)?;
+
+        if &riff != b"RIFF" || &wave != b"WAVE" {
+            return Err(SoundError::InvalidWav);
+        }
+
+        let mut format = None;
+
+        // walk chunks until the data chunk is found (fmt must come first)
+        loop {
+            let mut chunk_id = [0;4];
+            reader.read_exact(&mut chunk_id)?;
+            let chunk_size = reader.read_u32::<LittleEndian>()? as usize;
+            let chunk_start = reader.position();
+
+            match &chunk_id {
+                b"fmt " => {
+                    let format_tag = reader.read_u16::<LittleEndian>()?;
+                    let channels = reader.read_u16::<LittleEndian>()?;
+                    let sample_rate = reader.read_u32::<LittleEndian>()?;
+                    let _byte_rate = reader.read_u32::<LittleEndian>()?;
+                    let _block_align = reader.read_u16::<LittleEndian>()?;
+                    let bits_per_sample = reader.read_u16::<LittleEndian>()?;
+
+                    format = Some((format_tag, channels, sample_rate, bits_per_sample));
+                }
+                b"data" => {
+                    let (format_tag, channels, sample_rate, bits_per_sample) = match format {
+                        Some(v) => v,
+                        None => return Err(SoundError::InvalidWav)
+                    };
+
+                    let end = (chunk_start as usize + chunk_size).min(data.len());
+                    let mut reader = Cursor::new(&data[chunk_start as usize..end]);
+                    let num_samples = (end - chunk_start as usize) / (bits_per_sample as usize / 8).max(1);
+                    let mut samples = Vec::with_capacity(num_samples);
+
+                    for _ in 0..num_samples {
+                        let sample = match (format_tag, bits_per_sample) {
+                            (WAVE_FORMAT_PCM, 8) => (reader.read_u8()? as f32 - 128.0) / 128.0,
+                            (WAVE_FORMAT_PCM, 16) => reader.read_i16::<LittleEndian>()? as f32 / 32768.0,
+                            (WAVE_FORMAT_PCM, 24) => reader.read_i24::<LittleEndian>()? as f32 / 8388608.0,
+                            (WAVE_FORMAT_PCM, 32) => reader.read_i32::<LittleEndian>()? as f32 / 2147483648.0,
+                            (WAVE_FORMAT_IEEE_FLOAT, 32) => reader.read_f32::<LittleEndian>()?,
+                            _ => return Err(SoundError::UnsupportedWavFormat(format_tag, bits_per_sample))
+                        };
+
+                        samples.push(sample);
+                    }
+
+                    return Sound::from_interleaved(sample_rate, channels, samples);
+                }
+                _ => {}
+            }
+
+            // chunks are padded to an even number of bytes
+            reader.seek(SeekFrom::Start(chunk_start + ((chunk_size + 1) & !1) as u64))?;
+        }
+    }
+
+    /// Decode an Ogg Vorbis file
+    pub fn decode_ogg(data: &[u8]) -> Result<Sound, SoundError> {
+        let mut reader = OggStreamReader::new(Cursor::new(data))?;
+
+        let sample_rate = reader.ident_hdr.audio_sample_rate;
+        let channels = reader.ident_hdr.audio_channels as u16;
+
+        let mut samples = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl()? {
+            samples.extend(packet.iter().map(|x| *x as f32 / 32768.0));
+        }
+
+        Sound::from_interleaved(sample_rate, channels, samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+
+    // build a WAV file with a fmt chunk & a data chunk containing the given raw sample data
+    fn build_wav(format_tag: u16, channels: u16, sample_rate: u32, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+        let block_align = channels * bits_per_sample / 8;
+
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.write_u32::<LittleEndian>(36 + data.len() as u32).unwrap();
+        wav.extend_from_slice(b"WAVE");
+
+        wav.extend_from_slice(b"fmt ");
+        wav.write_u32::<LittleEndian>(16).unwrap();
+        wav.write_u16::<LittleEndian>(format_tag).unwrap();
+        wav.write_u16::<LittleEndian>(channels).unwrap();
+        wav.write_u32::<LittleEndian>(sample_rate).unwrap();
+        wav.write_u32::<LittleEndian>(sample_rate * block_align as u32).unwrap();
+        wav.write_u16::<LittleEndian>(block_align).unwrap();
+        wav.write_u16::<LittleEndian>(bits_per_sample).unwrap();
+
+        wav.extend_from_slice(b"data");
+        wav.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+        wav.extend_from_slice(data);
+        wav
+    }
+
+    fn pcm16(samples: &[i16]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for s in samples {
+            data.write_i16::<LittleEndian>(*s).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn decode_pcm16_mono() {
+        let wav = build_wav(WAVE_FORMAT_PCM, 1, 22050, 16, &pcm16(&[0, 16384, -32768]));
+        let sound = Sound::decode_wav(&wav).unwrap();
+
+        assert_eq!(sound.sample_rate, 22050);
+        assert_eq!(sound.samples, vec![0.0, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn decode_pcm8() {
+        let wav = build_wav(WAVE_FORMAT_PCM, 1, 11025, 8, &[128, 192, 0]);
+        let sound = Sound::decode_wav(&wav).unwrap();
+
+        assert_eq!(sound.samples, vec![0.0, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn decode_float() {
+        let mut data = Vec::new();
+        data.write_f32::<LittleEndian>(0.25).unwrap();
+        data.write_f32::<LittleEndian>(-0.75).unwrap();
+
+        let wav = build_wav(WAVE_FORMAT_IEEE_FLOAT, 1, 44100, 32, &data);
+        let sound = Sound::decode_wav(&wav).unwrap();
+
+        assert_eq!(sound.samples, vec![0.25, -0.75]);
+    }
+
+    #[test]
+    fn stereo_downmixed_to_mono() {
+        let wav = build_wav(WAVE_FORMAT_PCM, 2, 44100, 16, &pcm16(&[16384, 0, -16384, -16384]));
+        let sound = Sound::decode_wav(&wav).unwrap();
+
+        assert_eq!(sound.samples, vec![0.25, -0.5]);
+    }
+
+    #[test]
+    fn skips_unknown_chunks() {
+        let mut wav = build_wav(WAVE_FORMAT_PCM, 1, 22050, 16, &pcm16(&[16384]));
+
+        // odd sized chunk between fmt & data, which is padded to an even size
+        let data_chunk = wav.split_off(36);
+        wav.extend_from_slice(b"LIST");
+        wav.write_u32::<LittleEndian>(3).unwrap();
+        wav.extend_from_slice(&[1, 2, 3, 0]);
+        wav.extend_from_slice(&data_chunk);
+
+        let sound = Sound::decode_wav(&wav).unwrap();
+        assert_eq!(sound.samples, vec![0.5]);
+    }
+
+    #[test]
+    fn malformed_header() {
+        let mut wav = build_wav(WAVE_FORMAT_PCM, 1, 22050, 16, &pcm16(&[0]));
+        wav[8..12].copy_from_slice(b"AVI ");
+
+        assert!(matches!(Sound::decode_wav(&wav), Err(SoundError::InvalidWav)));
+        assert!(matches!(Sound::decode_wav(b"RIFF"), Err(SoundError::IOError(_))));
+    }
+
+    #[test]
+    fn data_before_fmt() {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.write_u32::<LittleEndian>(14).unwrap();
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(b"data");
+        wav.write_u32::<LittleEndian>(2).unwrap();
+        wav.extend_from_slice(&[0, 0]);
+
+        assert!(matches!(Sound::decode_wav(&wav), Err(SoundError::InvalidWav)));
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let wav = build_wav(WAVE_FORMAT_IEEE_FLOAT, 1, 22050, 64, &[0;8]);
+        assert!(matches!(Sound::decode_wav(&wav), Err(SoundError::UnsupportedWavFormat(WAVE_FORMAT_IEEE_FLOAT, 64))));
+    }
+
+    #[test]
+    fn missing_data_chunk() {
+        let mut wav = build_wav(WAVE_FORMAT_PCM, 1, 22050, 16, &[]);
+        wav.truncate(36);
+
+        assert!(matches!(Sound::decode_wav(&wav), Err(SoundError::IOError(_))));
+    }
+}
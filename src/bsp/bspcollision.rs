@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 use hecs::Entity;
 
-use crate::{bsp::bspfile::MASK_SOLID, math::Vector3, misc::AABB};
+use crate::{bsp::bspfile::{CONTENTS_SOLID, MASK_SOLID}, math::Vector3, misc::AABB};
 
 use super::bspfile::BspFile;
 
@@ -14,9 +14,34 @@ pub struct Trace {
     pub fraction: f32,
     pub end_pos: Vector3,
     pub hit_normal: Vector3,
+    /// Index of the plane which was hit, or None if nothing was hit or the hit was against an entity's bounds
+    pub hit_plane: Option<usize>,
+    /// Index of the TexInfo of the brush side which was hit, if any
+    pub tex_info: Option<usize>,
+    /// SURF_* flags of the surface which was hit
+    pub surface_flags: u32,
+    /// CONTENTS_* flags of the brush which was hit (or which the trace started inside of)
+    pub contents: u32,
     pub entity: Option<Entity>
 }
 
+impl Default for Trace {
+    fn default() -> Self {
+        Trace {
+            all_solid: false,
+            start_solid: false,
+            fraction: 1.0,
+            end_pos: Vector3::zero(),
+            hit_normal: Vector3::zero(),
+            hit_plane: None,
+            tex_info: None,
+            surface_flags: 0,
+            contents: 0,
+            entity: None
+        }
+    }
+}
+
 impl BspFile {
     pub fn trace_aabb(bounds: &AABB, start: &Vector3, end: &Vector3, box_extents: Option<&Vector3>, trace: &mut Trace) -> bool {
         let planes = [
@@ -86,6 +111,7 @@ impl BspFile {
 
         if !startout {
             trace.start_solid = true;
+            trace.contents = CONTENTS_SOLID;
             if !getout {
                 trace.all_solid = true;
             }
@@ -103,6 +129,12 @@ impl BspFile {
                 trace.hit_normal = hit_normal;
                 trace.end_pos = *start + ((*end - *start) * enterfrac);
 
+                // boxes have no surface of their own
+                trace.hit_plane = None;
+                trace.tex_info = None;
+                trace.surface_flags = 0;
+                trace.contents = CONTENTS_SOLID;
+
                 return true;
             }
         }
@@ -118,6 +150,7 @@ impl BspFile {
         }
 
         let mut hit_normal = Vector3::zero();
+        let mut hit_side = None;
         let mut enterfrac = f32::MIN;
         let mut exitfrac = 1.0;
         let mut startout = false;
@@ -166,6 +199,7 @@ impl BspFile {
                 if f > enterfrac {
                     enterfrac = f;
                     hit_normal = plane.normal;
+                    hit_side = Some(side);
                 }
             }
             else {
@@ -178,6 +212,7 @@ impl BspFile {
         
         if !startout {
             trace.start_solid = true;
+            trace.contents = brush.contents;
             if !getout {
                 trace.all_solid = true;
             }
@@ -193,6 +228,15 @@ impl BspFile {
 
                 trace.fraction = enterfrac + frac_adj;
                 trace.hit_normal = hit_normal;
+                trace.contents = brush.contents;
+
+                if let Some(side) = hit_side {
+                    let tex_info = if side.tex == u16::MAX { None } else { Some(side.tex as usize) };
+
+                    trace.hit_plane = Some(side.plane as usize);
+                    trace.tex_info = tex_info;
+                    trace.surface_flags = tex_info.and_then(|x| self.tex_info_lump.textures.get(x)).map_or(0, |x| x.flags);
+                }
             }
         }
     }
@@ -217,7 +261,7 @@ impl BspFile {
             let brush = &self.brush_lump.brushes[brush_idx as usize];
 
             if brush.contents & content_mask == 0 {
-                return;
+                continue;
             }
 
             self.trace_brush(brush_idx as usize, start, end, frac_adj, box_extents, trace);
@@ -318,14 +362,7 @@ impl BspFile {
     pub fn box_check(self: &Self, content_mask: u32, start: Vector3, box_extents: Vector3) -> bool {
         let head_node = self.submodel_lump.submodels[0].headnode as i32;
 
-        let mut trace_trace = Trace {
-            all_solid: false,
-            start_solid: false,
-            fraction: 1.0,
-            end_pos: Vector3::zero(),
-            hit_normal: Vector3::zero(),
-            entity: None
-        };
+        let mut trace_trace = Trace::default();
 
         self.recursive_trace(head_node, &mut HashSet::<u16>::new(), content_mask, 0.0, 1.0, start, start, 0.0, Some(box_extents), &mut trace_trace);
 
@@ -336,14 +373,7 @@ impl BspFile {
     pub fn boxtrace(self: &Self, model_index: usize, content_mask: u32, start: Vector3, end: Vector3, box_extents: Vector3) -> Trace {
         let head_node = self.submodel_lump.submodels[model_index].headnode as i32;
 
-        let mut trace_trace = Trace {
-            all_solid: false,
-            start_solid: false,
-            fraction: 1.0,
-            end_pos: Vector3::zero(),
-            hit_normal: Vector3::zero(),
-            entity: None
-        };
+        let mut trace_trace = Trace::default();
 
         self.recursive_trace(head_node, &mut HashSet::<u16>::new(), content_mask, 0.0, 1.0, start, end, 0.0, Some(box_extents), &mut trace_trace);
 
@@ -361,14 +391,7 @@ impl BspFile {
     pub fn linetrace(self: &Self, model_index: usize, content_mask: u32, start: Vector3, end: Vector3) -> Trace {
         let head_node = self.submodel_lump.submodels[model_index].headnode as i32;
 
-        let mut trace_trace = Trace {
-            all_solid: false,
-            start_solid: false,
-            fraction: 1.0,
-            end_pos: Vector3::zero(),
-            hit_normal: Vector3::zero(),
-            entity: None
-        };
+        let mut trace_trace = Trace::default();
 
         self.recursive_trace(head_node, &mut HashSet::<u16>::new(), content_mask, 0.0, 1.0, start, end, 0.0, None, &mut trace_trace);
 
@@ -404,7 +427,32 @@ impl BspFile {
         return -cur_node - 1;
     }
 
-    /// Attempts to sweep a box through the world, sliding along any surfaces it hits and returning a new position and velocity as well as trace hit information
+    /// Get the CONTENTS_* flags of the world at the given point
+    pub fn point_contents(self: &Self, position: &Vector3) -> u32 {
+        self.model_point_contents(0, position)
+    }
+
+    /// Get the CONTENTS_* flags of the given submodel at the given point (in the submodel's local space)
+    pub fn model_point_contents(self: &Self, model_index: usize, position: &Vector3) -> u32 {
+        let mut cur_node = self.submodel_lump.submodels[model_index].headnode as i32;
+
+        while cur_node >= 0 {
+            let node = &self.node_lump.nodes[cur_node as usize];
+            let plane = &self.plane_lump.planes[node.plane as usize];
+
+            if position.dot(plane.normal) - plane.distance >= 0.0 {
+                cur_node = node.front_child;
+            }
+            else {
+                cur_node = node.back_child;
+            }
+        }
+
+        self.leaf_lump.leaves[(-cur_node - 1) as usize].contents
+    }
+
+    /// Attempts to sweep a box through the world, sliding along any surfaces it hits and returning a new position and velocity as well as trace hit information.
+    /// The returned trace describes the first surface hit, except for its contents which include the contents of every surface hit while sliding
     /// 
     /// # Arguments
     /// 
@@ -424,14 +472,7 @@ impl BspFile {
         let mut planes: [Vector3; NUM_ITERATIONS] = [Vector3::zero(); NUM_ITERATIONS];
         let mut num_planes: usize = 0;
 
-        let mut ret_trace = Trace {
-            all_solid: false,
-            start_solid: false,
-            fraction: 1.0,
-            end_pos: Vector3::zero(),
-            hit_normal: Vector3::zero(),
-            entity: None
-        };
+        let mut ret_trace = Trace::default();
 
         for _iter in 0..NUM_ITERATIONS {
             let end = cur_pos + (cur_velocity * remaining_delta);
@@ -451,6 +492,10 @@ impl BspFile {
             if ret_trace.fraction == 1.0 {
                 ret_trace = trace;
             }
+            else if trace.fraction < 1.0 {
+                // remember every surface touched while sliding (so that, for example, sliding along a wall into a ladder still counts as touching the ladder)
+                ret_trace.contents |= trace.contents;
+            }
 
             if trace.fraction == 1.0 || !allow_sliding {
                 break;
@@ -1,5 +1,5 @@
-use std::{collections::HashMap, io::Seek};
-use log::info;
+use std::{collections::HashMap, fmt::Display, io::Seek};
+use log::{info, warn};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 use regex::Regex;
@@ -10,6 +10,11 @@ const BSP_MAGIC: u32 = 0x50534249;  // IBSP
 const BSPX_MAGIC: u32 = 0x58505342; // BSPX
 const BSP_VERSION: u32 = 38;
 
+const LUMP_NAMES: [&str; 19] = [
+    "ENTITIES", "PLANES", "VERTICES", "VISIBILITY", "NODES", "TEXINFO", "FACES", "LIGHTING", "LEAVES", "LEAFFACES",
+    "LEAFBRUSHES", "EDGES", "SURFEDGES", "MODELS", "BRUSHES", "BRUSHSIDES", "POP", "AREAS", "AREAPORTALS"
+];
+
 pub const SURF_LIGHT: u32   = 0x1;
 pub const SURF_SLICK: u32   = 0x2;
 pub const SURF_SKY: u32     = 0x4;
@@ -28,71 +33,122 @@ pub const CONTENTS_LAVA: u32        = 8;
 pub const CONTENTS_SLIME: u32       = 16;
 pub const CONTENTS_WATER: u32       = 32;
 pub const CONTENTS_MIST: u32        = 64;
+pub const CONTENTS_LADDER: u32      = 0x20000000;
 
 pub const MASK_SOLID: u32           = CONTENTS_SOLID | CONTENTS_WINDOW;
+pub const MASK_WATER: u32           = CONTENTS_WATER | CONTENTS_SLIME | CONTENTS_LAVA;
 
-fn read_vec2f<R: ReadBytesExt>(reader: &mut R) -> Vector2 {
-    let x = reader.read_f32::<LittleEndian>().unwrap();
-    let y = reader.read_f32::<LittleEndian>().unwrap();
+fn read_vec2f<R: ReadBytesExt>(reader: &mut R) -> Result<Vector2, std::io::Error> {
+    let x = reader.read_f32::<LittleEndian>()?;
+    let y = reader.read_f32::<LittleEndian>()?;
 
-    Vector2::new(x, y)
+    Ok(Vector2::new(x, y))
 }
 
-fn read_vec3f<R: ReadBytesExt>(reader: &mut R) -> Vector3 {
-    let x = reader.read_f32::<LittleEndian>().unwrap();
-    let y = reader.read_f32::<LittleEndian>().unwrap();
-    let z = reader.read_f32::<LittleEndian>().unwrap();
+fn read_vec3f<R: ReadBytesExt>(reader: &mut R) -> Result<Vector3, std::io::Error> {
+    let x = reader.read_f32::<LittleEndian>()?;
+    let y = reader.read_f32::<LittleEndian>()?;
+    let z = reader.read_f32::<LittleEndian>()?;
 
-    Vector3::new(x, y, z)
+    Ok(Vector3::new(x, y, z))
 }
 
-fn read_vec4f<R: ReadBytesExt>(reader: &mut R) -> Vector4 {
-    let x = reader.read_f32::<LittleEndian>().unwrap();
-    let y = reader.read_f32::<LittleEndian>().unwrap();
-    let z = reader.read_f32::<LittleEndian>().unwrap();
-    let w = reader.read_f32::<LittleEndian>().unwrap();
+fn read_vec4f<R: ReadBytesExt>(reader: &mut R) -> Result<Vector4, std::io::Error> {
+    let x = reader.read_f32::<LittleEndian>()?;
+    let y = reader.read_f32::<LittleEndian>()?;
+    let z = reader.read_f32::<LittleEndian>()?;
+    let w = reader.read_f32::<LittleEndian>()?;
 
-    Vector4::new(x, y, z, w)
+    Ok(Vector4::new(x, y, z, w))
 }
 
-fn read_vec3s<R: ReadBytesExt>(reader: &mut R) -> Vector3 {
-    let x = reader.read_i16::<LittleEndian>().unwrap() as f32;
-    let y = reader.read_i16::<LittleEndian>().unwrap() as f32;
-    let z = reader.read_i16::<LittleEndian>().unwrap() as f32;
+fn read_vec3s<R: ReadBytesExt>(reader: &mut R) -> Result<Vector3, std::io::Error> {
+    let x = reader.read_i16::<LittleEndian>()? as f32;
+    let y = reader.read_i16::<LittleEndian>()? as f32;
+    let z = reader.read_i16::<LittleEndian>()? as f32;
 
-    Vector3::new(x, y, z)
+    Ok(Vector3::new(x, y, z))
 }
 
-fn read_vec3i<R: ReadBytesExt>(reader: &mut R) -> Vector3 {
-    let x = reader.read_i32::<LittleEndian>().unwrap() as f32;
-    let y = reader.read_i32::<LittleEndian>().unwrap() as f32;
-    let z = reader.read_i32::<LittleEndian>().unwrap() as f32;
+fn read_vec3i<R: ReadBytesExt>(reader: &mut R) -> Result<Vector3, std::io::Error> {
+    let x = reader.read_i32::<LittleEndian>()? as f32;
+    let y = reader.read_i32::<LittleEndian>()? as f32;
+    let z = reader.read_i32::<LittleEndian>()? as f32;
 
-    Vector3::new(x, y, z)
+    Ok(Vector3::new(x, y, z))
 }
 
-fn read_color24<R: ReadBytesExt>(reader: &mut R) -> Color32 {
-    let r = reader.read_u8().unwrap();
-    let g = reader.read_u8().unwrap();
-    let b = reader.read_u8().unwrap();
+fn read_color24<R: ReadBytesExt>(reader: &mut R) -> Result<Color32, std::io::Error> {
+    let r = reader.read_u8()?;
+    let g = reader.read_u8()?;
+    let b = reader.read_u8()?;
 
-    Color32::new(r, g, b, 255)
+    Ok(Color32::new(r, g, b, 255))
 }
 
-fn read_color32<R: ReadBytesExt>(reader: &mut R) -> Color32 {
-    let r = reader.read_u8().unwrap();
-    let g = reader.read_u8().unwrap();
-    let b = reader.read_u8().unwrap();
-    let a = reader.read_u8().unwrap();
+fn read_color32<R: ReadBytesExt>(reader: &mut R) -> Result<Color32, std::io::Error> {
+    let r = reader.read_u8()?;
+    let g = reader.read_u8()?;
+    let b = reader.read_u8()?;
+    let a = reader.read_u8()?;
+
+    Ok(Color32::new(r, g, b, a))
+}
 
-    Color32::new(r, g, b, a)
+#[derive(Debug)]
+pub enum BspError {
+    IOError(std::io::Error),
+    InvalidMagic(u32),
+    InvalidVersion(u32),
+    LumpOutOfBounds(String),
+    MalformedLump(String),
+    MissingBspxLump(String),
+}
+
+impl Display for BspError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BspError::IOError(e) => write!(f, "IO error: {}", e),
+            BspError::InvalidMagic(magic) => write!(f, "input is not valid IBSP data (magic: {:#010x})", magic),
+            BspError::InvalidVersion(version) => write!(f, "wrong IBSP file version (expected {}, got {})", BSP_VERSION, version),
+            BspError::LumpOutOfBounds(name) => write!(f, "lump {} extends past end of file", name),
+            BspError::MalformedLump(name) => write!(f, "lump {} is malformed", name),
+            BspError::MissingBspxLump(name) => write!(f, "missing BSPX lump {}", name),
+        }
+    }
+}
+
+impl From<std::io::Error> for BspError {
+    fn from(value: std::io::Error) -> Self {
+        BspError::IOError(value)
+    }
 }
 
 pub struct BspLumpInfo {
+    name: String,
     offset: u32,
     length: u32,
 }
 
+impl BspLumpInfo {
+    /// Returns the number of fixed-size elements in the lump, or an error if the lump length isn't a multiple of the element size
+    fn num_elements(self: &Self, element_size: u32) -> Result<usize, BspError> {
+        if !self.length.is_multiple_of(element_size) {
+            return Err(BspError::MalformedLump(self.name.clone()));
+        }
+
+        Ok((self.length / element_size) as usize)
+    }
+
+    fn check_bounds(self: &Self, file_len: u64) -> Result<(), BspError> {
+        if self.offset as u64 + self.length as u64 > file_len {
+            return Err(BspError::LumpOutOfBounds(self.name.clone()));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Edge {
     pub a: u16,
@@ -100,8 +156,8 @@ pub struct Edge {
 }
 
 pub struct BspFace {
-    pub _plane: u16,
-    pub _plane_side: u16,
+    pub plane: u16,
+    pub plane_side: u16,
     pub first_edge: u32,
     pub num_edges: u16,
     pub texture_info: u16,
@@ -157,7 +213,8 @@ pub struct Brush {
 
 pub struct BrushSide {
     pub plane: u16,
-    pub _tex: u16,
+    /// Index of the side's TexInfo, or u16::MAX if the side has no texture
+    pub tex: u16,
 }
 
 pub struct VisCluster {
@@ -340,11 +397,11 @@ pub struct StaticPropMaterialsLump {
 }
 
 impl EntityLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> EntityLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<EntityLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
         let mut data: Vec<u8> = vec![0;info.length as usize];
-        reader.read_exact(&mut data).unwrap();
+        reader.read_exact(&mut data)?;
 
         let mut len = 0;
         for val in &data {
@@ -357,9 +414,9 @@ impl EntityLump {
         let slice = &data[0..len];
         let entities = unsafe { std::str::from_utf8_unchecked(slice).to_owned() };
 
-        EntityLump {
+        Ok(EntityLump {
             entities
-        }
+        })
     }
 
     pub fn parse<F>(self: &Self, mut f: F) where F: FnMut(HashMap<&str, &str>) {
@@ -392,61 +449,61 @@ impl EntityLump {
 }
 
 impl VertexLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> VertexLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<VertexLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_vertices = (info.length / 12) as usize;
+        let num_vertices = info.num_elements(12)?;
         let mut vertices: Vec<Vector3> = Vec::with_capacity(num_vertices);
 
         for _ in 0..num_vertices {
-            vertices.push(read_vec3f(reader));
+            vertices.push(read_vec3f(reader)?);
         }
 
-        VertexLump {
+        Ok(VertexLump {
             vertices
-        }
+        })
     }
 }
 
 impl EdgeLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> EdgeLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<EdgeLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_edges = (info.length / 4) as usize;
+        let num_edges = info.num_elements(4)?;
         let mut edges: Vec<Edge> = Vec::with_capacity(num_edges);
 
         for _ in 0..num_edges {
-            let a = reader.read_u16::<LittleEndian>().unwrap();
-            let b = reader.read_u16::<LittleEndian>().unwrap();
+            let a = reader.read_u16::<LittleEndian>()?;
+            let b = reader.read_u16::<LittleEndian>()?;
             edges.push(Edge {a, b});
         }
 
-        EdgeLump {
+        Ok(EdgeLump {
             edges
-        }
+        })
     }
 }
 
 impl FaceLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> FaceLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<FaceLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_faces = (info.length / 20) as usize;
+        let num_faces = info.num_elements(20)?;
         let mut faces: Vec<BspFace> = Vec::with_capacity(num_faces);
 
         for _ in 0..num_faces {
-            let plane = reader.read_u16::<LittleEndian>().unwrap();
-            let plane_side = reader.read_u16::<LittleEndian>().unwrap();
-            let first_edge = reader.read_u32::<LittleEndian>().unwrap();
-            let num_edges = reader.read_u16::<LittleEndian>().unwrap();
-            let texture_info = reader.read_u16::<LittleEndian>().unwrap();
+            let plane = reader.read_u16::<LittleEndian>()?;
+            let plane_side = reader.read_u16::<LittleEndian>()?;
+            let first_edge = reader.read_u32::<LittleEndian>()?;
+            let num_edges = reader.read_u16::<LittleEndian>()?;
+            let texture_info = reader.read_u16::<LittleEndian>()?;
             let lightmap_styles = [
-                reader.read_u8().unwrap(),
-                reader.read_u8().unwrap(),
-                reader.read_u8().unwrap(),
-                reader.read_u8().unwrap()
+                reader.read_u8()?,
+                reader.read_u8()?,
+                reader.read_u8()?,
+                reader.read_u8()?
             ];
-            let lightmap_offset = reader.read_u32::<LittleEndian>().unwrap();
+            let lightmap_offset = reader.read_u32::<LittleEndian>()?;
 
             let mut num_lightmaps = 0;
 
@@ -458,70 +515,70 @@ impl FaceLump {
             }
 
             faces.push(BspFace {
-                _plane: plane, _plane_side: plane_side, first_edge, num_edges, texture_info, lightmap_styles, num_lightmaps, lightmap_offset
+                plane, plane_side, first_edge, num_edges, texture_info, lightmap_styles, num_lightmaps, lightmap_offset
             });
         }
 
-        FaceLump {
+        Ok(FaceLump {
             faces
-        }
+        })
     }
 }
 
 impl FaceEdgeLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> FaceEdgeLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<FaceEdgeLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_edges = (info.length / 4) as usize;
+        let num_edges = info.num_elements(4)?;
         let mut edges: Vec<i32> = Vec::with_capacity(num_edges);
 
         for _ in 0..num_edges {
-            edges.push(reader.read_i32::<LittleEndian>().unwrap());
+            edges.push(reader.read_i32::<LittleEndian>()?);
         }
 
-        FaceEdgeLump {
+        Ok(FaceEdgeLump {
             edges
-        }
+        })
     }
 }
 
 impl PlaneLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> PlaneLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<PlaneLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_planes = (info.length / 20) as usize;
+        let num_planes = info.num_elements(20)?;
         let mut planes: Vec<Plane> = Vec::with_capacity(num_planes);
 
         for _ in 0..num_planes {
-            let normal = read_vec3f(reader);
-            let distance = reader.read_f32::<LittleEndian>().unwrap();
-            let plane_type = reader.read_u32::<LittleEndian>().unwrap();
+            let normal = read_vec3f(reader)?;
+            let distance = reader.read_f32::<LittleEndian>()?;
+            let plane_type = reader.read_u32::<LittleEndian>()?;
             planes.push(Plane { normal, distance, plane_type });
         }
 
-        PlaneLump {
+        Ok(PlaneLump {
             planes
-        }
+        })
     }
 }
 
 impl NodeLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> NodeLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<NodeLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_nodes = (info.length / 28) as usize;
+        let num_nodes = info.num_elements(28)?;
         let mut nodes: Vec<Node> = Vec::with_capacity(num_nodes);
 
         info!("Num nodes in node lump: {}", num_nodes);
 
         for _ in 0..num_nodes {
-            let plane = reader.read_u32::<LittleEndian>().unwrap();
-            let front_child = reader.read_i32::<LittleEndian>().unwrap();
-            let back_child = reader.read_i32::<LittleEndian>().unwrap();
-            let bbox_min = read_vec3s(reader);
-            let bbox_max = read_vec3s(reader);
-            let first_face = reader.read_u16::<LittleEndian>().unwrap();
-            let num_faces = reader.read_u16::<LittleEndian>().unwrap();
+            let plane = reader.read_u32::<LittleEndian>()?;
+            let front_child = reader.read_i32::<LittleEndian>()?;
+            let back_child = reader.read_i32::<LittleEndian>()?;
+            let bbox_min = read_vec3s(reader)?;
+            let bbox_max = read_vec3s(reader)?;
+            let first_face = reader.read_u16::<LittleEndian>()?;
+            let num_faces = reader.read_u16::<LittleEndian>()?;
 
             nodes.push(Node {
                 plane,
@@ -534,31 +591,31 @@ impl NodeLump {
             });
         }
 
-        NodeLump {
+        Ok(NodeLump {
             nodes
-        }
+        })
     }
 }
 
 impl LeafLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> LeafLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<LeafLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_leaves = (info.length / 28) as usize;
+        let num_leaves = info.num_elements(28)?;
         let mut leaves: Vec<Leaf> = Vec::with_capacity(num_leaves);
 
         info!("Num leaves in leaf lump: {}", num_leaves);
 
         for _ in 0..num_leaves {
-            let brush_or = reader.read_u32::<LittleEndian>().unwrap();
-            let cluster = reader.read_u16::<LittleEndian>().unwrap();
-            let area = reader.read_u16::<LittleEndian>().unwrap();
-            let bbox_min = read_vec3s(reader);
-            let bbox_max = read_vec3s(reader);
-            let first_leaf_face = reader.read_u16::<LittleEndian>().unwrap();
-            let num_leaf_faces = reader.read_u16::<LittleEndian>().unwrap();
-            let first_leaf_brush = reader.read_u16::<LittleEndian>().unwrap();
-            let num_leaf_brushes = reader.read_u16::<LittleEndian>().unwrap();
+            let brush_or = reader.read_u32::<LittleEndian>()?;
+            let cluster = reader.read_u16::<LittleEndian>()?;
+            let area = reader.read_u16::<LittleEndian>()?;
+            let bbox_min = read_vec3s(reader)?;
+            let bbox_max = read_vec3s(reader)?;
+            let first_leaf_face = reader.read_u16::<LittleEndian>()?;
+            let num_leaf_faces = reader.read_u16::<LittleEndian>()?;
+            let first_leaf_brush = reader.read_u16::<LittleEndian>()?;
+            let num_leaf_brushes = reader.read_u16::<LittleEndian>()?;
 
             leaves.push(Leaf {
                 contents: brush_or,
@@ -573,69 +630,69 @@ impl LeafLump {
             });
         }
 
-        LeafLump {
+        Ok(LeafLump {
             leaves
-        }
+        })
     }
 }
 
 impl LeafFaceLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> LeafFaceLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<LeafFaceLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_faces = (info.length / 2) as usize;
+        let num_faces = info.num_elements(2)?;
         let mut faces: Vec<u16> = Vec::with_capacity(num_faces);
 
         for _ in 0..num_faces {
-            let a = reader.read_u16::<LittleEndian>().unwrap();
+            let a = reader.read_u16::<LittleEndian>()?;
             faces.push(a);
         }
 
-        LeafFaceLump {
+        Ok(LeafFaceLump {
             faces
-        }
+        })
     }
 }
 
 impl LeafBrushLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> LeafBrushLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<LeafBrushLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_brushes = (info.length / 2) as usize;
+        let num_brushes = info.num_elements(2)?;
         let mut brushes: Vec<u16> = Vec::with_capacity(num_brushes);
 
         for _ in 0..num_brushes {
-            let a = reader.read_u16::<LittleEndian>().unwrap();
+            let a = reader.read_u16::<LittleEndian>()?;
             brushes.push(a);
         }
 
-        LeafBrushLump {
+        Ok(LeafBrushLump {
             brushes
-        }
+        })
     }
 }
 
 impl TexInfoLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> TexInfoLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<TexInfoLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_textures = (info.length / 76) as usize;
+        let num_textures = info.num_elements(76)?;
         let mut textures: Vec<TexInfo> = Vec::with_capacity(num_textures);
 
         info!("Num textures in tex info lump: {}", num_textures);
 
         for _ in 0..num_textures {
-            let u_axis = read_vec3f(reader);
-            let u_offset = reader.read_f32::<LittleEndian>().unwrap();
+            let u_axis = read_vec3f(reader)?;
+            let u_offset = reader.read_f32::<LittleEndian>()?;
 
-            let v_axis = read_vec3f(reader);
-            let v_offset = reader.read_f32::<LittleEndian>().unwrap();
+            let v_axis = read_vec3f(reader)?;
+            let v_offset = reader.read_f32::<LittleEndian>()?;
 
-            let flags = reader.read_u32::<LittleEndian>().unwrap();
-            let value = reader.read_u32::<LittleEndian>().unwrap();
+            let flags = reader.read_u32::<LittleEndian>()?;
+            let value = reader.read_u32::<LittleEndian>()?;
 
             let mut texture_name: [u8; 32] = [0; 32];
-            reader.read_exact(&mut texture_name).unwrap();
+            reader.read_exact(&mut texture_name)?;
 
             let mut name_len = 32;
             for i in 0..32 {
@@ -646,7 +703,7 @@ impl TexInfoLump {
             }
 
             let texture_name = unsafe { std::str::from_utf8_unchecked(&texture_name[0..name_len]) }.to_owned();
-            let next_texinfo = reader.read_u32::<LittleEndian>().unwrap();
+            let next_texinfo = reader.read_u32::<LittleEndian>()?;
 
             textures.push(TexInfo {
                 u_axis,
@@ -660,26 +717,42 @@ impl TexInfoLump {
             });
         }
 
-        TexInfoLump {
+        Ok(TexInfoLump {
             textures
-        }
+        })
     }
 }
 
 impl VisLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> VisLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<VisLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
+
+        // maps compiled without vis have an empty vis lump
+        if info.length == 0 {
+            return Ok(VisLump {
+                clusters: Vec::new(),
+                vis_buffer: Vec::new()
+            });
+        }
 
-        let num_clusters = reader.read_u32::<LittleEndian>().unwrap() as usize;
+        let num_clusters = reader.read_u32::<LittleEndian>()? as usize;
         let hdr_size = 4 + (num_clusters * 8);
 
+        if hdr_size > info.length as usize {
+            return Err(BspError::MalformedLump(info.name.clone()));
+        }
+
         let mut clusters: Vec<VisCluster> = Vec::with_capacity(num_clusters);
 
         info!("Num clusters in vis lump: {}", num_clusters);
 
         for _ in 0..num_clusters {
-            let pvs = reader.read_u32::<LittleEndian>().unwrap();
-            let _phs = reader.read_u32::<LittleEndian>().unwrap();
+            let pvs = reader.read_u32::<LittleEndian>()?;
+            let _phs = reader.read_u32::<LittleEndian>()?;
+
+            if (pvs as usize) < hdr_size || (pvs as usize) >= info.length as usize {
+                return Err(BspError::MalformedLump(info.name.clone()));
+            }
 
             let offs = (pvs as usize) - hdr_size;
 
@@ -691,12 +764,12 @@ impl VisLump {
         // read remainder of lump as byte array
         let buf_len = (info.length as usize) - hdr_size;
         let mut vis_buffer: Vec<u8> = vec![0;buf_len];
-        reader.read_exact(&mut vis_buffer).unwrap();
+        reader.read_exact(&mut vis_buffer)?;
 
-        VisLump {
+        Ok(VisLump {
             clusters,
             vis_buffer
-        }
+        })
     }
 
     // Unpack vis info for a given cluster index
@@ -725,82 +798,82 @@ impl VisLump {
 }
 
 impl LightmapLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> LightmapLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<LightmapLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_px = (info.length / 3) as usize;
+        let num_px = info.num_elements(3)?;
         let mut lm: Vec<Color32> = Vec::with_capacity(num_px);
 
         for _ in 0..num_px {
-            let mut c = read_color24(reader);
+            let mut c = read_color24(reader)?;
             c.r = (c.r as i32 * 2).clamp(0, 255) as u8;
             c.g = (c.g as i32 * 2).clamp(0, 255) as u8;
             c.b = (c.b as i32 * 2).clamp(0, 255) as u8;
             lm.push(c);
         }
 
-        LightmapLump {
+        Ok(LightmapLump {
             lm
-        }
+        })
     }
 }
 
 impl BrushLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> BrushLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<BrushLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_brushes = (info.length / 12) as usize;
+        let num_brushes = info.num_elements(12)?;
         let mut brushes: Vec<Brush> = Vec::with_capacity(num_brushes);
 
         for _ in 0..num_brushes {
-            let first_brush_side = reader.read_u32::<LittleEndian>().unwrap();
-            let num_brush_sides = reader.read_u32::<LittleEndian>().unwrap();
-            let contents = reader.read_u32::<LittleEndian>().unwrap();
+            let first_brush_side = reader.read_u32::<LittleEndian>()?;
+            let num_brush_sides = reader.read_u32::<LittleEndian>()?;
+            let contents = reader.read_u32::<LittleEndian>()?;
 
             brushes.push(Brush { first_brush_side, num_brush_sides, contents });
         }
 
-        BrushLump {
+        Ok(BrushLump {
             brushes
-        }
+        })
     }
 }
 
 impl BrushSideLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> BrushSideLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<BrushSideLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_brush_sides = (info.length / 4) as usize;
+        let num_brush_sides = info.num_elements(4)?;
         let mut brush_sides: Vec<BrushSide> = Vec::with_capacity(num_brush_sides);
 
         for _ in 0..num_brush_sides {
-            let plane = reader.read_u16::<LittleEndian>().unwrap();
-            let tex = reader.read_u16::<LittleEndian>().unwrap();
+            let plane = reader.read_u16::<LittleEndian>()?;
+            let tex = reader.read_u16::<LittleEndian>()?;
 
-            brush_sides.push(BrushSide { plane, _tex: tex });
+            brush_sides.push(BrushSide { plane, tex });
         }
 
-        BrushSideLump {
+        Ok(BrushSideLump {
             brush_sides
-        }
+        })
     }
 }
 
 impl SubModelLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> SubModelLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<SubModelLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let num_submodels = (info.length / 48) as usize;
+        let num_submodels = info.num_elements(48)?;
         let mut submodels: Vec<SubModel> = Vec::with_capacity(num_submodels);
 
         for _ in 0..num_submodels {
-            let mins = read_vec3f(reader);
-            let maxs = read_vec3f(reader);
-            let origin = read_vec3f(reader);
+            let mins = read_vec3f(reader)?;
+            let maxs = read_vec3f(reader)?;
+            let origin = read_vec3f(reader)?;
 
-            let headnode = reader.read_u32::<LittleEndian>().unwrap();
-            let first_face = reader.read_u32::<LittleEndian>().unwrap();
-            let num_faces = reader.read_u32::<LittleEndian>().unwrap();
+            let headnode = reader.read_u32::<LittleEndian>()?;
+            let first_face = reader.read_u32::<LittleEndian>()?;
+            let num_faces = reader.read_u32::<LittleEndian>()?;
 
             submodels.push(SubModel {
                 mins,
@@ -812,19 +885,19 @@ impl SubModelLump {
             });
         }
 
-        SubModelLump {
+        Ok(SubModelLump {
             submodels
-        }
+        })
     }
 }
 
 impl LSHGridLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> LSHGridLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<LSHGridLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let grid_dist = read_vec3f(reader);
-        let grid_size = read_vec3i(reader);
-        let grid_mins = read_vec3f(reader);
+        let grid_dist = read_vec3f(reader)?;
+        let grid_size = read_vec3i(reader)?;
+        let grid_mins = read_vec3f(reader)?;
 
         let num_x = grid_size.x as i32;
         let num_y = grid_size.y as i32;
@@ -832,19 +905,38 @@ impl LSHGridLump {
 
         info!("LSH Grid: {} x {} x {}", num_x, num_y, num_z);
 
-        let num_total = num_x * num_y * num_z;
-        let mut probes = Vec::with_capacity(num_total as usize);
+        if num_x < 0 || num_y < 0 || num_z < 0 {
+            return Err(BspError::MalformedLump(info.name.clone()));
+        }
+
+        // every probe takes up at least its style bytes, so make sure the lump is actually big enough to hold the grid before allocating it
+        const GRID_HEADER_SIZE: usize = 3 * 12;
+        const MIN_PROBE_SIZE: usize = 4;
+
+        let num_total = (num_x as usize).checked_mul(num_y as usize)
+            .and_then(|x| x.checked_mul(num_z as usize))
+            .ok_or_else(|| BspError::MalformedLump(info.name.clone()))?;
+
+        let probe_bytes = num_total.checked_mul(MIN_PROBE_SIZE)
+            .and_then(|x| x.checked_add(GRID_HEADER_SIZE))
+            .ok_or_else(|| BspError::MalformedLump(info.name.clone()))?;
+
+        if probe_bytes > info.length as usize {
+            return Err(BspError::MalformedLump(info.name.clone()));
+        }
+
+        let mut probes = Vec::with_capacity(num_total);
 
         for _ in 0..num_total {
             let mut probe = LSHProbe::default();
-            reader.read_exact(&mut probe.styles).unwrap();
+            reader.read_exact(&mut probe.styles)?;
 
             for i in 0..4 {
                 if probe.styles[i] != 255 {
-                    let l0_rgb = read_vec3f(reader);
-                    let l1_r = read_vec3f(reader);
-                    let l1_g = read_vec3f(reader);
-                    let l1_b = read_vec3f(reader);
+                    let l0_rgb = read_vec3f(reader)?;
+                    let l1_r = read_vec3f(reader)?;
+                    let l1_g = read_vec3f(reader)?;
+                    let l1_b = read_vec3f(reader)?;
 
                     probe.probes[i] = LSHProbeSample {
                         sh_r: Vector4::new(l1_r.x, l1_r.y, l1_r.z, l0_rgb.x),
@@ -857,10 +949,23 @@ impl LSHGridLump {
             probes.push(probe);
         }
 
-        LSHGridLump { grid_dist, grid_size, grid_mins, probes }
+        // probes with lightstyles are larger than the minimum size checked above, so make sure they didn't run past the end of the lump
+        if reader.stream_position()? > info.offset as u64 + info.length as u64 {
+            return Err(BspError::MalformedLump(info.name.clone()));
+        }
+
+        Ok(LSHGridLump { grid_dist, grid_size, grid_mins, probes })
+    }
+
+    pub fn empty() -> LSHGridLump {
+        LSHGridLump { grid_dist: Vector3::new(1.0, 1.0, 1.0), grid_size: Vector3::zero(), grid_mins: Vector3::zero(), probes: Vec::new() }
     }
 
     pub fn sample_position(self: &Self, pos: Vector3, light_layers: &[f32]) -> LSHProbeSample {
+        if self.probes.is_empty() {
+            return LSHProbeSample::default();
+        }
+
         let mut coord = (pos - self.grid_mins) / self.grid_dist;
         coord.x = coord.x.clamp(0.0, self.grid_size.x - 1.001);
         coord.y = coord.y.clamp(0.0, self.grid_size.y - 1.001);
@@ -920,43 +1025,51 @@ impl LSHGridLump {
 
 
 impl LeafStaticPropLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> LeafStaticPropLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn empty() -> LeafStaticPropLump {
+        LeafStaticPropLump { leaves: Vec::new(), indices: Vec::new() }
+    }
+
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<LeafStaticPropLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let leaf_count = reader.read_u32::<LittleEndian>().unwrap();
-        let index_count = reader.read_u32::<LittleEndian>().unwrap();
+        let leaf_count = reader.read_u32::<LittleEndian>()?;
+        let index_count = reader.read_u32::<LittleEndian>()?;
 
         let mut leaves = Vec::new();
         for _ in 0..leaf_count {
-            let first_prop = reader.read_u32::<LittleEndian>().unwrap();
-            let num_props = reader.read_u32::<LittleEndian>().unwrap();
+            let first_prop = reader.read_u32::<LittleEndian>()?;
+            let num_props = reader.read_u32::<LittleEndian>()?;
 
             leaves.push(LeafStaticProps { first_prop, num_props });
         }
 
         let mut indices = Vec::new();
         for _ in 0..index_count {
-            indices.push(reader.read_u32::<LittleEndian>().unwrap());
+            indices.push(reader.read_u32::<LittleEndian>()?);
         }
 
-        LeafStaticPropLump { leaves, indices }
+        Ok(LeafStaticPropLump { leaves, indices })
     }
 }
 
 impl StaticPropLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> StaticPropLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn empty() -> StaticPropLump {
+        StaticPropLump { props: Vec::new() }
+    }
 
-        let prop_count = reader.read_u32::<LittleEndian>().unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<StaticPropLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
+
+        let prop_count = reader.read_u32::<LittleEndian>()?;
 
         let mut props = Vec::new();
         for _ in 0..prop_count {
-            let material = reader.read_u32::<LittleEndian>().unwrap();
-            let mode = reader.read_u32::<LittleEndian>().unwrap();
-            let first_index = reader.read_u32::<LittleEndian>().unwrap();
-            let num_indices = reader.read_u32::<LittleEndian>().unwrap();
-            let first_vertex = reader.read_u32::<LittleEndian>().unwrap();
-            let num_vertices = reader.read_u32::<LittleEndian>().unwrap();
+            let material = reader.read_u32::<LittleEndian>()?;
+            let mode = reader.read_u32::<LittleEndian>()?;
+            let first_index = reader.read_u32::<LittleEndian>()?;
+            let num_indices = reader.read_u32::<LittleEndian>()?;
+            let first_vertex = reader.read_u32::<LittleEndian>()?;
+            let num_vertices = reader.read_u32::<LittleEndian>()?;
 
             let topology = match mode {
                 0 => {
@@ -966,56 +1079,64 @@ impl StaticPropLump {
                     gl::TRIANGLE_STRIP
                 },
                 _ => {
-                    panic!("Invalid static prop topology")
+                    return Err(BspError::MalformedLump(info.name.clone()));
                 }
             };
 
             props.push(StaticProp { material, topology, first_index, num_indices, first_vertex, num_vertices });
         }
 
-        StaticPropLump { props }
+        Ok(StaticPropLump { props })
     }
 }
 
 impl StaticPropIndicesLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> StaticPropIndicesLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn empty() -> StaticPropIndicesLump {
+        StaticPropIndicesLump { indices: Vec::new() }
+    }
+
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<StaticPropIndicesLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let index_count = reader.read_u32::<LittleEndian>().unwrap();
+        let index_count = reader.read_u32::<LittleEndian>()?;
 
         let mut indices = Vec::new();
         for _ in 0..index_count {
-            indices.push(reader.read_u16::<LittleEndian>().unwrap());
+            indices.push(reader.read_u16::<LittleEndian>()?);
         }
 
-        StaticPropIndicesLump { indices }
+        Ok(StaticPropIndicesLump { indices })
     }
 }
 
 impl StaticPropVerticesLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> StaticPropVerticesLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn empty() -> StaticPropVerticesLump {
+        StaticPropVerticesLump { vertices: Vec::new() }
+    }
+
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<StaticPropVerticesLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
 
-        let vertex_count = reader.read_u32::<LittleEndian>().unwrap();
+        let vertex_count = reader.read_u32::<LittleEndian>()?;
 
         let mut vertices = Vec::new();
         for _ in 0..vertex_count {
-            let position = read_vec3f(reader);
-            let normal = read_vec3f(reader);
-            let tangent = read_vec4f(reader);
-            let texcoord = read_vec2f(reader);
-            let color = read_color32(reader);
+            let position = read_vec3f(reader)?;
+            let normal = read_vec3f(reader)?;
+            let tangent = read_vec4f(reader)?;
+            let texcoord = read_vec2f(reader)?;
+            let color = read_color32(reader)?;
 
             let mut light_styles = [0;4];
-            reader.read_exact(&mut light_styles).unwrap();
+            reader.read_exact(&mut light_styles)?;
 
             let mut light_colors = [Color32::new(0, 0, 0, 0); 4];
 
             for i in 0..4 {
                 if light_styles[i] != 255 {
-                    light_colors[i].r = reader.read_u8().unwrap();
-                    light_colors[i].g = reader.read_u8().unwrap();
-                    light_colors[i].b = reader.read_u8().unwrap();
+                    light_colors[i].r = reader.read_u8()?;
+                    light_colors[i].g = reader.read_u8()?;
+                    light_colors[i].b = reader.read_u8()?;
                 }
             }
 
@@ -1030,20 +1151,24 @@ impl StaticPropVerticesLump {
             });
         }
 
-        StaticPropVerticesLump { vertices }
+        Ok(StaticPropVerticesLump { vertices })
     }
 }
 
 impl StaticPropMaterialsLump {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> StaticPropMaterialsLump {
-        reader.seek(std::io::SeekFrom::Start(info.offset as u64)).unwrap();
+    pub fn empty() -> StaticPropMaterialsLump {
+        StaticPropMaterialsLump { materials: Vec::new() }
+    }
 
-        let material_count = reader.read_u32::<LittleEndian>().unwrap();
+    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R, info: &BspLumpInfo) -> Result<StaticPropMaterialsLump, BspError> {
+        reader.seek(std::io::SeekFrom::Start(info.offset as u64))?;
+
+        let material_count = reader.read_u32::<LittleEndian>()?;
 
         let mut materials = Vec::new();
         for _ in 0..material_count {
             let mut material_name: [u8; 64] = [0; 64];
-            reader.read_exact(&mut material_name).unwrap();
+            reader.read_exact(&mut material_name)?;
 
             let mut name_len = 64;
             for i in 0..64 {
@@ -1057,7 +1182,7 @@ impl StaticPropMaterialsLump {
             materials.push(material_name);
         }
 
-        StaticPropMaterialsLump { materials }
+        Ok(StaticPropMaterialsLump { materials })
     }
 }
 
@@ -1086,16 +1211,26 @@ pub struct BspFile {
     pub sprop_materials_lump: StaticPropMaterialsLump,
 }
 
+fn get_bspx_lump<'a>(bspx_lumps: &'a HashMap<String, BspLumpInfo>, name: &str) -> Result<&'a BspLumpInfo, BspError> {
+    match bspx_lumps.get(name) {
+        Some(v) => Ok(v),
+        None => Err(BspError::MissingBspxLump(name.to_owned()))
+    }
+}
+
 impl BspFile {
-    pub fn new<R: Seek + ReadBytesExt>(reader: &mut R) -> BspFile {
-        let magic = reader.read_u32::<LittleEndian>().unwrap();
+    pub fn load<R: Seek + ReadBytesExt>(reader: &mut R) -> Result<BspFile, BspError> {
+        let file_len = reader.seek(std::io::SeekFrom::End(0))?;
+        reader.seek(std::io::SeekFrom::Start(0))?;
+
+        let magic = reader.read_u32::<LittleEndian>()?;
         if magic != BSP_MAGIC {
-            panic!("Failed loading BSP: input is not valid IBSP data");
+            return Err(BspError::InvalidMagic(magic));
         }
 
-        let version = reader.read_u32::<LittleEndian>().unwrap();
+        let version = reader.read_u32::<LittleEndian>()?;
         if version != BSP_VERSION {
-            panic!("Failed loading BSP: wrong IBSP file version");
+            return Err(BspError::InvalidVersion(version));
         }
 
         // read BSP lump info
@@ -1103,74 +1238,110 @@ impl BspFile {
 
         let mut max_offset = 0;
 
-        for _ in 0..19 {
-            let offset = reader.read_u32::<LittleEndian>().unwrap();
-            let length = reader.read_u32::<LittleEndian>().unwrap();
+        for name in LUMP_NAMES {
+            let offset = reader.read_u32::<LittleEndian>()?;
+            let length = reader.read_u32::<LittleEndian>()?;
 
-            max_offset = max_offset.max((offset + length) as u64);
+            let lump_info = BspLumpInfo { name: name.to_owned(), offset, length };
+            lump_info.check_bounds(file_len)?;
 
-            bsp_lumps.push(BspLumpInfo { offset, length });
+            max_offset = max_offset.max(offset as u64 + length as u64);
+
+            bsp_lumps.push(lump_info);
         }
 
         // read lumps
-        let entity_lump = EntityLump::new(reader, &bsp_lumps[0]);
-        let plane_lump = PlaneLump::new(reader, &bsp_lumps[1]);
-        let vertex_lump = VertexLump::new(reader, &bsp_lumps[2]);
-        let vis_lump = VisLump::new(reader, &bsp_lumps[3]);
-        let node_lump = NodeLump::new(reader, &bsp_lumps[4]);
-        let tex_info_lump = TexInfoLump::new(reader, &bsp_lumps[5]);
-        let face_lump = FaceLump::new(reader, &bsp_lumps[6]);
-        let lm_lump = LightmapLump::new(reader, &bsp_lumps[7]);
-        let leaf_lump = LeafLump::new(reader, &bsp_lumps[8]);
-        let leaf_face_lump = LeafFaceLump::new(reader, &bsp_lumps[9]);
-        let leaf_brush_lump = LeafBrushLump::new(reader, &bsp_lumps[10]);
-        let edge_lump = EdgeLump::new(reader, &bsp_lumps[11]);
-        let face_edge_lump = FaceEdgeLump::new(reader, &bsp_lumps[12]);
-        let submodel_lump = SubModelLump::new(reader, &bsp_lumps[13]);
-        let brush_lump = BrushLump::new(reader, &bsp_lumps[14]);
-        let brush_side_lump = BrushSideLump::new(reader, &bsp_lumps[15]);
+        let entity_lump = EntityLump::new(reader, &bsp_lumps[0])?;
+        let plane_lump = PlaneLump::new(reader, &bsp_lumps[1])?;
+        let vertex_lump = VertexLump::new(reader, &bsp_lumps[2])?;
+        let vis_lump = VisLump::new(reader, &bsp_lumps[3])?;
+        let node_lump = NodeLump::new(reader, &bsp_lumps[4])?;
+        let tex_info_lump = TexInfoLump::new(reader, &bsp_lumps[5])?;
+        let face_lump = FaceLump::new(reader, &bsp_lumps[6])?;
+        let lm_lump = LightmapLump::new(reader, &bsp_lumps[7])?;
+        let leaf_lump = LeafLump::new(reader, &bsp_lumps[8])?;
+        let leaf_face_lump = LeafFaceLump::new(reader, &bsp_lumps[9])?;
+        let leaf_brush_lump = LeafBrushLump::new(reader, &bsp_lumps[10])?;
+        let edge_lump = EdgeLump::new(reader, &bsp_lumps[11])?;
+        let face_edge_lump = FaceEdgeLump::new(reader, &bsp_lumps[12])?;
+        let submodel_lump = SubModelLump::new(reader, &bsp_lumps[13])?;
+        let brush_lump = BrushLump::new(reader, &bsp_lumps[14])?;
+        let brush_side_lump = BrushSideLump::new(reader, &bsp_lumps[15])?;
 
         // seek to end of main BSP and look for "BSPX" header
+        // plain IBSP files won't have one, in which case all BSPX lumps are treated as missing
         let bspx_start = if max_offset % 4 == 0 { max_offset } else { max_offset + (4 - max_offset % 4) };
-        reader.seek(std::io::SeekFrom::Start(bspx_start)).unwrap();
-        let bspx_magic = reader.read_u32::<LittleEndian>().unwrap();
-        if bspx_magic != BSPX_MAGIC {
-            panic!("Failed loading BSP: expected BSPX extension");
-        }
-
-        let num_bspx_lumps = reader.read_u32::<LittleEndian>().unwrap();
         let mut bspx_lumps = HashMap::new();
 
-        for _ in 0..num_bspx_lumps {
-            let mut lump_name: [u8; 24] = [0; 24];
-            reader.read_exact(&mut lump_name).unwrap();
-
-            let mut name_len = 32;
-            for i in 0..32 {
-                if lump_name[i] == 0 {
-                    name_len = i;
-                    break;
+        let bspx_magic = if bspx_start + 8 <= file_len {
+            reader.seek(std::io::SeekFrom::Start(bspx_start))?;
+            reader.read_u32::<LittleEndian>()?
+        }
+        else {
+            0
+        };
+
+        if bspx_magic == BSPX_MAGIC {
+            let num_bspx_lumps = reader.read_u32::<LittleEndian>()?;
+
+            for _ in 0..num_bspx_lumps {
+                let mut lump_name: [u8; 24] = [0; 24];
+                reader.read_exact(&mut lump_name)?;
+
+                let mut name_len = 24;
+                for i in 0..24 {
+                    if lump_name[i] == 0 {
+                        name_len = i;
+                        break;
+                    }
                 }
-            }
 
-            let lump_name = unsafe { std::str::from_utf8_unchecked(&lump_name[0..name_len]) }.to_owned();
-            let lump_offset = reader.read_u32::<LittleEndian>().unwrap();
-            let lump_length = reader.read_u32::<LittleEndian>().unwrap();
+                let lump_name = unsafe { std::str::from_utf8_unchecked(&lump_name[0..name_len]) }.to_owned();
+                let lump_offset = reader.read_u32::<LittleEndian>()?;
+                let lump_length = reader.read_u32::<LittleEndian>()?;
 
-            info!("BSPX Lump: {} (offs: {}, len: {})", lump_name, lump_offset, lump_length);
+                info!("BSPX Lump: {} (offs: {}, len: {})", lump_name, lump_offset, lump_length);
 
-            let lump_info = BspLumpInfo { offset: lump_offset, length: lump_length };
-            bspx_lumps.insert(lump_name, lump_info);
-        }
+                let lump_info = BspLumpInfo { name: lump_name.clone(), offset: lump_offset, length: lump_length };
+                lump_info.check_bounds(file_len)?;
 
-        let lsh_grid_lump = LSHGridLump::new(reader, &bspx_lumps["LSH_GRID"]);
-        let leaf_sprop_lump = LeafStaticPropLump::new(reader, &bspx_lumps["LEAF_SPROP"]);
-        let sprop_lump = StaticPropLump::new(reader, &bspx_lumps["SPROP"]);
-        let sprop_indices_lump = StaticPropIndicesLump::new(reader, &bspx_lumps["SPROP_INDICES"]);
-        let sprop_vertices_lump = StaticPropVerticesLump::new(reader, &bspx_lumps["SPROP_VERTICES"]);
-        let sprop_materials_lump = StaticPropMaterialsLump::new(reader, &bspx_lumps["SPROP_MATERIALS"]);
+                bspx_lumps.insert(lump_name, lump_info);
+            }
+        }
+        else {
+            info!("No BSPX extension found");
+        }
 
-        BspFile {
+        let lsh_grid_lump = match get_bspx_lump(&bspx_lumps, "LSH_GRID") {
+            Ok(v) => LSHGridLump::new(reader, v)?,
+            Err(e) => {
+                warn!("{} - light grid will be empty", e);
+                LSHGridLump::empty()
+            }
+        };
+
+        // static props are spread across several lumps, which must either all be present or all be absent
+        let (leaf_sprop_lump, sprop_lump, sprop_indices_lump, sprop_vertices_lump, sprop_materials_lump) = if bspx_lumps.contains_key("SPROP") {
+            (
+                LeafStaticPropLump::new(reader, get_bspx_lump(&bspx_lumps, "LEAF_SPROP")?)?,
+                StaticPropLump::new(reader, get_bspx_lump(&bspx_lumps, "SPROP")?)?,
+                StaticPropIndicesLump::new(reader, get_bspx_lump(&bspx_lumps, "SPROP_INDICES")?)?,
+                StaticPropVerticesLump::new(reader, get_bspx_lump(&bspx_lumps, "SPROP_VERTICES")?)?,
+                StaticPropMaterialsLump::new(reader, get_bspx_lump(&bspx_lumps, "SPROP_MATERIALS")?)?,
+            )
+        }
+        else {
+            info!("No static props in map");
+            (
+                LeafStaticPropLump::empty(),
+                StaticPropLump::empty(),
+                StaticPropIndicesLump::empty(),
+                StaticPropVerticesLump::empty(),
+                StaticPropMaterialsLump::empty(),
+            )
+        };
+
+        Ok(BspFile {
             entity_lump,
             vertex_lump,
             edge_lump,
@@ -1193,6 +1364,6 @@ impl BspFile {
             sprop_indices_lump,
             sprop_vertices_lump,
             sprop_materials_lump,
-        }
+        })
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,7 @@
 use std::{collections::HashSet, mem::offset_of, sync::Arc};
 
-use crate::{asset_loader::{load_material, load_shader, LoadedAsset, MaterialHandle}, gl_checked, graphics::{buffer::Buffer, material::{Material, MaterialParam, TextureSampler}, shader::Shader, texture::{Texture, TextureFormat}}, math::{Matrix4x4, Vector2, Vector3, Vector4}, misc::{Color32, AABB}, runtime_asset, serialization::SerializedResource};
-use super::{bspcommon::{aabb_aabb_intersects, aabb_frustum}, bspfile::{BspFile, Edge, StaticPropVertex, SURF_NODRAW, SURF_SKY, SURF_TRANS33, SURF_TRANS66}, bsplightmap::BspLightmap};
+use crate::{asset_loader::{load_material, load_shader, LoadedAsset, MaterialHandle}, gl_checked, graphics::{buffer::Buffer, dynamic_light::{DynamicLight, DynamicLightSet}, material::{Material, MaterialParam, TextureSampler}, shader::Shader, texture::{Texture, TextureFormat}}, math::{Matrix4x4, Vector2, Vector3, Vector4}, misc::{Color32, AABB}, runtime_asset, serialization::SerializedResource};
+use super::{bspcommon::{aabb_aabb_intersects, aabb_frustum, transform_aabb}, bspfile::{BspFile, Edge, StaticPropVertex, SURF_NODRAW, SURF_SKY, SURF_TRANS33, SURF_TRANS66}, bsplightmap::BspLightmap};
 
 // If you peruse this file, you might notice that in a lot of cases we actually update vertex data on the CPU and dynamically update the vertex buffers each frame
 // You might be wondering why we're doing this rather than doing it on the GPU in a shader
@@ -26,6 +26,9 @@ fn unpack_face(bsp: &BspFile, textures: &BspMapTextures, light_styles: &[f32], f
 
     let mut col = Color32::new(255, 255, 255, 255);
 
+    let plane = &bsp.plane_lump.planes[face.plane as usize];
+    let normal = if face.plane_side != 0 { plane.normal * -1.0 } else { plane.normal };
+
     if tex_info.flags & SURF_TRANS33 != 0 {
         col.a = 85;
     }
@@ -123,7 +126,7 @@ fn unpack_face(bsp: &BspFile, textures: &BspMapTextures, light_styles: &[f32], f
 
         let pos = Vector4::new(pos.x, pos.y, pos.z, 1.0);
 
-        let vtx = MapVertex::new(pos, tex, lm_uvs[0], lm_uvs[1], lm_uvs[2], lm_uvs[3], col);
+        let vtx = MapVertex::new(pos, normal, tex, lm_uvs[0], lm_uvs[1], lm_uvs[2], lm_uvs[3], col);
 
         geo.push(vtx);
     }
@@ -139,9 +142,28 @@ fn unpack_face(bsp: &BspFile, textures: &BspMapTextures, light_styles: &[f32], f
     }
 }
 
-fn setup_vtx_arrays(position: u32, uv: u32, lm0: u32, lm1: u32, lm2: u32, lm3: u32, color: u32) {
+// compute the bounds of a set of map vertices
+fn geom_bounds(geom: &[MapVertex]) -> AABB {
+    let mut min = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+
+    for vtx in geom {
+        min.x = min.x.min(vtx.position.x);
+        min.y = min.y.min(vtx.position.y);
+        min.z = min.z.min(vtx.position.z);
+
+        max.x = max.x.max(vtx.position.x);
+        max.y = max.y.max(vtx.position.y);
+        max.z = max.z.max(vtx.position.z);
+    }
+
+    AABB::min_max(min, max)
+}
+
+fn setup_vtx_arrays(position: u32, normal: u32, uv: u32, lm0: u32, lm1: u32, lm2: u32, lm3: u32, color: u32) {
     unsafe {
         gl::EnableVertexAttribArray(position);
+        gl::EnableVertexAttribArray(normal);
         gl::EnableVertexAttribArray(uv);
         gl::EnableVertexAttribArray(lm0);
         gl::EnableVertexAttribArray(lm1);
@@ -149,6 +171,7 @@ fn setup_vtx_arrays(position: u32, uv: u32, lm0: u32, lm1: u32, lm2: u32, lm3: u
         gl::EnableVertexAttribArray(lm3);
         gl::EnableVertexAttribArray(color);
         gl::VertexAttribPointer(position, 4, gl::FLOAT, gl::FALSE, size_of::<MapVertex>() as i32, offset_of!(MapVertex, position) as *const _);
+        gl::VertexAttribPointer(normal, 3, gl::FLOAT, gl::FALSE, size_of::<MapVertex>() as i32, offset_of!(MapVertex, normal) as *const _);
         gl::VertexAttribPointer(uv, 2, gl::FLOAT, gl::FALSE, size_of::<MapVertex>() as i32, offset_of!(MapVertex, uv) as *const _);
         gl::VertexAttribPointer(lm0, 3, gl::FLOAT, gl::FALSE, size_of::<MapVertex>() as i32, offset_of!(MapVertex, lm0) as *const _);
         gl::VertexAttribPointer(lm1, 3, gl::FLOAT, gl::FALSE, size_of::<MapVertex>() as i32, offset_of!(MapVertex, lm1) as *const _);
@@ -178,12 +201,14 @@ fn draw_geom_setup(material: &Material, model: Matrix4x4, viewproj: Matrix4x4) {
     material.apply();
 
     material.shader.inner.set_uniform_mat4("mvp", model * viewproj);
+    material.shader.inner.set_uniform_mat4("localToWorld", model);
     material.shader.inner.set_uniform_int("lmTexture", 1);
 }
 
 #[derive(Clone, Copy)]
 pub struct MapVertex {
     pub position: Vector4,
+    pub normal: Vector3,
     pub uv: Vector2,
     pub lm0: Vector3,
     pub lm1: Vector3,
@@ -258,9 +283,10 @@ impl BspMapTextures {
 }
 
 impl MapVertex {
-    pub fn new(position: Vector4, uv: Vector2, lm0: Vector3, lm1: Vector3, lm2: Vector3, lm3: Vector3, color: Color32) -> MapVertex {
+    pub fn new(position: Vector4, normal: Vector3, uv: Vector2, lm0: Vector3, lm1: Vector3, lm2: Vector3, lm3: Vector3, color: Color32) -> MapVertex {
         MapVertex {
             position,
+            normal,
             uv,
             lm0,
             lm1,
@@ -273,6 +299,7 @@ impl MapVertex {
 
 struct ModelPart {
     tex_idx: usize,
+    bounds: AABB,
     light_styles: [u8;4],
     needs_update: bool,
     idx_len: usize,
@@ -319,9 +346,9 @@ impl BspMapModelRenderer {
                 idx_buffer.set_data(0, &idx);
 
                 // optimization: if a face only has a single light style of 0, we don't need to bother updating the vertices for lightmapping
-                let needs_lm_update = face.lightmap_styles[0] == 0 && face.num_lightmaps == 1;
+                let needs_lm_update = !(face.lightmap_styles[0] == 0 && face.num_lightmaps == 1);
 
-                model_parts.push(ModelPart { tex_idx, light_styles: face.lightmap_styles, geom, vtx_buffer, idx_buffer, idx_len: idx.len(), needs_update: needs_lm_update });
+                model_parts.push(ModelPart { tex_idx, bounds: geom_bounds(&geom), light_styles: face.lightmap_styles, geom, vtx_buffer, idx_buffer, idx_len: idx.len(), needs_update: needs_lm_update });
             }
 
             models.push(Model {
@@ -354,7 +381,7 @@ impl BspMapModelRenderer {
         }
     }
 
-    pub fn draw_model(self: &mut BspMapModelRenderer, transparent: bool, textures: &BspMapTextures, lm: &BspLightmap, model_idx: usize, model_transform: Matrix4x4, camera_viewproj: Matrix4x4) {
+    pub fn draw_model(self: &mut BspMapModelRenderer, transparent: bool, textures: &BspMapTextures, lm: &BspLightmap, model_idx: usize, model_transform: Matrix4x4, camera_viewproj: Matrix4x4, lights: &[DynamicLight], max_lights: usize) {
         let model = &self.models[model_idx];
 
         for part in &model.parts {
@@ -363,7 +390,11 @@ impl BspMapModelRenderer {
                 draw_geom_setup(material, model_transform, camera_viewproj);
                 bind_lightmap(lm);
 
+                let part_bounds = transform_aabb(&part.bounds, model_transform);
+                DynamicLightSet::select(lights, &part_bounds, max_lights).apply(&material.shader.inner);
+
                 let shader_position = material.shader.inner.get_attribute_location("in_pos");
+                let shader_normal = material.shader.inner.get_attribute_location("in_nrm");
                 let shader_uv = material.shader.inner.get_attribute_location("in_uv");
                 let shader_lm0 = material.shader.inner.get_attribute_location("in_lm0");
                 let shader_lm1 = material.shader.inner.get_attribute_location("in_lm1");
@@ -375,7 +406,7 @@ impl BspMapModelRenderer {
                     gl_checked!{ gl::BindBuffer(gl::ARRAY_BUFFER, part.vtx_buffer.handle()) }
                     gl_checked!{ gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, part.idx_buffer.handle()) }
     
-                    setup_vtx_arrays(shader_position, shader_uv, shader_lm0, shader_lm1, shader_lm2, shader_lm3, shader_color);
+                    setup_vtx_arrays(shader_position, shader_normal, shader_uv, shader_lm0, shader_lm1, shader_lm2, shader_lm3, shader_color);
     
                     // draw geometry
                     gl_checked!{ gl::DrawElements(gl::TRIANGLES, part.idx_len as i32, gl::UNSIGNED_SHORT, 0 as *const _) }
@@ -450,11 +481,19 @@ impl StaticPropVertex {
     }
 }
 
+// range of a texture batch's indices which belong to a single face
+struct FaceRange {
+    bounds: AABB,
+    first_index: usize,
+}
+
 pub struct BspMapRenderer {
     vis: Vec<bool>,
     prev_leaf: i32,
     mesh_vertices: Vec<Vec<MapVertex>>,
     mesh_indices: Vec<Vec<u16>>,
+    mesh_faces: Vec<Vec<FaceRange>>,
+    mesh_bounds: Vec<AABB>,
     visible_leaves: HashSet<usize>,
     drawn_faces: Vec<u32>,
     cur_frame: u32,
@@ -505,6 +544,8 @@ impl BspMapRenderer {
             visible_leaves: HashSet::with_capacity(num_leaves),
             mesh_vertices: vec![Vec::new();num_textures],
             mesh_indices: vec![Vec::new();num_textures],
+            mesh_faces: (0..num_textures).map(|_| Vec::new()).collect(),
+            mesh_bounds: vec![AABB::default();num_textures],
             drawn_faces: vec![0;num_faces],
             cur_frame: 0,
             prev_leaf: -1,
@@ -570,6 +611,10 @@ impl BspMapRenderer {
             idx.clear();
         }
 
+        for faces in &mut self.mesh_faces {
+            faces.clear();
+        }
+
         let mut edges: Vec<Edge> = Vec::new();
 
         for i in &self.visible_leaves {
@@ -588,18 +633,29 @@ impl BspMapRenderer {
 
                 let face = &bsp.face_lump.faces[face_idx];
                 let tex_idx = face.texture_info as usize;
+
+                let first_vertex = self.mesh_vertices[tex_idx].len();
+                let first_index = self.mesh_indices[tex_idx].len();
                 unpack_face(bsp, textures, light_layers, face_idx, &mut edges, &mut self.mesh_vertices[tex_idx], &mut self.mesh_indices[tex_idx], lm);
+
+                // remember where each face ended up in its batch, so that batches can be split up by which dynamic lights touch each face
+                if self.mesh_indices[tex_idx].len() > first_index {
+                    let bounds = geom_bounds(&self.mesh_vertices[tex_idx][first_vertex..]);
+                    self.mesh_faces[tex_idx].push(FaceRange { bounds, first_index });
+                }
             }
 
-            let leaf_props = &bsp.leaf_sprop_lump.leaves[*i];
-            let start_prop_idx = leaf_props.first_prop as usize;
-            let end_prop_idx = start_prop_idx + (leaf_props.num_props as usize);
-            let prop_indices = &bsp.leaf_sprop_lump.indices[start_prop_idx..end_prop_idx];
+            // note: maps without static props have no leaf static prop data at all
+            if let Some(leaf_props) = bsp.leaf_sprop_lump.leaves.get(*i) {
+                let start_prop_idx = leaf_props.first_prop as usize;
+                let end_prop_idx = start_prop_idx + (leaf_props.num_props as usize);
+                let prop_indices = &bsp.leaf_sprop_lump.indices[start_prop_idx..end_prop_idx];
 
-            // mark currently visible static props & update geometry
-            for prop_idx in prop_indices {
-                self.static_props[*prop_idx as usize].frame_idx = self.cur_frame;
-                self.static_props[*prop_idx as usize].update(light_layers);
+                // mark currently visible static props & update geometry
+                for prop_idx in prop_indices {
+                    self.static_props[*prop_idx as usize].frame_idx = self.cur_frame;
+                    self.static_props[*prop_idx as usize].update(light_layers);
+                }
             }
         }
 
@@ -629,6 +685,8 @@ impl BspMapRenderer {
 
                 self.vtx_buffers[i].set_data(0, &self.mesh_vertices[i]);
                 self.idx_buffers[i].set_data(0, &self.mesh_indices[i]);
+
+                self.mesh_bounds[i] = geom_bounds(&self.mesh_vertices[i]);
             }
         }
     }
@@ -703,7 +761,50 @@ impl BspMapRenderer {
         return self.visible_leaves.contains(&leaf_index);
     }
 
-    pub fn draw_opaque(self: &mut Self, textures: &BspMapTextures, lm: &BspLightmap, animation_time: f32, camera_viewproj: Matrix4x4) {
+    // draw a texture batch, split into runs of consecutive faces which are touched by the same dynamic lights
+    fn draw_batch(self: &Self, tex_idx: usize, shader: &Shader, lights: &[DynamicLight], max_lights: usize) {
+        // only lights which reach the batch at all can affect any of its faces
+        let batch_lights = lights.iter()
+            .filter(|x| aabb_aabb_intersects(&x.bounds(), &self.mesh_bounds[tex_idx]))
+            .copied()
+            .collect::<Vec<_>>();
+
+        let num_indices = self.mesh_indices[tex_idx].len();
+        let mut run_start = 0;
+        let mut run_lights: Option<DynamicLightSet> = None;
+
+        let draw_run = |run_lights: &DynamicLightSet, start: usize, end: usize| {
+            run_lights.apply(shader);
+
+            unsafe {
+                gl_checked!{ gl::DrawElements(gl::TRIANGLES, (end - start) as i32, gl::UNSIGNED_SHORT, (start * size_of::<u16>()) as *const _) }
+            }
+        };
+
+        for face in &self.mesh_faces[tex_idx] {
+            let face_lights = if batch_lights.is_empty() {
+                DynamicLightSet::default()
+            }
+            else {
+                DynamicLightSet::select(&batch_lights, &face.bounds, max_lights)
+            };
+
+            if let Some(prev_lights) = &run_lights {
+                if !prev_lights.same_lights(&face_lights) {
+                    draw_run(prev_lights, run_start, face.first_index);
+                    run_start = face.first_index;
+                }
+            }
+
+            run_lights = Some(face_lights);
+        }
+
+        if let Some(prev_lights) = &run_lights {
+            draw_run(prev_lights, run_start, num_indices);
+        }
+    }
+
+    pub fn draw_opaque(self: &mut Self, textures: &BspMapTextures, lm: &BspLightmap, animation_time: f32, camera_viewproj: Matrix4x4, lights: &[DynamicLight], max_lights: usize) {
         for i in &textures.opaque_meshes {
             if self.mesh_indices[*i].len() > 0 {
                 let vtx_buf = &self.vtx_buffers[*i];
@@ -713,7 +814,9 @@ impl BspMapRenderer {
                 draw_geom_setup(&material, Matrix4x4::identity(), camera_viewproj);
                 bind_lightmap(lm);
 
+
                 let shader_position = material.shader.inner.get_attribute_location("in_pos");
+                let shader_normal = material.shader.inner.get_attribute_location("in_nrm");
                 let shader_uv = material.shader.inner.get_attribute_location("in_uv");
                 let shader_lm0 = material.shader.inner.get_attribute_location("in_lm0");
                 let shader_lm1 = material.shader.inner.get_attribute_location("in_lm1");
@@ -727,11 +830,11 @@ impl BspMapRenderer {
                     gl_checked!{ gl::BindBuffer(gl::ARRAY_BUFFER, vtx_buf.handle()) }
                     gl_checked!{ gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, idx_buf.handle()) }
     
-                    setup_vtx_arrays(shader_position, shader_uv, shader_lm0, shader_lm1, shader_lm2, shader_lm3, shader_color);
-
-                    // draw geometry
-                    gl_checked!{ gl::DrawElements(gl::TRIANGLES, self.mesh_indices[*i].len() as i32, gl::UNSIGNED_SHORT, 0 as *const _) }
+                    setup_vtx_arrays(shader_position, shader_normal, shader_uv, shader_lm0, shader_lm1, shader_lm2, shader_lm3, shader_color);
                 }
+
+                // draw geometry
+                self.draw_batch(*i, &material.shader.inner, lights, max_lights);
             }
         }
 
@@ -756,7 +859,7 @@ impl BspMapRenderer {
         }
     }
 
-    pub fn draw_transparent(self: &mut Self, textures: &BspMapTextures, lm: &BspLightmap, animation_time: f32, camera_viewproj: Matrix4x4) {
+    pub fn draw_transparent(self: &mut Self, textures: &BspMapTextures, lm: &BspLightmap, animation_time: f32, camera_viewproj: Matrix4x4, lights: &[DynamicLight], max_lights: usize) {
         for i in &textures.transp_meshes {
             if self.mesh_indices[*i].len() > 0 {
                 let vtx_buf = &self.vtx_buffers[*i];
@@ -766,7 +869,9 @@ impl BspMapRenderer {
                 draw_geom_setup(&material, Matrix4x4::identity(), camera_viewproj);
                 bind_lightmap(lm);
 
+
                 let shader_position = material.shader.inner.get_attribute_location("in_pos");
+                let shader_normal = material.shader.inner.get_attribute_location("in_nrm");
                 let shader_uv = material.shader.inner.get_attribute_location("in_uv");
                 let shader_lm0 = material.shader.inner.get_attribute_location("in_lm0");
                 let shader_lm1 = material.shader.inner.get_attribute_location("in_lm1");
@@ -780,11 +885,11 @@ impl BspMapRenderer {
                     gl_checked!{ gl::BindBuffer(gl::ARRAY_BUFFER, vtx_buf.handle()) }
                     gl_checked!{ gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, idx_buf.handle()) }
     
-                    setup_vtx_arrays(shader_position, shader_uv, shader_lm0, shader_lm1, shader_lm2, shader_lm3, shader_color);
-
-                    // draw geometry
-                    gl_checked!{ gl::DrawElements(gl::TRIANGLES, self.mesh_indices[*i].len() as i32, gl::UNSIGNED_SHORT, 0 as *const _) }
+                    setup_vtx_arrays(shader_position, shader_normal, shader_uv, shader_lm0, shader_lm1, shader_lm2, shader_lm3, shader_color);
                 }
+
+                // draw geometry
+                self.draw_batch(*i, &material.shader.inner, lights, max_lights);
             }
         }
 
@@ -1,13 +1,15 @@
 use hecs::Entity;
 
-use crate::misc::Rectangle;
+use crate::{math::Vector3, misc::Rectangle};
 
 #[derive(Clone, Copy)]
 pub struct Camera {
     pub fov: f32,
     pub near: f32,
     pub far: f32,
-    pub viewport_rect: Option<Rectangle>
+    pub viewport_rect: Option<Rectangle>,
+    /// Local player this camera belongs to, if any. Player cameras have their viewport laid out automatically for split-screen
+    pub player_index: Option<usize>,
 }
 
 impl Camera {
@@ -16,9 +18,16 @@ impl Camera {
             fov: 60.0,
             near: 10.0,
             far: 10000.0,
-            viewport_rect: None
+            viewport_rect: None,
+            player_index: None,
         }
     }
+
+    pub fn with_player_index(self: Self, player_index: usize) -> Camera {
+        let mut result = self;
+        result.player_index = Some(player_index);
+        result
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -32,4 +41,81 @@ impl FPCamera {
             follow_entity
         }
     }
+}
+
+/// How a ThirdPersonCamera chooses its view angles
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThirdPersonMode {
+    /// Look along the followed entity's FPView (if it has one), so the camera turns with the player
+    Follow,
+    /// Orbit around the followed entity using the camera's own yaw & pitch, which may be driven by tools (for example, to inspect a model)
+    Orbit,
+}
+
+/// Camera which looks at an entity from behind, pulling in towards it to avoid clipping into walls
+#[derive(Clone, Copy)]
+pub struct ThirdPersonCamera {
+    pub follow_entity: Entity,
+    pub mode: ThirdPersonMode,
+    /// Offset from the followed entity's origin to the point the camera looks at
+    pub target_offset: Vector3,
+    /// Preferred distance from the target to the camera
+    pub distance: f32,
+    /// Sideways offset of the camera, for over-the-shoulder views
+    pub shoulder_offset: f32,
+    /// View angles in degrees. In follow mode these are copied from the followed entity's FPView
+    pub yaw: f32,
+    pub pitch: f32,
+    pub min_pitch: f32,
+    pub max_pitch: f32,
+    /// Yaw limits in degrees, or None for unlimited yaw
+    pub yaw_limits: Option<(f32, f32)>,
+    /// Seconds taken for the camera to (mostly) catch up with the target when it moves, or 0 to follow it rigidly
+    pub smoothing: f32,
+    /// Speed at which the camera moves back out to its preferred distance after being pulled in
+    pub return_speed: f32,
+    /// Current distance from the target, which may be less than the preferred distance if the view is blocked
+    pub current_distance: f32,
+    /// Smoothed target position, or None if the camera has not been updated yet
+    pub current_target: Option<Vector3>,
+}
+
+impl ThirdPersonCamera {
+    pub fn new(follow_entity: Entity, mode: ThirdPersonMode) -> ThirdPersonCamera {
+        ThirdPersonCamera {
+            follow_entity,
+            mode,
+            target_offset: Vector3::new(0.0, 0.0, 40.0),
+            distance: 100.0,
+            shoulder_offset: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            min_pitch: -80.0,
+            max_pitch: 80.0,
+            yaw_limits: None,
+            smoothing: 0.1,
+            return_speed: 200.0,
+            current_distance: 100.0,
+            current_target: None,
+        }
+    }
+
+    pub fn with_distance(self: Self, distance: f32) -> ThirdPersonCamera {
+        let mut result = self;
+        result.distance = distance;
+        result.current_distance = distance;
+        result
+    }
+
+    pub fn with_shoulder_offset(self: Self, shoulder_offset: f32) -> ThirdPersonCamera {
+        let mut result = self;
+        result.shoulder_offset = shoulder_offset;
+        result
+    }
+
+    pub fn with_target_offset(self: Self, target_offset: Vector3) -> ThirdPersonCamera {
+        let mut result = self;
+        result.target_offset = target_offset;
+        result
+    }
 }
\ No newline at end of file
@@ -1,3 +1,5 @@
+use hecs::Entity;
+
 use crate::math::Vector3;
 
 #[derive(Clone, Copy)]
@@ -10,12 +12,35 @@ pub struct CharacterController {
     pub crouch_height: f32,
 }
 
+/// How deep a character is submerged in liquid
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum WaterLevel {
+    None,
+    Feet,
+    Waist,
+    Eyes,
+}
+
 #[derive(Clone, Copy)]
 pub struct CharacterState {
     pub height: f32,
     pub velocity: Vector3,
     pub grounded: bool,
     pub crouched: bool,
+    pub ground_entity: Option<Entity>,
+    /// TexInfo index of the surface the character is standing on, if any
+    pub ground_tex_info: Option<usize>,
+    /// SURF_* flags of the surface the character is standing on
+    pub ground_surface_flags: u32,
+    pub water_level: WaterLevel,
+    /// CONTENTS_* flags of the liquid the character is in (CONTENTS_WATER, CONTENTS_SLIME or CONTENTS_LAVA), or 0 if not in any liquid
+    pub water_type: u32,
+    /// Seconds the character's eyes have been continuously submerged
+    pub submerged_time: f32,
+    /// Whether the character is climbing a ladder
+    pub on_ladder: bool,
+    /// Normal of the ladder surface being climbed, pointing away from the ladder
+    pub ladder_normal: Vector3,
 }
 
 #[derive(Clone, Copy)]
@@ -23,6 +48,8 @@ pub struct CharacterInputState {
     pub input_move_dir: Vector3,
     pub input_crouch: bool,
     pub input_jump: bool,
+    /// Movement direction while swimming, which follows the character's view pitch
+    pub input_swim_dir: Vector3,
 }
 
 impl CharacterController {
@@ -45,6 +72,14 @@ impl CharacterState {
             velocity: Vector3::zero(),
             grounded: false,
             crouched: false,
+            ground_entity: None,
+            ground_tex_info: None,
+            ground_surface_flags: 0,
+            water_level: WaterLevel::None,
+            water_type: 0,
+            submerged_time: 0.0,
+            on_ladder: false,
+            ladder_normal: Vector3::zero(),
         }
     }
 }
@@ -55,6 +90,7 @@ impl CharacterInputState {
             input_move_dir: Vector3::zero(),
             input_crouch: false,
             input_jump: false,
+            input_swim_dir: Vector3::zero(),
         }
     }
 }
\ No newline at end of file
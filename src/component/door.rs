@@ -1,12 +1,20 @@
 use hecs::Entity;
+use serde::{Deserialize, Serialize};
 
 use crate::math::Vector3;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Door {
     pub auto_open: bool,
     pub close_pos: Vector3,
     pub open_pos: Vector3,
     pub move_speed: f32,
+    /// Damage dealt to entities blocking the door
+    pub dmg: f32,
+    /// Crushers keep pushing against blocking entities instead of reversing
+    pub crusher: bool,
+    /// Time remaining for a blocked door to move back the way it came
+    pub reverse_timer: f32,
 }
 
 pub struct DoorLink {
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub health: f32,
+    pub max_health: f32,
+}
+
+impl Health {
+    pub fn new(max_health: f32) -> Health {
+        Health {
+            health: max_health,
+            max_health,
+        }
+    }
+}
@@ -1,7 +1,26 @@
+use serde::{Deserialize, Serialize};
+
 use crate::math::Vector3;
 
 #[derive(Clone, Copy)]
 pub struct Light {
     pub color: Vector3,
     pub max_radius: f32,
-}
\ No newline at end of file
+}
+
+/// Light baked into one of the map's custom light layers, which can be toggled on & off by triggering it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SwitchableLight {
+    /// Index of the custom light layer this light was baked into
+    pub layer: usize,
+    /// Lightstyle pattern used while on, or None to use the pattern in the lightstyle table
+    pub pattern: Option<String>,
+    /// Lightstyle pattern used while off
+    pub off_pattern: String,
+    /// Seconds taken to fade between the on & off patterns
+    pub fade_time: f32,
+    pub on: bool,
+    /// Current blend between the off (0.0) & on (1.0) patterns
+    pub fade: f32,
+    pub prev_triggered: bool,
+}
@@ -13,4 +13,9 @@ pub mod light;
 pub mod rendermesh;
 pub mod meshpose;
 pub mod basicanim;
-pub mod effect;
\ No newline at end of file
+pub mod effect;
+pub mod plat;
+pub mod train;
+pub mod health;
+pub mod soundsource;
+pub mod spawnindex;
\ No newline at end of file
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use crate::math::Vector3;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PlatState {
+    Top,
+    Bottom,
+    MovingUp,
+    MovingDown,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Plat {
+    pub top_pos: Vector3,
+    pub bottom_pos: Vector3,
+    pub move_speed: f32,
+    pub wait: f32,
+    pub wait_timer: f32,
+    pub state: PlatState,
+    /// Damage dealt to entities blocking the plat
+    pub dmg: f32,
+}
@@ -1,10 +1,13 @@
+/// Marks an entity as controlled by a local player, whose input is routed to it by player index
 #[derive(Clone, Copy)]
 pub struct PlayerInput {
+    pub player_index: usize,
 }
 
 impl PlayerInput {
-    pub fn new() -> PlayerInput {
+    pub fn new(player_index: usize) -> PlayerInput {
         PlayerInput {
+            player_index
         }
     }
 }
\ No newline at end of file
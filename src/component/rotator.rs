@@ -1,7 +1,28 @@
+use serde::{Deserialize, Serialize};
+
 use crate::math::Vector3;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Rotator {
     pub rot_axis: Vector3,
     pub rot_speed: f32,
+    /// Damage dealt per crush interval to characters blocking a rotating map model
+    #[serde(default)]
+    pub dmg: f32,
+}
+
+impl Rotator {
+    pub fn new(rot_axis: Vector3, rot_speed: f32) -> Rotator {
+        Rotator {
+            rot_axis,
+            rot_speed,
+            dmg: 0.0,
+        }
+    }
+
+    pub fn with_dmg(self: Self, dmg: f32) -> Rotator {
+        let mut result = self;
+        result.dmg = dmg;
+        result
+    }
 }
\ No newline at end of file
@@ -0,0 +1,34 @@
+use crate::{asset_loader::SoundHandle, audio::mixer::VoiceId};
+
+/// Positional sound emitter, panned & attenuated relative to the active camera
+pub struct SoundSource {
+    pub sound: SoundHandle,
+    pub looping: bool,
+    pub volume: f32,
+    /// Distance at which the sound becomes silent, or 0 to play at full volume everywhere
+    pub radius: f32,
+    /// Whether the sound is currently playing. Looping sounds are toggled when triggered, one-shot sounds are restarted
+    pub active: bool,
+    pub voice: Option<VoiceId>,
+    pub prev_triggered: bool,
+}
+
+impl SoundSource {
+    pub fn new(sound: SoundHandle, looping: bool, volume: f32, radius: f32) -> SoundSource {
+        SoundSource {
+            sound,
+            looping,
+            volume,
+            radius,
+            active: false,
+            voice: None,
+            prev_triggered: false,
+        }
+    }
+
+    pub fn with_active(self: Self, active: bool) -> SoundSource {
+        let mut result = self;
+        result.active = active;
+        result
+    }
+}
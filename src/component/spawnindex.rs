@@ -0,0 +1,6 @@
+/// Order in which an entity was spawned when its level was loaded. Loading a level always spawns the same entities in the same order,
+/// so this identifies map entities across save & load, unlike entity IDs which depend on which slots the world happens to reuse
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SpawnIndex {
+    pub index: u32,
+}
\ No newline at end of file
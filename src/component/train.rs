@@ -0,0 +1,24 @@
+use hecs::Entity;
+
+use crate::math::Vector3;
+
+pub struct Train {
+    pub move_speed: f32,
+    pub cur_speed: f32,
+    /// Path corner the train is currently moving towards
+    pub target: Option<Entity>,
+    /// Offset subtracted from each path corner position (trains align their mins to path corners)
+    pub corner_offset: Vector3,
+    pub wait_timer: f32,
+    pub stopped: bool,
+    pub started: bool,
+    pub prev_triggered: bool,
+    /// Damage dealt to entities blocking the train
+    pub dmg: f32,
+}
+
+pub struct PathCorner {
+    pub next: Option<Entity>,
+    pub wait: f32,
+    pub speed: f32,
+}
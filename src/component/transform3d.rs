@@ -33,4 +33,28 @@ impl Transform3D {
         result.rotation = new_rotation;
         result
     }
-}
\ No newline at end of file
+
+    /// Blend between two transforms, linearly interpolating position & scale and slerping rotation
+    pub fn lerp(a: &Transform3D, b: &Transform3D, t: f32) -> Transform3D {
+        Transform3D {
+            position: Vector3::lerp(a.position, b.position, t),
+            scale: Vector3::lerp(a.scale, b.scale, t),
+            rotation: Quaternion::slerp(a.rotation, b.rotation, t)
+        }
+    }
+
+    /// Transform to draw this entity with, given its transform from the start of the current tick (if any) and how far rendering is between the previous tick & the current one
+    pub fn interpolated(self: &Self, prev: Option<&PrevTransform3D>, alpha: f32) -> Transform3D {
+        match prev {
+            Some(v) => Transform3D::lerp(&v.transform, self, alpha),
+            None => *self
+        }
+    }
+}
+
+/// Copy of an entity's Transform3D from the start of the current tick, used to smooth out rendering of moving entities between ticks.
+/// Attached automatically to entities which may move (characters, map models, rotators, skinned meshes & cameras)
+#[derive(Clone, Copy)]
+pub struct PrevTransform3D {
+    pub transform: Transform3D
+}
@@ -1,9 +1,41 @@
 use hecs::Entity;
+use serde::{Deserialize, Serialize};
 
+use crate::misc::AABB;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TriggerState {
     pub triggered: bool,
 }
 
 pub struct TriggerLink {
-    pub target: Entity
-}
\ No newline at end of file
+    pub targets: Vec<Entity>,
+    pub killtargets: Vec<Entity>,
+    /// Seconds between a change in trigger state & that change being passed on to targets
+    pub delay: f32,
+    pub delay_timer: f32,
+    pub pending: bool,
+    pub output: bool,
+}
+
+/// Brush volume which becomes triggered when a character enters its bounds
+pub struct TriggerVolume {
+    pub bounds: AABB,
+    /// Seconds before the volume can fire again, or -1 to only fire once
+    pub wait: f32,
+    pub wait_timer: f32,
+    pub fired: bool,
+}
+
+impl TriggerLink {
+    pub fn new(delay: f32) -> TriggerLink {
+        TriggerLink {
+            targets: Vec::new(),
+            killtargets: Vec::new(),
+            delay,
+            delay_timer: 0.0,
+            pending: false,
+            output: false,
+        }
+    }
+}
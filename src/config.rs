@@ -0,0 +1,37 @@
+use std::{fs, io};
+
+use log::info;
+
+use crate::{cvar::archived_cvar_commands, input::binding_commands};
+
+/// Path to the config file which archived CVARs & key bindings are written to on exit
+pub const CONFIG_PATH: &str = "config.cfg";
+
+/// Path to the user script which is executed on startup after the config file
+pub const AUTOEXEC_PATH: &str = "autoexec.cfg";
+
+/// Read the console commands in a script file, one per line. Blank lines & lines starting with // are skipped
+pub fn read_script(path: &str) -> io::Result<Vec<String>> {
+    let script = fs::read_to_string(path)?;
+
+    Ok(script.lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with("//"))
+        .map(|x| x.to_string())
+        .collect())
+}
+
+/// Write the current key bindings & the values of all archived CVARs to the config file
+pub fn write_config() -> io::Result<()> {
+    let mut config = String::from("// generated by nanogame3d, do not modify (put custom commands in autoexec.cfg instead)\n");
+
+    for cmd in binding_commands().into_iter().chain(archived_cvar_commands()) {
+        config.push_str(&cmd);
+        config.push('\n');
+    }
+
+    fs::write(CONFIG_PATH, config)?;
+    info!("Wrote {}", CONFIG_PATH);
+
+    Ok(())
+}
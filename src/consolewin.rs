@@ -1,14 +1,24 @@
-use std::sync::RwLock;
+use std::{collections::VecDeque, sync::RwLock};
 
 use imgui::{InputTextCallbackHandler, Ui};
 use lazy_static::lazy_static;
 use log::Level;
 
+use crate::{logging::{log_enabled, write_log_file}, system::ccmd_system::{complete_command_line, Completion}};
+
+// maximum number of completion candidates shown in the suggestion popup
+const MAX_SUGGESTIONS: usize = 12;
+
+/// Maximum number of log items kept for display in the console (older items are discarded)
+const MAX_LOG_HISTORY: usize = 2000;
+
 lazy_static! {
-    static ref LOG_HISTORY: RwLock<Vec<LogItem>> = RwLock::new(Vec::new());
+    static ref LOG_HISTORY: RwLock<VecDeque<LogItem>> = RwLock::new(VecDeque::with_capacity(MAX_LOG_HISTORY));
 }
 
+#[derive(Clone, Copy, PartialEq)]
 enum LogItemType {
+    Debug,
     Info,
     Warn,
     Error,
@@ -26,6 +36,16 @@ impl LogItem {
     }
 }
 
+fn push_log_item(item: LogItem) {
+    let mut log_history = LOG_HISTORY.write().unwrap();
+
+    if log_history.len() >= MAX_LOG_HISTORY {
+        log_history.pop_front();
+    }
+
+    log_history.push_back(item);
+}
+
 pub struct ConsoleWindow {
     item_spacing_height: f32,
     cmd_string: String,
@@ -33,6 +53,13 @@ pub struct ConsoleWindow {
     history: Vec<String>,
     history_pos: i32,
     exec_queue: Vec<String>,
+    show_debug: bool,
+    show_info: bool,
+    show_warn: bool,
+    show_error: bool,
+    search: String,
+    /// Completions for the command line they were last looked up for
+    suggestions: Option<(String, Completion)>,
 }
 
 pub struct ConsoleWindowLogger {
@@ -43,7 +70,46 @@ struct ConsoleWindowCallbackHandler<'a> {
     history_pos: &'a mut i32,
 }
 
+// longest prefix shared by all of the given strings
+fn common_prefix(candidates: &[String]) -> &str {
+    let first = &candidates[0];
+    let mut len = first.len();
+
+    for c in &candidates[1..] {
+        len = len.min(first.bytes().zip(c.bytes()).take_while(|(a, b)| a == b).count());
+    }
+
+    while !first.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    &first[..len]
+}
+
 impl<'a> InputTextCallbackHandler for ConsoleWindowCallbackHandler<'a> {
+    fn on_completion(&mut self, mut data: imgui::TextCallbackData) {
+        let line = data.str().to_string();
+        let completion = complete_command_line(&line);
+
+        if completion.candidates.is_empty() {
+            return;
+        }
+
+        // complete a unique match & move on to the next argument, otherwise complete as much as all candidates share
+        let replacement = if completion.candidates.len() == 1 {
+            format!("{} ", completion.candidates[0])
+        }
+        else {
+            common_prefix(&completion.candidates).to_string()
+        };
+
+        if replacement.len() > line.len() - completion.start {
+            // replace everything from the start of the word to the end of the line
+            data.remove_chars(completion.start, line.len() - completion.start);
+            data.push_str(&replacement);
+        }
+    }
+
     fn on_history(&mut self, dir: imgui::HistoryDirection, mut data: imgui::TextCallbackData) {
         if self.history.len() == 0 {
             return;
@@ -90,9 +156,27 @@ impl ConsoleWindow {
             history: Vec::new(),
             history_pos: -1,
             exec_queue: Vec::new(),
+            show_debug: true,
+            show_info: true,
+            show_warn: true,
+            show_error: true,
+            search: String::new(),
+            suggestions: None,
         }
     }
 
+    fn item_visible(self: &Self, item: &LogItem) -> bool {
+        let type_visible = match item.item_type {
+            LogItemType::Debug => self.show_debug,
+            LogItemType::Info => self.show_info,
+            LogItemType::Warn => self.show_warn,
+            LogItemType::Error => self.show_error,
+            LogItemType::Command => true,
+        };
+
+        type_visible && (self.search.is_empty() || item.item.to_lowercase().contains(&self.search.to_lowercase()))
+    }
+
     pub fn drain_commands(self: &mut Self) -> std::vec::Drain<'_, String> {
         self.exec_queue.drain(..)
     }
@@ -111,6 +195,20 @@ impl ConsoleWindow {
             .flags(overlay_flags)
             .begin()
         {
+            // log filters
+            ui.checkbox("Errors", &mut self.show_error);
+            ui.same_line();
+            ui.checkbox("Warnings", &mut self.show_warn);
+            ui.same_line();
+            ui.checkbox("Info", &mut self.show_info);
+            ui.same_line();
+            ui.checkbox("Debug", &mut self.show_debug);
+            ui.same_line();
+            ui.set_next_item_width(-1.0);
+            ui.input_text("##LOG_SEARCH", &mut self.search).hint("Search").build();
+
+            ui.separator();
+
             let footer_height_to_reserve = self.item_spacing_height + ui.frame_height_with_spacing();
             if let Some(scroll_area) = ui.child_window("CONSOLE_SCROLL_REGION")
                 .size([0.0, -footer_height_to_reserve])
@@ -119,8 +217,9 @@ impl ConsoleWindow {
                 // draw items
                 let item_spacing = ui.push_style_var(imgui::StyleVar::ItemSpacing([4.0, 1.0]));
                 let items = LOG_HISTORY.read().unwrap();
-                for item in items.iter() {
+                for item in items.iter().filter(|x| self.item_visible(x)) {
                     let item_color = match item.item_type {
+                        LogItemType::Debug => ui.push_style_color(imgui::StyleColor::Text, [0.6, 0.6, 0.6, 1.0]),
                         LogItemType::Info => ui.push_style_color(imgui::StyleColor::Text, [1.0, 1.0, 1.0, 1.0]),
                         LogItemType::Warn => ui.push_style_color(imgui::StyleColor::Text, [1.0, 1.0, 0.0, 1.0]),
                         LogItemType::Error => ui.push_style_color(imgui::StyleColor::Text, [1.0, 0.1, 0.1, 1.0]),
@@ -151,45 +250,90 @@ impl ConsoleWindow {
             ui.set_next_item_width(-1.0);
             if ui.input_text("##CMD_INPUT", &mut self.cmd_string)
                 .enter_returns_true(true)
-                .callback(imgui::InputTextCallback::HISTORY, cmd_callback_handler)
+                .callback(imgui::InputTextCallback::HISTORY | imgui::InputTextCallback::COMPLETION, cmd_callback_handler)
                 .build()
             {
                 self.history.push(self.cmd_string.clone());
                 self.exec_queue.push(self.cmd_string.clone());
                 
-                let mut log_history = LOG_HISTORY.write().unwrap();
-                log_history.push(LogItem::new(LogItemType::Command, format!(">> {}", self.cmd_string)));
+                push_log_item(LogItem::new(LogItemType::Command, format!(">> {}", self.cmd_string)));
 
                 self.scroll_to_bottom = true;
                 self.cmd_string.clear();
                 self.history_pos = -1;
             }
 
+            let input_pos = ui.item_rect_min();
+            let input_height = ui.item_rect_size()[1];
+
             console_win.end();
+
+            self.draw_suggestions([input_pos[0], input_pos[1] + input_height], ui);
+        }
+    }
+
+    // show possible completions for the command being typed in a popup below the input box
+    fn draw_suggestions(self: &mut Self, position: [f32;2], ui: &Ui) {
+        if self.cmd_string.is_empty() {
+            return;
+        }
+
+        // completers may read directories, so only look completions up again when the command line changes
+        if self.suggestions.as_ref().is_none_or(|(line, _)| *line != self.cmd_string) {
+            self.suggestions = Some((self.cmd_string.clone(), complete_command_line(&self.cmd_string)));
+        }
+
+        let (_, completion) = self.suggestions.as_ref().unwrap();
+        let partial = &self.cmd_string[completion.start..];
+
+        if completion.candidates.is_empty() || (completion.candidates.len() == 1 && completion.candidates[0] == partial) {
+            return;
+        }
+
+        let popup_flags = imgui::WindowFlags::NO_DECORATION |
+            imgui::WindowFlags::ALWAYS_AUTO_RESIZE |
+            imgui::WindowFlags::NO_SAVED_SETTINGS |
+            imgui::WindowFlags::NO_FOCUS_ON_APPEARING |
+            imgui::WindowFlags::NO_NAV;
+
+        if let Some(popup_win) = ui.window("CONSOLE_SUGGESTIONS")
+            .position(position, imgui::Condition::Always)
+            .bg_alpha(0.9)
+            .flags(popup_flags)
+            .begin()
+        {
+            for candidate in completion.candidates.iter().take(MAX_SUGGESTIONS) {
+                ui.text(candidate);
+            }
+
+            if completion.candidates.len() > MAX_SUGGESTIONS {
+                ui.text_disabled(format!("... {} more", completion.candidates.len() - MAX_SUGGESTIONS));
+            }
+
+            popup_win.end();
         }
     }
 }
 
 impl log::Log for ConsoleWindowLogger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= Level::Info
+        log_enabled(metadata.target(), metadata.level())
     }
 
     fn log(&self, record: &log::Record) {
         if self.enabled(record.metadata()) {
             println!("{} - {}", record.level(), record.args());
-
-            let mut log_history = LOG_HISTORY.write().unwrap();
+            write_log_file(record);
 
             let log_type = match record.level() {
                 Level::Error => LogItemType::Error,
                 Level::Warn => LogItemType::Warn,
                 Level::Info => LogItemType::Info,
-                Level::Debug => LogItemType::Info,
-                Level::Trace => LogItemType::Info,
+                Level::Debug => LogItemType::Debug,
+                Level::Trace => LogItemType::Debug,
             };
 
-            log_history.push(LogItem::new(log_type, format!("{}", record.args())));
+            push_log_item(LogItem::new(log_type, format!("{}", record.args())));
         }
     }
 
@@ -1,12 +1,26 @@
 use log::{info, error};
-use std::{collections::HashMap, sync::RwLock};
+use std::{collections::HashMap, fmt::Display, sync::RwLock};
 
 use lazy_static::lazy_static;
+use shellwords::join;
 
 lazy_static! {
     static ref CVARS: RwLock<HashMap<String, CVar>> = RwLock::new(HashMap::new());
 }
 
+/// CVAR can only be changed while sv_cheats is enabled
+pub const CVAR_CHEAT: u32 = 1;
+
+/// CVAR cannot be changed from the console
+pub const CVAR_READONLY: u32 = 2;
+
+/// CVAR is saved to the config file on exit, so that it persists between sessions
+pub const CVAR_ARCHIVE: u32 = 4;
+
+/// Callback invoked with the new value whenever a CVAR is set
+pub type CVarCallback = fn(&CVarValue);
+
+#[derive(Clone, PartialEq)]
 pub enum CVarValue {
     Bool(bool),
     Int(i32),
@@ -18,17 +32,18 @@ pub trait SetCVar<T> {
     fn from(val: T) -> CVarValue;
 }
 
+/// Conversion from a CVAR value to a concrete type. Returns None if the value cannot be converted
 pub trait GetCVar<T> {
-    fn get(&self) -> T;
+    fn get(&self) -> Option<T>;
 }
 
 impl GetCVar<bool> for CVarValue {
-    fn get(&self) -> bool {
+    fn get(&self) -> Option<bool> {
         match self {
-            CVarValue::Bool(v) => *v,
-            CVarValue::Int(v) => *v != 0,
-            CVarValue::Float(v) => *v != 0.0,
-            CVarValue::String(_) => panic!("Cannot convert string to bool"),
+            CVarValue::Bool(v) => Some(*v),
+            CVarValue::Int(v) => Some(*v != 0),
+            CVarValue::Float(v) => Some(*v != 0.0),
+            CVarValue::String(v) => parse_bool(v),
         }
     }
 }
@@ -40,12 +55,12 @@ impl From<bool> for CVarValue {
 }
 
 impl GetCVar<i32> for CVarValue {
-    fn get(&self) -> i32 {
+    fn get(&self) -> Option<i32> {
         match self {
-            CVarValue::Bool(v) => *v as i32,
-            CVarValue::Int(v) => *v,
-            CVarValue::Float(v) => *v as i32,
-            CVarValue::String(_) => panic!("Cannot convert string to int"),
+            CVarValue::Bool(v) => Some(*v as i32),
+            CVarValue::Int(v) => Some(*v),
+            CVarValue::Float(v) => Some(*v as i32),
+            CVarValue::String(v) => v.parse().ok(),
         }
     }
 }
@@ -57,12 +72,12 @@ impl From<i32> for CVarValue {
 }
 
 impl GetCVar<f32> for CVarValue {
-    fn get(&self) -> f32 {
+    fn get(&self) -> Option<f32> {
         match self {
-            CVarValue::Bool(v) => *v as i32 as f32,
-            CVarValue::Int(v) => *v as f32,
-            CVarValue::Float(v) => *v,
-            CVarValue::String(_) => panic!("Cannot convert string to float"),
+            CVarValue::Bool(v) => Some(*v as i32 as f32),
+            CVarValue::Int(v) => Some(*v as f32),
+            CVarValue::Float(v) => Some(*v),
+            CVarValue::String(v) => v.parse().ok(),
         }
     }
 }
@@ -74,13 +89,8 @@ impl From<f32> for CVarValue {
 }
 
 impl GetCVar<String> for CVarValue {
-    fn get(&self) -> String {
-        match self {
-            CVarValue::Bool(v) => v.to_string(),
-            CVarValue::Int(v) => v.to_string(),
-            CVarValue::Float(v) => v.to_string(),
-            CVarValue::String(v) => v.clone(),
-        }
+    fn get(&self) -> Option<String> {
+        Some(self.to_string())
     }
 }
 
@@ -90,91 +100,267 @@ impl From<String> for CVarValue {
     }
 }
 
+impl Display for CVarValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CVarValue::Bool(v) => write!(f, "{}", v),
+            CVarValue::Int(v) => write!(f, "{}", v),
+            CVarValue::Float(v) => write!(f, "{}", v),
+            CVarValue::String(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl CVarValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            CVarValue::Bool(_) => "bool",
+            CVarValue::Int(_) => "int",
+            CVarValue::Float(_) => "float",
+            CVarValue::String(_) => "string",
+        }
+    }
+
+    // parse a string into a value of the same type as this one
+    fn parse_as(&self, val: &str) -> Result<CVarValue, String> {
+        match self {
+            CVarValue::Bool(_) => match parse_bool(val) {
+                Some(v) => Ok(CVarValue::Bool(v)),
+                None => Err(format!("\"{}\" is not a valid bool", val))
+            },
+            CVarValue::Int(_) => match val.parse::<i32>() {
+                Ok(v) => Ok(CVarValue::Int(v)),
+                Err(e) => Err(e.to_string())
+            },
+            CVarValue::Float(_) => match val.parse::<f32>() {
+                Ok(v) => Ok(CVarValue::Float(v)),
+                Err(e) => Err(e.to_string())
+            },
+            CVarValue::String(_) => Ok(CVarValue::String(val.to_string())),
+        }
+    }
+}
+
+// bools may be given either as true/false or as 1/0
+fn parse_bool(val: &str) -> Option<bool> {
+    match val {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None
+    }
+}
+
+/// Optional metadata for a CVAR: flags (CVAR_CHEAT, CVAR_READONLY, CVAR_ARCHIVE), bounds for numeric values, and a change callback
+#[derive(Clone, Copy, Default)]
+pub struct CVarOptions {
+    pub flags: u32,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub on_change: Option<CVarCallback>,
+}
+
+impl CVarOptions {
+    pub fn new() -> CVarOptions {
+        CVarOptions::default()
+    }
+
+    pub fn with_flags(mut self: Self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn with_min(mut self: Self, min: f32) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn with_max(mut self: Self, max: f32) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    pub fn with_on_change(mut self: Self, on_change: CVarCallback) -> Self {
+        self.on_change = Some(on_change);
+        self
+    }
+
+    fn flag_names(self: &Self) -> Vec<&'static str> {
+        [(CVAR_CHEAT, "cheat"), (CVAR_READONLY, "read-only"), (CVAR_ARCHIVE, "archive")].into_iter()
+            .filter(|(flag, _)| self.flags & flag != 0)
+            .map(|(_, name)| name)
+            .collect()
+    }
+}
+
 struct CVar {
     pub help: String,
     pub value: Option<CVarValue>,
-    pub default: CVarValue
+    pub default: CVarValue,
+    pub options: CVarOptions,
+}
+
+impl CVar {
+    fn current(self: &Self) -> &CVarValue {
+        self.value.as_ref().unwrap_or(&self.default)
+    }
 }
 
 pub fn define_cvar<T>(name: &str, default_val: T, help: &str) where CVarValue : From<T> {
+    define_cvar_with_options(name, default_val, help, CVarOptions::default());
+}
+
+/// Define a CVAR with flags, bounds and/or a change callback
+pub fn define_cvar_with_options<T>(name: &str, default_val: T, help: &str, options: CVarOptions) where CVarValue : From<T> {
     let mut cvars = CVARS.write().unwrap();
     
     cvars.insert(name.to_string(), CVar {
         help: help.to_string(),
         value: None,
-        default: CVarValue::from(default_val)
+        default: CVarValue::from(default_val),
+        options,
     });
 }
 
-pub fn print_cvars() {
+/// Returns a "set" command for each archived CVAR which would restore its current value
+pub fn archived_cvar_commands() -> Vec<String> {
     let cvars = CVARS.read().unwrap();
 
-    for (name, val) in cvars.iter() {
-        let cvar_type = match val.default {
-            CVarValue::Bool(_) => "bool",
-            CVarValue::Int(_) => "int",
-            CVarValue::Float(_) => "float",
-            CVarValue::String(_) => "string",
-        };
-        
-        info!("{} ({}) - {}", name, cvar_type, val.help);
+    let mut commands = cvars.iter()
+        .filter(|(_, cv)| cv.options.flags & CVAR_ARCHIVE != 0)
+        .map(|(name, cv)| join(&["set", name, &cv.current().to_string()]))
+        .collect::<Vec<_>>();
+
+    commands.sort();
+    commands
+}
+
+/// Returns the names of all defined CVARs
+pub fn cvar_names() -> Vec<String> {
+    CVARS.read().unwrap().keys().cloned().collect()
+}
+
+/// Print the value, default, flags & help of each CVAR whose name starts with the given prefix
+pub fn print_cvars(prefix: &str) {
+    let cvars = CVARS.read().unwrap();
+
+    let mut sorted = cvars.iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (name, cv) in sorted {
+        let mut attributes = vec![cv.default.type_name()];
+        attributes.extend(cv.options.flag_names());
+
+        info!("{} = \"{}\" (default: \"{}\") [{}] - {}", name, cv.current(), cv.default, attributes.join(", "), cv.help);
     }
 }
 
-pub fn set_cvar(name: &str, val: &str) {
-    let mut cvars = CVARS.write().unwrap();
-    let cv = cvars.get_mut(name);
-
-    if let Some(cv) = cv {
-        match cv.default {
-            CVarValue::Bool(_) => {
-                match val.parse::<bool>() {
-                    Ok(v) => {
-                        cv.value = Some(CVarValue::Bool(v));
-                    }
-                    Err(e) => {
-                        error!("Failed setting CVAR: {}", e);
-                    }
-                };
-            },
-            CVarValue::Int(_) => {
-                match val.parse::<i32>() {
-                    Ok(v) => {
-                        cv.value = Some(CVarValue::Int(v));
-                    }
-                    Err(e) => {
-                        error!("Failed setting CVAR: {}", e);
-                    }
-                };
-            },
-            CVarValue::Float(_) => {
-                match val.parse::<f32>() {
-                    Ok(v) => {
-                        cv.value = Some(CVarValue::Float(v));
-                    }
-                    Err(e) => {
-                        error!("Failed setting CVAR: {}", e);
-                    }
-                };
-            },
-            CVarValue::String(_) => {
-                cv.value = Some(CVarValue::String(val.to_string()));
-            },
+// validate & apply a new value to a CVAR, returning its change callback (which must be invoked after the CVAR lock is released)
+fn apply_cvar(cvars: &mut HashMap<String, CVar>, name: &str, new_value: Result<CVarValue, String>) -> Option<(CVarCallback, CVarValue)> {
+    let cheats = cvars.get("sv_cheats").and_then(|x| GetCVar::<bool>::get(x.current())).unwrap_or(false);
+
+    let cv = match cvars.get_mut(name) {
+        Some(v) => v,
+        None => {
+            error!("Invalid/unknown CVAR: {}", name);
+            return None;
         }
+    };
+
+    if cv.options.flags & CVAR_READONLY != 0 {
+        error!("CVAR {} is read-only", name);
+        return None;
     }
-    else {
-        error!("Invalid/unknown CVAR: {}", name);
+
+    if cv.options.flags & CVAR_CHEAT != 0 && !cheats {
+        error!("CVAR {} is cheat protected (requires sv_cheats 1)", name);
+        return None;
+    }
+
+    let new_value = match new_value {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed setting CVAR: {}", e);
+            return None;
+        }
+    };
+
+    if let Some(v) = GetCVar::<f32>::get(&new_value).filter(|_| !matches!(new_value, CVarValue::String(_))) {
+        if cv.options.min.is_some_and(|min| v < min) || cv.options.max.is_some_and(|max| v > max) {
+            error!("Failed setting CVAR: {} is out of range for {} (min: {}, max: {})", new_value, name,
+                cv.options.min.map_or("none".to_string(), |x| x.to_string()),
+                cv.options.max.map_or("none".to_string(), |x| x.to_string()));
+            return None;
+        }
     }
+
+    cv.value = Some(new_value.clone());
+    cv.options.on_change.map(|x| (x, new_value))
 }
 
-pub fn get_cvar<T>(name: &str) -> T where CVarValue : GetCVar<T> {
-    let cvars = CVARS.read().unwrap();
-    let cv = &cvars[name];
+pub fn set_cvar(name: &str, val: &str) {
+    let callback = {
+        let mut cvars = CVARS.write().unwrap();
+        let new_value = match cvars.get(name) {
+            Some(cv) => cv.default.parse_as(val),
+            None => Err(String::new()),
+        };
+
+        apply_cvar(&mut cvars, name, new_value)
+    };
 
-    if let Some(val) = &cv.value {
-        return val.get();
+    if let Some((on_change, value)) = callback {
+        on_change(&value);
     }
-    else {
-        return cv.default.get();
+}
+
+/// Restore a CVAR to its default value
+pub fn reset_cvar(name: &str) {
+    let callback = {
+        let mut cvars = CVARS.write().unwrap();
+        let new_value = match cvars.get(name) {
+            Some(cv) => Ok(cv.default.clone()),
+            None => Err(String::new()),
+        };
+
+        apply_cvar(&mut cvars, name, new_value)
+    };
+
+    if let Some((on_change, value)) = callback {
+        on_change(&value);
     }
-}
\ No newline at end of file
+}
+
+/// Flip a bool CVAR between true & false, or an int CVAR between 0 & 1
+pub fn toggle_cvar(name: &str) {
+    let callback = {
+        let mut cvars = CVARS.write().unwrap();
+        let new_value = match cvars.get(name).map(|x| x.current()) {
+            Some(CVarValue::Bool(v)) => Ok(CVarValue::Bool(!v)),
+            Some(CVarValue::Int(v)) => Ok(CVarValue::Int(if *v == 0 { 1 } else { 0 })),
+            Some(v) => Err(format!("{} is a {} and cannot be toggled", name, v.type_name())),
+            None => Err(String::new()),
+        };
+
+        apply_cvar(&mut cvars, name, new_value)
+    };
+
+    if let Some((on_change, value)) = callback {
+        on_change(&value);
+    }
+}
+
+/// Get the value of a CVAR, or None if it is not defined or cannot be converted to the requested type
+pub fn try_get_cvar<T>(name: &str) -> Option<T> where CVarValue : GetCVar<T> {
+    let cvars = CVARS.read().unwrap();
+    cvars.get(name).and_then(|cv| cv.current().get())
+}
+
+/// Get the value of a CVAR. Panics if the CVAR is not defined or cannot be converted to the requested type
+pub fn get_cvar<T>(name: &str) -> T where CVarValue : GetCVar<T> {
+    match try_get_cvar(name) {
+        Some(v) => v,
+        None => panic!("CVAR {} is not defined or cannot be converted to the requested type", name)
+    }
+}
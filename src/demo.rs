@@ -0,0 +1,351 @@
+use std::{fmt::Display, fs::{self, File}, io::{BufReader, BufWriter, ErrorKind, Read, Write}, path::Path};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::gamestate::{InputState, MAX_LOCAL_PLAYERS};
+
+pub const DEMO_DIR: &str = "demos";
+const DEMO_MAGIC: [u8;4] = *b"NDEM";
+const DEMO_VERSION: u32 = 1;
+
+const INPUT_FLAG_JUMP: u8 = 1;
+const INPUT_FLAG_CROUCH: u8 = 2;
+
+#[derive(Debug)]
+pub enum DemoError {
+    IOError(std::io::Error),
+    InvalidMagic,
+    InvalidVersion(u32),
+    InvalidMapName,
+    InvalidPlayerCount(u8),
+    InvalidName(String),
+}
+
+impl Display for DemoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DemoError::IOError(e) => write!(f, "IO error: {}", e),
+            DemoError::InvalidMagic => write!(f, "Not a demo file"),
+            DemoError::InvalidVersion(v) => write!(f, "Unsupported demo version: {} (expected {})", v, DEMO_VERSION),
+            DemoError::InvalidMapName => write!(f, "Demo map name is not valid UTF-8"),
+            DemoError::InvalidPlayerCount(v) => write!(f, "Invalid demo player count: {}", v),
+            DemoError::InvalidName(name) => write!(f, "Invalid demo name: {}", name),
+        }
+    }
+}
+
+impl From<std::io::Error> for DemoError {
+    fn from(value: std::io::Error) -> Self {
+        DemoError::IOError(value)
+    }
+}
+
+/// Starting state a demo is recorded from
+pub struct DemoHeader {
+    pub map_name: String,
+    pub seed: u64,
+    /// Number of local players, each of which has their input recorded every tick
+    pub num_players: usize,
+}
+
+/// Writes per-tick input to a demo file
+pub struct DemoRecorder {
+    writer: BufWriter<File>,
+    num_players: usize,
+    num_frames: usize,
+}
+
+/// Replays per-tick input from a demo file
+pub struct DemoPlayer {
+    pub header: DemoHeader,
+    frames: Vec<Vec<InputState>>,
+    cursor: usize,
+}
+
+/// Get the path of a demo file. Names must be plain file names so that demos can't be written outside of the demos directory
+pub fn demo_path(name: &str) -> Result<String, DemoError> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        return Err(DemoError::InvalidName(name.to_string()));
+    }
+
+    Ok(format!("{}/{}.dem", DEMO_DIR, name))
+}
+
+impl DemoRecorder {
+    /// Create a new demo file, creating the demos directory if necessary
+    pub fn create(name: &str, header: &DemoHeader) -> Result<DemoRecorder, DemoError> {
+        let path = demo_path(name)?;
+        fs::create_dir_all(DEMO_DIR)?;
+        DemoRecorder::create_at(path, header)
+    }
+
+    fn create_at<P: AsRef<Path>>(path: P, header: &DemoHeader) -> Result<DemoRecorder, DemoError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(&DEMO_MAGIC)?;
+        writer.write_u32::<LittleEndian>(DEMO_VERSION)?;
+        writer.write_u64::<LittleEndian>(header.seed)?;
+        writer.write_u16::<LittleEndian>(header.map_name.len() as u16)?;
+        writer.write_all(header.map_name.as_bytes())?;
+        writer.write_u8(header.num_players as u8)?;
+
+        Ok(DemoRecorder {
+            writer,
+            num_players: header.num_players,
+            num_frames: 0,
+        })
+    }
+
+    /// Write one tick of input for each player the demo was created with. Players missing from inputs are recorded as having no input
+    pub fn write_frame(self: &mut Self, inputs: &[InputState]) -> Result<(), DemoError> {
+        for player_index in 0..self.num_players {
+            let input = InputState::for_player(inputs, player_index);
+
+            let mut flags = 0;
+            if input.jump {
+                flags |= INPUT_FLAG_JUMP;
+            }
+            if input.crouch {
+                flags |= INPUT_FLAG_CROUCH;
+            }
+
+            self.writer.write_f32::<LittleEndian>(input.move_x)?;
+            self.writer.write_f32::<LittleEndian>(input.move_y)?;
+            self.writer.write_f32::<LittleEndian>(input.look_x)?;
+            self.writer.write_f32::<LittleEndian>(input.look_y)?;
+            self.writer.write_u8(flags)?;
+        }
+
+        self.num_frames += 1;
+        Ok(())
+    }
+
+    /// Flush the demo to disk, returning the number of frames recorded
+    pub fn finish(mut self: Self) -> Result<usize, DemoError> {
+        self.writer.flush()?;
+        Ok(self.num_frames)
+    }
+}
+
+impl DemoPlayer {
+    pub fn open(name: &str) -> Result<DemoPlayer, DemoError> {
+        DemoPlayer::open_at(demo_path(name)?)
+    }
+
+    fn open_at<P: AsRef<Path>>(path: P) -> Result<DemoPlayer, DemoError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0;4];
+        reader.read_exact(&mut magic)?;
+        if magic != DEMO_MAGIC {
+            return Err(DemoError::InvalidMagic);
+        }
+
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != DEMO_VERSION {
+            return Err(DemoError::InvalidVersion(version));
+        }
+
+        let seed = reader.read_u64::<LittleEndian>()?;
+
+        let map_name_len = reader.read_u16::<LittleEndian>()? as usize;
+        let mut map_name = vec![0;map_name_len];
+        reader.read_exact(&mut map_name)?;
+        let map_name = match String::from_utf8(map_name) {
+            Ok(v) => v,
+            Err(_) => {
+                return Err(DemoError::InvalidMapName);
+            }
+        };
+
+        let num_players = reader.read_u8()?;
+        if num_players == 0 || num_players as usize > MAX_LOCAL_PLAYERS {
+            return Err(DemoError::InvalidPlayerCount(num_players));
+        }
+
+        // read frames until end of file
+        let mut frames = Vec::new();
+        'frames: loop {
+            let mut frame = Vec::with_capacity(num_players as usize);
+
+            for player_index in 0..num_players {
+                let move_x = match reader.read_f32::<LittleEndian>() {
+                    Ok(v) => v,
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof && player_index == 0 => break 'frames,
+                    Err(e) => return Err(DemoError::IOError(e))
+                };
+
+                let move_y = reader.read_f32::<LittleEndian>()?;
+                let look_x = reader.read_f32::<LittleEndian>()?;
+                let look_y = reader.read_f32::<LittleEndian>()?;
+                let flags = reader.read_u8()?;
+
+                frame.push(InputState {
+                    move_x,
+                    move_y,
+                    look_x,
+                    look_y,
+                    jump: flags & INPUT_FLAG_JUMP != 0,
+                    crouch: flags & INPUT_FLAG_CROUCH != 0,
+                });
+            }
+
+            frames.push(frame);
+        }
+
+        Ok(DemoPlayer {
+            header: DemoHeader { map_name, seed, num_players: num_players as usize },
+            frames,
+            cursor: 0,
+        })
+    }
+
+    pub fn num_frames(self: &Self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns each player's input for the next tick, or None once the demo has finished
+    pub fn next_frame(self: &mut Self) -> Option<Vec<InputState>> {
+        let frame = self.frames.get(self.cursor).cloned();
+        self.cursor += 1;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    // per-test file in the system temp directory, so tests running in parallel don't clobber each other
+    fn temp_demo_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nanogame3d-test-{}-{}.dem", std::process::id(), name))
+    }
+
+    fn test_input(i: usize) -> InputState {
+        InputState {
+            move_x: i as f32 * 0.25,
+            move_y: -(i as f32),
+            look_x: 0.5,
+            look_y: i as f32 * 2.0,
+            crouch: i.is_multiple_of(2),
+            jump: i.is_multiple_of(3),
+        }
+    }
+
+    fn record_test_demo(path: &Path, num_players: usize, num_frames: usize) {
+        let header = DemoHeader { map_name: "e1m1".to_string(), seed: 0xDEADBEEF, num_players };
+        let mut recorder = DemoRecorder::create_at(path, &header).unwrap();
+
+        for frame in 0..num_frames {
+            let inputs = (0..num_players).map(|x| test_input(frame * num_players + x)).collect::<Vec<_>>();
+            recorder.write_frame(&inputs).unwrap();
+        }
+
+        assert_eq!(recorder.finish().unwrap(), num_frames);
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_demo_path("round_trip");
+        record_test_demo(&path, 2, 10);
+
+        let mut player = DemoPlayer::open_at(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(player.header.map_name, "e1m1");
+        assert_eq!(player.header.seed, 0xDEADBEEF);
+        assert_eq!(player.header.num_players, 2);
+        assert_eq!(player.num_frames(), 10);
+
+        for frame in 0..10 {
+            let inputs = player.next_frame().unwrap();
+            assert_eq!(inputs.len(), 2);
+
+            for (player_index, input) in inputs.iter().enumerate() {
+                let expected = test_input(frame * 2 + player_index);
+                assert_eq!(input.move_x, expected.move_x);
+                assert_eq!(input.move_y, expected.move_y);
+                assert_eq!(input.look_x, expected.look_x);
+                assert_eq!(input.look_y, expected.look_y);
+                assert_eq!(input.crouch, expected.crouch);
+                assert_eq!(input.jump, expected.jump);
+            }
+        }
+
+        assert!(player.next_frame().is_none());
+    }
+
+    #[test]
+    fn missing_players_recorded_as_no_input() {
+        let path = temp_demo_path("missing_players");
+        let header = DemoHeader { map_name: "e1m1".to_string(), seed: 0, num_players: 3 };
+        let mut recorder = DemoRecorder::create_at(&path, &header).unwrap();
+        recorder.write_frame(&[test_input(1)]).unwrap();
+        recorder.finish().unwrap();
+
+        let mut player = DemoPlayer::open_at(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let inputs = player.next_frame().unwrap();
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[0].move_x, 0.25);
+        assert_eq!(inputs[2].move_x, 0.0);
+        assert!(!inputs[2].jump);
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let path = temp_demo_path("truncated_frame");
+        record_test_demo(&path, 2, 4);
+
+        // cut off partway through the last player's input on the last frame
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 3]).unwrap();
+
+        let result = DemoPlayer::open_at(&path);
+        fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(DemoError::IOError(e)) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+            Err(e) => panic!("Unexpected error: {}", e),
+            Ok(_) => panic!("Truncated demo should fail to open"),
+        }
+    }
+
+    #[test]
+    fn truncated_header() {
+        let path = temp_demo_path("truncated_header");
+        record_test_demo(&path, 1, 0);
+
+        // cut off the player count
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+
+        let result = DemoPlayer::open_at(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(DemoError::IOError(_))));
+    }
+
+    #[test]
+    fn invalid_magic() {
+        let path = temp_demo_path("invalid_magic");
+        fs::write(&path, b"NOPE").unwrap();
+
+        let result = DemoPlayer::open_at(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(DemoError::InvalidMagic)));
+    }
+
+    #[test]
+    fn invalid_names() {
+        assert_eq!(demo_path("test").unwrap(), format!("{}/test.dem", DEMO_DIR));
+
+        for name in ["", "../../x", "a/b", "a\\b", ".."] {
+            assert!(matches!(demo_path(name), Err(DemoError::InvalidName(_))));
+        }
+    }
+}
\ No newline at end of file
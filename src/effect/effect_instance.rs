@@ -1,7 +1,7 @@
 use std::{mem::offset_of, ops::Range};
 
 use noise::{NoiseFn, Simplex};
-use rand::{distr::uniform::SampleUniform, rngs::ThreadRng, Rng};
+use rand::{distr::uniform::SampleUniform, rngs::StdRng, Rng};
 
 use crate::{asset_loader::EffectHandle, bsp::bspcommon::coord_space_transform, graphics::{buffer::Buffer, shader::Shader}, math::{Matrix4x4, Quaternion, Vector2, Vector3, Vector4}, misc::Color32};
 
@@ -128,7 +128,7 @@ impl EffectEmitterInstance {
         }
     }
 
-    fn random_range<T>(rng: &mut ThreadRng, range: Range<T>) -> T where T : SampleUniform + PartialOrd {
+    fn random_range<T>(rng: &mut StdRng, range: Range<T>) -> T where T : SampleUniform + PartialOrd {
         if range.is_empty() {
             return range.start;
         }
@@ -136,7 +136,7 @@ impl EffectEmitterInstance {
         rng.random_range::<T, Range<T>>(range)
     }
 
-    fn random_axis(rng: &mut ThreadRng, dir: Vector3, spread: f32) -> Vector3 {
+    fn random_axis(rng: &mut StdRng, dir: Vector3, spread: f32) -> Vector3 {
         let r = Quaternion::from_euler(Vector3::new(
             Self::random_range(rng, -spread .. spread),
             Self::random_range(rng, -spread .. spread),
@@ -146,7 +146,7 @@ impl EffectEmitterInstance {
         return r * dir;
     }
 
-    fn emit_particle(self: &mut EffectEmitterInstance, data: &EffectEmitter, parent_transform: Matrix4x4, rng: &mut ThreadRng) -> Particle {
+    fn emit_particle(self: &mut EffectEmitterInstance, data: &EffectEmitter, parent_transform: Matrix4x4, rng: &mut StdRng) -> Particle {
         let xform = self.transform * parent_transform;
 
         let position = match data.emit.shape {
@@ -231,7 +231,7 @@ impl EffectEmitterInstance {
         }
     }
 
-    pub fn update_emit(self: &mut EffectEmitterInstance, data: &EffectEmitter, parent_transform: Matrix4x4, rng: &mut ThreadRng, delta: f32) {
+    pub fn update_emit(self: &mut EffectEmitterInstance, data: &EffectEmitter, parent_transform: Matrix4x4, rng: &mut StdRng, delta: f32) {
         if !self.enable_emit {
             return;
         }
@@ -568,7 +568,7 @@ impl EffectInstance {
         EffectInstance { transform: Matrix4x4::identity(), effect_data: data.clone(), enable_emit, emitters }
     }
 
-    pub fn update(self: &mut EffectInstance, rng: &mut ThreadRng, delta: f32) {
+    pub fn update(self: &mut EffectInstance, rng: &mut StdRng, delta: f32) {
         for em in &mut self.emitters {
             let emitter_data = &self.effect_data.emitters[em.index];
 
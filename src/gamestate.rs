@@ -1,13 +1,18 @@
-use std::{collections::HashMap, fs::File};
+use std::{fs::File, path::Path, sync::{Arc, Mutex}};
 
-use hecs::{CommandBuffer, Entity, World};
-use log::{info, warn};
-use rand::rngs::ThreadRng;
-use sdl2::controller::{Axis, Button, GameController};
+use hecs::{Entity, World};
+use log::{error, info, warn};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use crate::{asset_loader::{load_effect, load_model}, bsp::{bspcommon::aabb_aabb_intersects, bspfile::BspFile, bsplightmap::BspLightmap, bsprenderer::{BspMapModelRenderer, BspMapRenderer, BspMapTextures}}, component::{basicanim::{AnimationLoopMode, BasicLerpAnim}, camera::{Camera, FPCamera}, charactercontroller::CharacterController, door::{Door, DoorLink, DoorOpener}, effect::Effect, fpview::FPView, light::Light, mapmodel::MapModel, meshpose::MeshPose, playerinput::PlayerInput, rendermesh::{RenderMesh, SkinnedMesh}, rotator::Rotator, transform3d::Transform3D, triggerable::{TriggerLink, TriggerState}}, math::{Quaternion, Vector3}, misc::AABB, parse_utils, system::{anim_system::{basic_animation_system, compute_pose_transforms}, ccmd_system::ConsoleCommandSystem, character_system::{character_apply_input_update, character_init, character_input_update, character_rotation_update, character_update}, door_system::door_system_update, effect_system::effect_system, flycam_system::flycam_system_update, fpcam_system::fpcam_update, fpview_system::{fpview_eye_update, fpview_input_system_update}, render_system::{render_system, skinning_system, NUM_CUSTOM_LIGHT_LAYERS}, rotator_system::rotator_system_update, triggerable_system::trigger_link_system_update}};
+use crate::{asset_loader::{load_effect, load_model}, audio::{mixer::Mixer, output::OUTPUT_SAMPLE_RATE}, bsp::{bspfile::{BspError, BspFile}, bsplightmap::BspLightmap, bsprenderer::{BspMapModelRenderer, BspMapRenderer, BspMapTextures}}, component::{basicanim::{AnimationLoopMode, BasicLerpAnim}, camera::{Camera, FPCamera}, charactercontroller::CharacterController, door::DoorOpener, effect::Effect, fpview::FPView, health::Health, light::Light, meshpose::MeshPose, playerinput::PlayerInput, rendermesh::{RenderMesh, SkinnedMesh}, rotator::Rotator, spawnindex::SpawnIndex, transform3d::Transform3D}, cvar::get_cvar, demo::{DemoHeader, DemoPlayer, DemoRecorder}, inspector::pick_entity, math::{Matrix4x4, Vector3}, savegame::{capture_world, read_save, restore_world, write_save}, lightstyle::reset_lightstyles, spawn::spawn_registry::spawn_map_entities, system::{anim_system::{basic_animation_system, compute_pose_transforms}, audio_system::audio_system_update, ccmd_system::{ConsoleCommandSystem, GameCommand}, character_system::{character_apply_input_update, character_init, character_input_update, character_rotation_update, character_update, character_water_update}, door_system::door_system_update, effect_system::effect_system, flycam_system::flycam_system_update, fpcam_system::fpcam_update, interpolation_system::prev_transform_update, thirdpersoncam_system::thirdperson_cam_update, fpview_system::{fpview_eye_update, fpview_input_system_update}, lightstyle_system::lightstyle_system_update, mover_system::{plat_system_update, train_system_update}, render_system::{render_system, skinning_system, NUM_CUSTOM_LIGHT_LAYERS}, rotator_system::rotator_system_update, splitscreen_system::splitscreen_viewport_update, triggerable_system::{trigger_link_system_update, trigger_volume_system_update}}};
 
-#[derive(Default)]
+/// Maximum number of local split-screen players
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+
+/// Per-tick player input. Stick deadzones are applied before input reaches the game state, and look values are
+/// rates relative to full speed (mouse look may exceed 1.0)
+#[derive(Default, Clone, Copy)]
 pub struct InputState {
     pub move_x: f32,
     pub move_y: f32,
@@ -17,12 +22,25 @@ pub struct InputState {
     pub jump: bool,
 }
 
-pub struct MapData {
-    pub map: BspFile,
+impl InputState {
+    /// Input for the given local player, or no input if none was provided for that player
+    pub fn for_player(inputs: &[InputState], player_index: usize) -> InputState {
+        inputs.get(player_index).copied().unwrap_or_default()
+    }
+}
+
+/// GL resources used to render a map
+pub struct MapRenderData {
     pub map_textures: BspMapTextures,
     pub map_lightmap: BspLightmap,
     pub map_model_renderer: BspMapModelRenderer,
     pub map_renderers: Vec<BspMapRenderer>,
+}
+
+pub struct MapData {
+    pub map: BspFile,
+    /// Rendering resources for the map, or None when running headless
+    pub render_data: Option<MapRenderData>,
     pub light_layers: [f32;NUM_CUSTOM_LIGHT_LAYERS],
 }
 
@@ -32,7 +50,7 @@ pub struct WindowData {
     pub height: i32
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub struct TimeData {
     pub delta_time: f32,
     pub total_time: f32
@@ -44,392 +62,474 @@ pub struct GameState {
     test_model: Entity,
     test_fx: Entity,
     map_data: Option<MapData>,
-    rng: ThreadRng,
+    map_name: String,
+    rng: StdRng,
     console_command_system: ConsoleCommandSystem,
+    demo_recorder: Option<DemoRecorder>,
+    demo_player: Option<DemoPlayer>,
+    mixer: Arc<Mutex<Mixer>>,
+    num_players: usize,
+    headless: bool,
 }
 
 impl MapData {
-    pub fn load_map(map_name: &str) -> MapData {
+    pub fn map_path(map_name: &str) -> String {
+        format!("content/maps/{}.bsp", map_name)
+    }
+
+    /// Load a map, along with its rendering resources unless headless is set
+    pub fn load_map(map_name: &str, headless: bool) -> Result<MapData, BspError> {
         info!("Loading map: {}", map_name);
 
-        let mut bsp_file = File::open(format!("content/maps/{}.bsp", map_name).as_str()).unwrap();
-        let bsp = BspFile::new(&mut bsp_file);
+        let mut bsp_file = File::open(Self::map_path(map_name))?;
+        let bsp = BspFile::load(&mut bsp_file)?;
         info!("BSP DATA LOADED");
-        let bsp_textures = BspMapTextures::new(&bsp);
-        info!("BSP TEXTURES LOADED");
-        let bsp_lightmap = BspLightmap::new(&bsp);
-        info!("LIGHTMAP ATLAS CREATED");
-        let bsp_map_model_renderer = BspMapModelRenderer::new(&bsp, &bsp_textures, &bsp_lightmap);
-        info!("MAP MODEL RENDERER CREATED");
+
+        let render_data = if headless {
+            None
+        }
+        else {
+            Some(MapRenderData::new(&bsp))
+        };
 
         info!("Map loaded");
 
-        MapData {
+        Ok(MapData {
             map: bsp,
+            render_data,
+            light_layers: [0.0;NUM_CUSTOM_LIGHT_LAYERS]
+        })
+    }
+
+    /// Whether this map was loaded without any rendering resources
+    pub fn is_headless(self: &Self) -> bool {
+        self.render_data.is_none()
+    }
+}
+
+impl MapRenderData {
+    pub fn new(bsp: &BspFile) -> MapRenderData {
+        let bsp_textures = BspMapTextures::new(bsp);
+        info!("BSP TEXTURES LOADED");
+        let bsp_lightmap = BspLightmap::new(bsp);
+        info!("LIGHTMAP ATLAS CREATED");
+        let bsp_map_model_renderer = BspMapModelRenderer::new(bsp, &bsp_textures, &bsp_lightmap);
+        info!("MAP MODEL RENDERER CREATED");
+
+        MapRenderData {
             map_textures: bsp_textures,
-            map_renderers: Vec::new(),
             map_lightmap: bsp_lightmap,
             map_model_renderer: bsp_map_model_renderer,
-            light_layers: [0.0;NUM_CUSTOM_LIGHT_LAYERS]
+            map_renderers: Vec::new(),
         }
     }
 
-    pub fn update_renderer_cache(self: &mut Self, index: usize) {
+    pub fn update_renderer_cache(self: &mut Self, bsp: &BspFile, index: usize) {
         while self.map_renderers.len() <= index {
             info!("Allocating map renderer for camera {}", index);
-            self.map_renderers.push(BspMapRenderer::new(&self.map));
+            self.map_renderers.push(BspMapRenderer::new(bsp));
         }
     }
 }
 
+/// Number of local players to spawn when a map is loaded, from cl_splitscreen
+pub fn local_player_count() -> usize {
+    get_cvar::<i32>("cl_splitscreen").clamp(1, MAX_LOCAL_PLAYERS as i32) as usize
+}
+
 impl GameState {
-    pub fn new() -> GameState {
-        let mut world = World::new();
+    /// Create the game state with no map loaded. Headless game states never create any GL resources & cannot be rendered
+    pub fn new(headless: bool) -> GameState {
+        GameState {
+            world: World::new(),
+            time_data: TimeData::default(),
+            map_data: None,
+            map_name: String::new(),
+            test_model: Entity::DANGLING,
+            test_fx: Entity::DANGLING,
+            rng: StdRng::from_os_rng(),
+            console_command_system: ConsoleCommandSystem::new(),
+            demo_recorder: None,
+            demo_player: None,
+            mixer: Arc::new(Mutex::new(Mixer::new(OUTPUT_SAMPLE_RATE as u32))),
+            num_players: 1,
+            headless,
+        }
+    }
 
-        let map_data = MapData::load_map("e1m1");
+    /// Name of the currently loaded map, or an empty string if no map has been loaded yet
+    pub fn map_name(self: &Self) -> &str {
+        &self.map_name
+    }
 
-        let mut player_start_pos = Vector3::zero();
-        let mut player_start_rot = 0.0;
+    /// Number of local players in the current map
+    pub fn num_players(self: &Self) -> usize {
+        self.num_players
+    }
 
-        let mut targetmap = HashMap::new();
-        let mut pending_resolve_targets = Vec::new();
+    /// Unloads the current map & all world entities, then loads the given map and respawns its entities & the players.
+    /// The number of players is taken from cl_splitscreen. If the map fails to load, the current level keeps running & false is returned
+    pub fn changelevel(self: &mut Self, map_name: &str) -> bool {
+        self.load_level(map_name, local_player_count())
+    }
 
-        let mut doors = Vec::new();
+    // load a map & spawn the given number of players into it, returning false if the map failed to load (in which case the current level is left untouched)
+    fn load_level(self: &mut Self, map_name: &str, num_players: usize) -> bool {
+        if !Path::new(&MapData::map_path(map_name)).exists() {
+            error!("Failed changing level: map {} does not exist", map_name);
+            return false;
+        }
 
-        // spawn entities
-        map_data.map.entity_lump.parse(|entity_data| {
-            match entity_data["classname"] {
-                "info_player_start" => {
-                    player_start_pos = parse_utils::parse_prop_vec3(&entity_data, "origin", Vector3::zero());
-                    player_start_rot = parse_utils::parse_prop::<f32>(&entity_data, "angle", 0.0) + 180.0;
-                }
-                "worldspawn" => {
-                    for (key, val) in entity_data {
-                        info!("worldspawn: {} = {}", key, val);
-                    }
-                }
-                "prop_dynamic" => {
-                    let pos = parse_utils::parse_prop_vec3(&entity_data, "origin", Vector3::zero());
-                    let angles = parse_utils::parse_prop_vec3(&entity_data, "angles", Vector3::zero());
-                    let model_path = parse_utils::get_prop_str(&entity_data, "model", "");
-                    let scale = parse_utils::parse_prop_vec3(&entity_data, "scale", Vector3::new(1.0, 1.0, 1.0));
-
-                    let rot = Quaternion::from_euler(Vector3::new(angles.x.to_radians(), angles.z.to_radians(), angles.y.to_radians()));
-                    let model = load_model(format!("content/{}", model_path).as_str()).unwrap();
-
-                    world.spawn((
-                        Transform3D::default().with_position(pos).with_rotation(rot).with_scale(scale),
-                        RenderMesh::new(model),
-                    ));
-                }
-                "env_effect" => {
-                    let pos = parse_utils::parse_prop_vec3(&entity_data, "origin", Vector3::zero());
-                    let angles = parse_utils::parse_prop_vec3(&entity_data, "angles", Vector3::zero());
-                    let effect_path = parse_utils::get_prop_str(&entity_data, "effect", "");
-                    let scale = parse_utils::parse_prop_vec3(&entity_data, "scale", Vector3::new(1.0, 1.0, 1.0));
-                    let world_space = parse_utils::parse_prop::<i32>(&entity_data, "worldspace", 1) != 0;
-
-                    let rot = Quaternion::from_euler(Vector3::new(angles.x.to_radians(), angles.z.to_radians(), angles.y.to_radians()));
-                    let effect = load_effect(format!("content/{}", effect_path).as_str()).unwrap();
-
-                    world.spawn((
-                        Transform3D::default().with_position(pos).with_rotation(rot).with_scale(scale),
-                        Effect::new(&effect, true, world_space),
-                    ));
-                }
-                "light" => {
-                    let light_pos = parse_utils::parse_prop_vec3(&entity_data, "origin", Vector3::zero());
-                    let light_intensity = parse_utils::parse_prop::<f32>(&entity_data, "light", 300.0);
-                    let light_color = parse_utils::parse_prop_vec3(&entity_data, "_color", Vector3::new(1.0, 1.0, 1.0));
-
-                    world.spawn((
-                        Transform3D::default().with_position(light_pos),
-                        Light { color: light_color, max_radius: light_intensity }
-                    ));
-                }
-                "func_door" => {
-                    let model_idx = parse_utils::parse_prop_modelindex(&entity_data, "model", usize::MAX);
-                    let submodel = &map_data.map.submodel_lump.submodels[model_idx + 1];
-                    let pos = submodel.origin;
-                    let size = submodel.maxs - submodel.mins;
-
-                    let target_name = parse_utils::get_prop_str(&entity_data, "targetname", "");
-                    let target = parse_utils::get_prop_str(&entity_data, "target", "");
-
-                    let auto_open = target_name == "";
-
-                    let angle = parse_utils::parse_prop::<i32>(&entity_data, "angle", 0);
-                    let speed = parse_utils::parse_prop::<f32>(&entity_data, "speed", 100.0);
-                    let lip = parse_utils::parse_prop::<f32>(&entity_data, "lip", 0.0);
-
-                    let spawn_flags = parse_utils::parse_prop::<u32>(&entity_data, "spawnflags", 0);
-
-                    let move_dir = if angle == -1 {
-                        Vector3::new(0.0, 0.0, 1.0)
-                    }
-                    else if angle == -2 {
-                        Vector3::new(0.0, 0.0, -1.0)
-                    }
-                    else {
-                        let r = (angle as f32).to_radians();
-                        let sx = r.cos();
-                        let sy = r.sin();
-
-                        Vector3::new(sx, sy, 0.0)
-                    };
-
-                    // calculate move distance along direction
-                    let move_dist = (move_dir.x.abs() * size.x +
-                        move_dir.y.abs() * size.y +
-                        move_dir.z.abs() * size.z) - lip;
-
-                    let open_pos = pos + (move_dir * move_dist);
-
-                    let e = world.spawn((
-                        Transform3D::default().with_position(pos),
-                        Door { auto_open, open_pos, close_pos: pos, move_speed: speed },
-                        TriggerState { triggered: false },
-                        MapModel { model_idx }
-                    ));
-
-                    if target != "" {
-                        pending_resolve_targets.push((e, target.to_owned()));
-                    }
-
-                    if target_name != "" {
-                        targetmap.insert(target_name.to_owned(), e);
-                    }
-
-                    // don't link doors if they have the "don't link" spawn flag set
-                    if spawn_flags & 4 == 0 {
-                        doors.push((e, submodel));
-                    }
-                }
-                "func_explosive" => {
-                    let model_idx = parse_utils::parse_prop_modelindex(&entity_data, "model", usize::MAX);
-                    let submodel = &map_data.map.submodel_lump.submodels[model_idx + 1];
-                    let pos = submodel.origin;
-                    
-                    world.spawn((
-                        Transform3D::default().with_position(pos),
-                        MapModel { model_idx }
-                    ));
-                }
-                "func_wall" => {
-                    let model_idx = parse_utils::parse_prop_modelindex(&entity_data, "model", usize::MAX);
-                    let submodel = &map_data.map.submodel_lump.submodels[model_idx + 1];
-                    let pos = submodel.origin;
-                    
-                    world.spawn((
-                        Transform3D::default().with_position(pos),
-                        MapModel { model_idx }
-                    ));
-                }
-                "func_object" => {
-                    let model_idx = parse_utils::parse_prop_modelindex(&entity_data, "model", usize::MAX);
-                    let submodel = &map_data.map.submodel_lump.submodels[model_idx + 1];
-                    let pos = submodel.origin;
-                    
-                    world.spawn((
-                        Transform3D::default().with_position(pos),
-                        MapModel { model_idx }
-                    ));
-                }
-                "func_plat" => {
-                    let model_idx = parse_utils::parse_prop_modelindex(&entity_data, "model", usize::MAX);
-                    let submodel = &map_data.map.submodel_lump.submodels[model_idx + 1];
-                    let pos = submodel.origin;
-                    
-                    world.spawn((
-                        Transform3D::default().with_position(pos),
-                        MapModel { model_idx }
-                    ));
-                }
-                "func_rotating" => {
-                    let model_idx = parse_utils::parse_prop_modelindex(&entity_data, "model", usize::MAX);
-                    let submodel = &map_data.map.submodel_lump.submodels[model_idx + 1];
-                    let spawn_flags = parse_utils::parse_prop::<u32>(&entity_data, "spawnflags", 0);
-                    let pos = parse_utils::parse_prop_vec3(&entity_data, "origin", submodel.origin);
-                    let speed = parse_utils::parse_prop::<f32>(&entity_data, "speed", 0.0);
-
-                    let axis = if spawn_flags & 4 != 0 {
-                        Vector3::unit_x()
-                    }
-                    else if spawn_flags & 8 != 0 {
-                        Vector3::unit_y()
-                    }
-                    else {
-                        Vector3::unit_z()
-                    };
-                    
-                    world.spawn((
-                        Transform3D::default().with_position(pos),
-                        Rotator { rot_axis: axis, rot_speed: speed },
-                        MapModel { model_idx }
-                    ));
-                }
-                "func_train" => {
-                    let model_idx = parse_utils::parse_prop_modelindex(&entity_data, "model", usize::MAX);
-                    let submodel = &map_data.map.submodel_lump.submodels[model_idx + 1];
-                    let pos = submodel.origin;
-                    
-                    world.spawn((
-                        Transform3D::default().with_position(pos),
-                        MapModel { model_idx }
-                    ));
-                }
-                _ => {
-                }
-            }
-        });
+        info!("Changing level: {}", map_name);
 
-        // resolve triggerable entity targets
-        let mut cmd_buf = CommandBuffer::new();
-        for (e, targetname) in pending_resolve_targets {
-            if !targetmap.contains_key(&targetname) {
-                warn!("Couldn't find trigger target: {}", &targetname);
+        // load the new map before touching the current one, so that if it fails to load the current level keeps running
+        let map_data = match MapData::load_map(map_name, self.headless) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed loading map: {}", e);
+                return false;
             }
-            else {
-                let target_ent = targetmap[&targetname];
-                cmd_buf.insert_one(e, TriggerLink {
-                    target: target_ent
-                });
-            }
-        }
-        cmd_buf.run_on(&mut world);
-
-        // link doors together if they are touching
-        let mut pending_door_links = Vec::new();
-        for (e, doormodel) in &doors {
-            let mut links = Vec::new();
-            for (e2, doormodel2) in &doors {
-                if e2 != e && aabb_aabb_intersects(&AABB::min_max(doormodel.mins, doormodel.maxs), &AABB::min_max(doormodel2.mins, doormodel2.maxs)) {
-                    links.push(*e2);
-                }
-            }
-            pending_door_links.push((e, links));
-        }
+        };
 
-        for (e, links) in pending_door_links {
-            cmd_buf.insert_one(*e, DoorLink {
-                links
-            });
-        }
+        // demos can't span level changes
+        self.stop_demo();
 
-        cmd_buf.run_on(&mut world);
+        // despawn all entities & release the previous map's resources
+        // (a fresh world rather than clearing the old one, so that entity IDs are allocated in spawn order)
+        self.mixer.lock().unwrap().stop_all();
+        self.world = World::new();
+        self.map_data = None;
+        self.map_name = map_name.to_owned();
 
-        // player & camera
-        let player_entity = world.spawn((
-            Transform3D::default().with_position(player_start_pos),
-            FPView::new(-player_start_rot, 0.0, 40.0),
-            CharacterController::default(),
-            PlayerInput::new(),
-            DoorOpener {},
-            Light { max_radius: 200.0, color: Vector3::new(1.0, 1.0, 1.0) }
-        ));
+        // lightstyle overrides only last until the map changes
+        reset_lightstyles();
 
-        world.spawn((
-            Transform3D::default(),
-            Camera::default(),
-            FPCamera::new(player_entity)
-        ));
+        let spawn_points = spawn_map_entities(&mut self.world, &map_data);
+        self.map_data = Some(map_data);
+
+        if !spawn_points.is_empty() && spawn_points.len() < num_players {
+            warn!("Map only has {} player spawn points for {} players, some players will share a spawn point", spawn_points.len(), num_players);
+        }
+
+        // players & their cameras
+        self.num_players = num_players;
+
+        for player_index in 0..num_players {
+            let (player_start_pos, player_start_rot) = match spawn_points.len() {
+                0 => (Vector3::zero(), 0.0),
+                n => spawn_points[player_index % n]
+            };
+
+            let player_entity = self.world.spawn((
+                Transform3D::default().with_position(player_start_pos),
+                FPView::new(-player_start_rot, 0.0, 40.0),
+                CharacterController::default(),
+                PlayerInput::new(player_index),
+                DoorOpener {},
+                Health::new(100.0),
+                Light { max_radius: 200.0, color: Vector3::new(1.0, 1.0, 1.0) }
+            ));
+
+            self.world.spawn((
+                Transform3D::default(),
+                Camera::default().with_player_index(player_index),
+                FPCamera::new(player_entity)
+            ));
+        }
 
         // test static model entity
-        let dragon_mesh = load_model("content/models/dragon-2_80.glb").unwrap();
-        let test_model = world.spawn((
+        self.test_model = self.world.spawn((
             Transform3D::default().with_position(Vector3::new(0.0, 0.0, 50.0)).with_scale(Vector3::new(100.0, 100.0, 100.0)),
-            RenderMesh::new(dragon_mesh.clone()),
-            MeshPose::init(&dragon_mesh),
-            SkinnedMesh::new(&dragon_mesh),
-            // BasicAnim::new(dragon_mesh.get_animation_id("walk").unwrap(), AnimationLoopMode::Wrap),
-            BasicLerpAnim::new(dragon_mesh.get_animation_id("idle").unwrap(), dragon_mesh.get_animation_id("walk").unwrap(), AnimationLoopMode::Wrap),
-            Rotator { rot_axis: Vector3::unit_z(), rot_speed: 45.0_f32.to_radians() }
+            Rotator::new(Vector3::unit_z(), 45.0_f32.to_radians())
         ));
 
         // test particle system
-        let particle_sys = load_effect("content/effects/fire.fx.ron").unwrap();
-        let test_fx = world.spawn((
+        self.test_fx = self.world.spawn((
             Transform3D::default().with_position(Vector3::new(0.0, 0.0, 50.0)),
-            Effect::new(&particle_sys, true, true)
         ));
 
-        GameState {
-            world,
-            time_data: TimeData::default(),
-            map_data: Some(map_data),
-            test_model,
-            test_fx,
-            rng: rand::rng(),
-            console_command_system: ConsoleCommandSystem::new(),
+        // the test entities are spawned the same way when running headless, only their GL resources are left out
+        if !self.headless {
+            let dragon_mesh = load_model("content/models/dragon-2_80.glb").unwrap();
+            self.world.insert(self.test_model, (
+                RenderMesh::new(dragon_mesh.clone()),
+                MeshPose::init(&dragon_mesh),
+                SkinnedMesh::new(&dragon_mesh),
+                // BasicAnim::new(dragon_mesh.get_animation_id("walk").unwrap(), AnimationLoopMode::Wrap),
+                BasicLerpAnim::new(dragon_mesh.get_animation_id("idle").unwrap(), dragon_mesh.get_animation_id("walk").unwrap(), AnimationLoopMode::Wrap),
+            )).unwrap();
+
+            let particle_sys = load_effect("content/effects/fire.fx.ron").unwrap();
+            self.world.insert_one(self.test_fx, Effect::new(&particle_sys, true, true)).unwrap();
+        }
+
+        // tag everything spawned by the level with the order it was spawned in, so saves can find the same entities again after reloading the map
+        let mut spawned = self.world.iter().map(|x| x.entity()).collect::<Vec<_>>();
+        spawned.sort_by_key(|x| x.id());
+
+        for (index, e) in spawned.into_iter().enumerate() {
+            self.world.insert_one(e, SpawnIndex { index: index as u32 }).unwrap();
+        }
+
+        true
+    }
+
+    /// Save the current game state to the given slot
+    pub fn save_game(self: &mut Self, slot: &str) {
+        if self.map_data.is_none() {
+            error!("Failed saving game: no map loaded");
+            return;
+        }
+
+        let save = capture_world(&self.world, &self.map_name, &self.time_data, self.num_players);
+
+        match write_save(slot, &save) {
+            Ok(_) => {
+                info!("Saved game: {}", slot);
+            }
+            Err(e) => {
+                error!("Failed saving game: {}", e);
+            }
+        }
+    }
+
+    /// Reload the map stored in the given save slot, then restore the saved entity state on top of it
+    pub fn load_game(self: &mut Self, slot: &str) {
+        let save = match read_save(slot) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed loading game: {}", e);
+                return;
+            }
+        };
+
+        if !self.load_level(&save.map_name, save.num_players.clamp(1, MAX_LOCAL_PLAYERS)) {
+            error!("Failed loading game: could not load map {}", save.map_name);
+            return;
+        }
+
+        restore_world(&save, &mut self.world);
+        self.time_data = save.time_data;
+
+        info!("Loaded game: {}", slot);
+    }
+
+    // restart the given map with a known RNG seed & clock, so that demos play back identically to how they were recorded
+    fn restart_for_demo(self: &mut Self, map_name: &str, seed: u64, num_players: usize) -> bool {
+        if !self.load_level(map_name, num_players) {
+            return false;
+        }
+
+        self.rng = StdRng::seed_from_u64(seed);
+        self.time_data = TimeData::default();
+        true
+    }
+
+    /// Restart the current map & begin recording input to the given demo
+    pub fn record_demo(self: &mut Self, name: &str) {
+        let header = DemoHeader {
+            map_name: self.map_name.clone(),
+            seed: rand::random(),
+            num_players: local_player_count(),
+        };
+
+        if !self.restart_for_demo(&header.map_name, header.seed, header.num_players) {
+            error!("Failed recording demo: could not restart map {}", header.map_name);
+            return;
+        }
+
+        match DemoRecorder::create(name, &header) {
+            Ok(v) => {
+                info!("Recording demo: {}", name);
+                self.demo_recorder = Some(v);
+            }
+            Err(e) => {
+                error!("Failed recording demo: {}", e);
+            }
         }
     }
 
-    pub fn tick(self: &mut Self, delta: f32, gamepad: Option<&GameController>) {
-        let mut input_state = InputState {
-            move_x: 0.0,
-            move_y: 0.0,
-            look_x: 0.0,
-            look_y: 0.0,
-            crouch: false,
-            jump: false
+    /// Load the map a demo was recorded on & begin playing it back
+    pub fn play_demo(self: &mut Self, name: &str) {
+        let player = match DemoPlayer::open(name) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed playing demo: {}", e);
+                return;
+            }
         };
 
-        if let Some(gp) = gamepad {
-            input_state.move_x = gp.axis(Axis::LeftX) as f32 / 32767.0;
-            input_state.move_y = gp.axis(Axis::LeftY) as f32 / -32767.0;
-            input_state.look_x = gp.axis(Axis::RightX) as f32 / 32767.0;
-            input_state.look_y = gp.axis(Axis::RightY) as f32 / -32767.0;
-            input_state.jump = gp.button(Button::A);
-            input_state.crouch = gp.button(Button::B);
+        if !self.restart_for_demo(&player.header.map_name, player.header.seed, player.header.num_players) {
+            error!("Failed playing demo: could not load map {}", player.header.map_name);
+            return;
+        }
+
+        info!("Playing demo: {} ({} frames)", name, player.num_frames());
+        self.demo_player = Some(player);
+    }
+
+    /// Stop recording or playing back the current demo, if any
+    pub fn stop_demo(self: &mut Self) {
+        if let Some(recorder) = self.demo_recorder.take() {
+            match recorder.finish() {
+                Ok(num_frames) => {
+                    info!("Finished recording demo ({} frames)", num_frames);
+                }
+                Err(e) => {
+                    error!("Failed writing demo: {}", e);
+                }
+            }
+        }
+
+        if self.demo_player.take().is_some() {
+            info!("Stopped demo playback");
+        }
+    }
+
+    /// Advance the simulation by one tick, given each local player's input (indexed by player index)
+    pub fn tick(self: &mut Self, delta: f32, inputs: Vec<InputState>) {
+        let mut inputs = inputs;
+
+        // demo playback overrides live input
+        if let Some(player) = &mut self.demo_player {
+            match player.next_frame() {
+                Some(frame) => {
+                    inputs = frame;
+                }
+                None => {
+                    self.stop_demo();
+                }
+            }
+        }
+
+        if let Some(recorder) = &mut self.demo_recorder {
+            if let Err(e) = recorder.write_frame(&inputs) {
+                error!("Failed writing demo frame: {}", e);
+                self.stop_demo();
+            }
         }
 
         // update time
         self.time_data.delta_time = delta;
         self.time_data.total_time += delta;
 
-        {
-            let mut test_model_transform = self.world.get::<&mut Transform3D>(self.test_model).unwrap();
-            test_model_transform.position = Vector3::new((self.time_data.total_time * 0.1).sin() * 150.0, (self.time_data.total_time * 0.25).sin() * 150.0, 50.0);
+        if !self.headless {
+            // remember where everything was at the start of this tick, so rendering can interpolate towards the new transforms
+            prev_transform_update(&mut self.world);
+        }
 
-            let mut test_fx_transform = self.world.get::<&mut Transform3D>(self.test_fx).unwrap();
-            test_fx_transform.position = test_model_transform.position;
-            test_fx_transform.rotation = test_model_transform.rotation;
+        // the test entities only exist once a map has been loaded
+        if self.map_data.is_some() {
+            if let Ok(mut test_model_transform) = self.world.get::<&mut Transform3D>(self.test_model) {
+                test_model_transform.position = Vector3::new((self.time_data.total_time * 0.1).sin() * 150.0, (self.time_data.total_time * 0.25).sin() * 150.0, 50.0);
 
-            let mut test_model_anim = self.world.get::<&mut BasicLerpAnim>(self.test_model).unwrap();
-            test_model_anim.mix = (self.time_data.total_time * 0.5).sin() * 0.5 + 0.5;
+                if let Ok(mut test_fx_transform) = self.world.get::<&mut Transform3D>(self.test_fx) {
+                    test_fx_transform.position = test_model_transform.position;
+                    test_fx_transform.rotation = test_model_transform.rotation;
+                }
+            }
+
+            // the headless test model has no animation
+            if let Ok(mut test_model_anim) = self.world.get::<&mut BasicLerpAnim>(self.test_model) {
+                test_model_anim.mix = (self.time_data.total_time * 0.5).sin() * 0.5 + 0.5;
+            }
         }
 
         // update
         if let Some(map_data) = &mut self.map_data {
-            rotator_system_update(&self.time_data, &mut self.world);
+            rotator_system_update(&self.time_data, map_data, &mut self.world);
+            lightstyle_system_update(&self.time_data, map_data, &mut self.world);
             door_system_update(&self.time_data, map_data, &mut self.world);
-            trigger_link_system_update(&mut self.world);
-            fpview_input_system_update(&input_state, &self.time_data, &mut self.world);
+            plat_system_update(&self.time_data, map_data, &mut self.world);
+            train_system_update(&self.time_data, map_data, &mut self.world);
+            trigger_volume_system_update(&self.time_data, &mut self.world);
+            trigger_link_system_update(&self.time_data, &mut self.world);
+            fpview_input_system_update(&inputs, &self.time_data, &mut self.world);
             character_init(&mut self.world);
             character_rotation_update(&mut self.world);
-            character_input_update(&input_state, &mut self.world);
+            character_input_update(&inputs, &mut self.world);
             fpview_eye_update(&self.time_data, &mut self.world);
+            character_water_update(&self.time_data, map_data, &mut self.world);
             character_apply_input_update(&self.time_data, map_data, &mut self.world);
             character_update(&self.time_data, map_data, &mut self.world);
-            flycam_system_update(&input_state, &self.time_data, &map_data.map, &mut self.world);
+            flycam_system_update(&inputs, &self.time_data, &map_data.map, &mut self.world);
             fpcam_update(&mut self.world);
+            thirdperson_cam_update(&self.time_data, &map_data.map, &mut self.world);
 
             basic_animation_system(&self.time_data, &mut self.world);
-            compute_pose_transforms(&mut self.world);
-            skinning_system(&mut self.world);
+
+            if !self.headless {
+                compute_pose_transforms(&mut self.world);
+                skinning_system(&mut self.world);
+            }
 
             effect_system(&self.time_data, &mut self.rng, &mut self.world);
+            audio_system_update(map_data, &mut self.mixer.lock().unwrap(), &mut self.world);
         }
     }
 
-    pub fn render(self: &mut Self, window_data: WindowData) {
+    /// Mixer which sound sources are played through. Shared with the audio device when running windowed
+    pub fn mixer(self: &Self) -> &Arc<Mutex<Mixer>> {
+        &self.mixer
+    }
+
+    pub fn world_mut(self: &mut Self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Find the entity under the given point in the window (in pixels from the top left), as seen by the first camera whose viewport contains the point
+    pub fn pick_entity(self: &Self, x: f32, y: f32, window_data: &WindowData) -> Option<Entity> {
+        let map_data = self.map_data.as_ref()?;
+        let mut camera_query = self.world.query::<(&Transform3D, &Camera)>();
+        let (transform, camera, (vp_x, vp_y, vp_w, vp_h)) = camera_query.iter()
+            .map(|(_, (transform, camera))| {
+                let viewport = match camera.viewport_rect {
+                    // note: viewport rects are in GL coordinates, with Y pointing up from the bottom of the window
+                    Some(v) => (v.x as f32, (window_data.height - v.y - v.h) as f32, v.w as f32, v.h as f32),
+                    None => (0.0, 0.0, window_data.width as f32, window_data.height as f32)
+                };
+
+                (transform, camera, viewport)
+            })
+            .find(|(_, _, (vp_x, vp_y, vp_w, vp_h))| x >= *vp_x && x < vp_x + vp_w && y >= *vp_y && y < vp_y + vp_h)?;
+
+        // build ray through the point in camera space (+X right, +Y forward, +Z up), then rotate into world space
+        let ndc_x = ((x - vp_x) / vp_w) * 2.0 - 1.0;
+        let ndc_y = 1.0 - ((y - vp_y) / vp_h) * 2.0;
+        let tan_half_fov = (camera.fov.to_radians() * 0.5).tan();
+
+        let dir = Vector3::new(ndc_x * tan_half_fov * (vp_w / vp_h), 1.0, ndc_y * tan_half_fov);
+        let dir = Matrix4x4::rotation(transform.rotation).transform_direction(dir);
+
+        pick_entity(&map_data.map, &self.world, transform.position, transform.position + (dir * camera.far))
+    }
+
+    /// Whether a demo is currently being played back
+    pub fn is_playing_demo(self: &Self) -> bool {
+        self.demo_player.is_some()
+    }
+
+    /// Render the world. Alpha is the fraction of a tick which has elapsed since the last tick, used to interpolate moving entities
+    pub fn render(self: &mut Self, window_data: WindowData, alpha: f32) {
         // render
         if let Some(map_data) = &mut self.map_data {
-            render_system(&self.time_data, &window_data, map_data, &mut self.world);
+            splitscreen_viewport_update(&window_data, self.num_players, &mut self.world);
+            render_system(&self.time_data, alpha, &window_data, map_data, &mut self.world);
         }
     }
 
     pub fn exec_commands<I>(self: &mut Self, commands: I) where I : Iterator::<Item = String> {
         self.console_command_system.exec_commands(commands, &mut self.world);
+
+        for cmd in self.console_command_system.take_game_commands() {
+            match cmd {
+                GameCommand::ChangeLevel(map_name) => { self.changelevel(&map_name); }
+                GameCommand::Save(slot) => self.save_game(&slot),
+                GameCommand::Load(slot) => self.load_game(&slot),
+                GameCommand::RecordDemo(name) => self.record_demo(&name),
+                GameCommand::PlayDemo(name) => self.play_demo(&name),
+                GameCommand::StopDemo => self.stop_demo(),
+            }
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use crate::{math::{Vector3, Vector4}, misc::AABB};
+
+use super::shader::Shader;
+
+/// Maximum number of dynamic lights which can affect a single draw call
+pub const MAX_DYNAMIC_LIGHTS: usize = 4;
+
+// note: uniforms are unrolled rather than declared as arrays, as some GLES2 targets don't support indexing uniform arrays in loops
+const LIGHT_POS_UNIFORMS: [&str;MAX_DYNAMIC_LIGHTS] = ["dlightPos0", "dlightPos1", "dlightPos2", "dlightPos3"];
+const LIGHT_COLOR_UNIFORMS: [&str;MAX_DYNAMIC_LIGHTS] = ["dlightColor0", "dlightColor1", "dlightColor2", "dlightColor3"];
+
+/// A point light in world space which has passed visibility culling for the current camera
+#[derive(Clone, Copy, Default)]
+pub struct DynamicLight {
+    pub position: Vector3,
+    pub color: Vector3,
+    pub radius: f32,
+}
+
+/// The set of dynamic lights which affect a single mesh or map surface
+#[derive(Clone, Copy, Default)]
+pub struct DynamicLightSet {
+    lights: [DynamicLight;MAX_DYNAMIC_LIGHTS],
+    /// Index of each selected light in the slice it was selected from
+    indices: [usize;MAX_DYNAMIC_LIGHTS],
+    count: usize,
+}
+
+impl DynamicLight {
+    pub fn bounds(self: &Self) -> AABB {
+        AABB::center_extents(self.position, Vector3::new(self.radius, self.radius, self.radius))
+    }
+
+    // squared distance from the light to the closest point on the given bounds
+    fn distance_sq_to(self: &Self, bounds: &AABB) -> f32 {
+        let min = bounds.min();
+        let max = bounds.max();
+
+        let closest = Vector3::new(
+            self.position.x.clamp(min.x, max.x),
+            self.position.y.clamp(min.y, max.y),
+            self.position.z.clamp(min.z, max.z),
+        );
+
+        Vector3::distance_sq(&self.position, &closest)
+    }
+}
+
+impl DynamicLightSet {
+    /// Pick up to max_lights of the lights nearest to the given bounds, skipping any whose radius doesn't reach it
+    pub fn select(lights: &[DynamicLight], bounds: &AABB, max_lights: usize) -> DynamicLightSet {
+        let mut result = DynamicLightSet::default();
+        let max_lights = max_lights.min(MAX_DYNAMIC_LIGHTS);
+
+        if max_lights == 0 {
+            return result;
+        }
+
+        let mut candidates = lights.iter()
+            .enumerate()
+            .map(|(i, x)| (x.distance_sq_to(bounds), i, x))
+            .filter(|(dist_sq, _, light)| *dist_sq < light.radius * light.radius)
+            .collect::<Vec<_>>();
+
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (_, i, light) in candidates.into_iter().take(max_lights) {
+            result.lights[result.count] = *light;
+            result.indices[result.count] = i;
+            result.count += 1;
+        }
+
+        result
+    }
+
+    /// Check whether two sets selected from the same slice of lights contain the same lights
+    pub fn same_lights(self: &Self, other: &DynamicLightSet) -> bool {
+        self.indices[..self.count] == other.indices[..other.count]
+    }
+
+    /// Set the dynamic light uniforms of the given shader. Unused light slots are given a radius & color of zero
+    pub fn apply(self: &Self, shader: &Shader) {
+        for i in 0..MAX_DYNAMIC_LIGHTS {
+            let light = if i < self.count { self.lights[i] } else { DynamicLight::default() };
+
+            shader.set_uniform_vec4(LIGHT_POS_UNIFORMS[i], Vector4::new(light.position.x, light.position.y, light.position.z, light.radius));
+            shader.set_uniform_vec3(LIGHT_COLOR_UNIFORMS[i], light.color);
+        }
+    }
+}
@@ -4,4 +4,5 @@ pub mod shader;
 pub mod material;
 pub mod gfx;
 pub mod anim;
-pub mod model;
\ No newline at end of file
+pub mod model;
+pub mod dynamic_light;
\ No newline at end of file
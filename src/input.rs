@@ -0,0 +1,403 @@
+use std::{collections::{HashMap, HashSet}, sync::RwLock};
+
+use clap::Command;
+use lazy_static::lazy_static;
+use log::{error, info};
+use sdl2::{controller::{Axis, Button, GameController}, event::Event, keyboard::Keycode, mouse::MouseButton};
+use shellwords::join;
+
+use crate::{cvar::get_cvar, gamestate::InputState, system::{ccmd_system::{register_arg_completer, register_command}, fpview_system::LOOK_SPEED}};
+
+const STICK_DEADZONE: f32 = 0.1;
+
+const ALL_AXES: [Axis;6] = [
+    Axis::LeftX,
+    Axis::LeftY,
+    Axis::RightX,
+    Axis::RightY,
+    Axis::TriggerLeft,
+    Axis::TriggerRight,
+];
+
+/// Game actions which keys, mouse buttons, gamepad buttons & gamepad axes can be bound to
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputAction {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    LookUp,
+    LookDown,
+    LookLeft,
+    LookRight,
+    Jump,
+    Crouch,
+}
+
+const ALL_ACTIONS: [InputAction;10] = [
+    InputAction::MoveForward,
+    InputAction::MoveBack,
+    InputAction::MoveLeft,
+    InputAction::MoveRight,
+    InputAction::LookUp,
+    InputAction::LookDown,
+    InputAction::LookLeft,
+    InputAction::LookRight,
+    InputAction::Jump,
+    InputAction::Crouch,
+];
+
+impl InputAction {
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            InputAction::MoveForward => "forward",
+            InputAction::MoveBack => "back",
+            InputAction::MoveLeft => "moveleft",
+            InputAction::MoveRight => "moveright",
+            InputAction::LookUp => "lookup",
+            InputAction::LookDown => "lookdown",
+            InputAction::LookLeft => "left",
+            InputAction::LookRight => "right",
+            InputAction::Jump => "jump",
+            InputAction::Crouch => "crouch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InputAction> {
+        ALL_ACTIONS.iter().find(|x| x.name() == name.to_lowercase()).copied()
+    }
+}
+
+lazy_static! {
+    static ref BINDINGS: RwLock<HashMap<String, InputAction>> = RwLock::new(default_bindings());
+}
+
+fn default_bindings() -> HashMap<String, InputAction> {
+    [
+        ("w", InputAction::MoveForward),
+        ("s", InputAction::MoveBack),
+        ("a", InputAction::MoveLeft),
+        ("d", InputAction::MoveRight),
+        ("up", InputAction::LookUp),
+        ("down", InputAction::LookDown),
+        ("left", InputAction::LookLeft),
+        ("right", InputAction::LookRight),
+        ("space", InputAction::Jump),
+        ("left ctrl", InputAction::Crouch),
+        ("pad_a", InputAction::Jump),
+        ("pad_b", InputAction::Crouch),
+        ("pad_leftx+", InputAction::MoveRight),
+        ("pad_leftx-", InputAction::MoveLeft),
+        ("pad_lefty-", InputAction::MoveForward),
+        ("pad_lefty+", InputAction::MoveBack),
+        ("pad_rightx+", InputAction::LookRight),
+        ("pad_rightx-", InputAction::LookLeft),
+        ("pad_righty-", InputAction::LookUp),
+        ("pad_righty+", InputAction::LookDown),
+    ].into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+fn mouse_button_name(button: MouseButton) -> Option<&'static str> {
+    match button {
+        MouseButton::Left => Some("mouse1"),
+        MouseButton::Right => Some("mouse2"),
+        MouseButton::Middle => Some("mouse3"),
+        MouseButton::X1 => Some("mouse4"),
+        MouseButton::X2 => Some("mouse5"),
+        _ => None
+    }
+}
+
+fn gamepad_button_name(button: Button) -> String {
+    format!("pad_{}", button.string())
+}
+
+// gamepad axes are bound separately in each direction (e.g. pad_leftx+ for pushing the left stick right)
+fn gamepad_axis_name(axis: Axis, positive: bool) -> String {
+    format!("pad_{}{}", axis.string(), if positive { "+" } else { "-" })
+}
+
+// check whether a key name refers to a keyboard key, mouse button, gamepad button, or gamepad axis direction
+fn is_valid_key(key: &str) -> bool {
+    if let Some(button) = key.strip_prefix("pad_") {
+        if let Some(axis) = button.strip_suffix(['+', '-']) {
+            return Axis::from_string(axis).is_some();
+        }
+
+        return Button::from_string(button).is_some();
+    }
+
+    ["mouse1", "mouse2", "mouse3", "mouse4", "mouse5"].contains(&key) || Keycode::from_name(key).is_some()
+}
+
+/// Bind a key to an action by name. Keys are named after their SDL key names (e.g. "w", "space", "left ctrl"),
+/// mouse buttons are named mouse1 through mouse5, and gamepad buttons are named pad_ followed by their SDL button name (e.g. "pad_a").
+/// Gamepad axes are named pad_ followed by their SDL axis name & a direction (e.g. "pad_leftx+", "pad_lefty-"), and drive the bound action by how far they're pushed
+pub fn bind(key: &str, action: &str) {
+    let key = key.to_lowercase();
+
+    if !is_valid_key(&key) {
+        error!("Invalid key name: {}", key);
+        return;
+    }
+
+    match InputAction::from_name(action) {
+        Some(action) => {
+            BINDINGS.write().unwrap().insert(key, action);
+        }
+        None => {
+            error!("Invalid action name: {} (valid actions: {})", action, ALL_ACTIONS.map(|x| x.name()).join(", "));
+        }
+    }
+}
+
+/// Remove the binding for a key, if any
+pub fn unbind(key: &str) {
+    if BINDINGS.write().unwrap().remove(&key.to_lowercase()).is_none() {
+        error!("Key is not bound: {}", key);
+    }
+}
+
+/// Remove all key bindings, including the defaults
+pub fn unbind_all() {
+    BINDINGS.write().unwrap().clear();
+}
+
+pub fn print_bindings() {
+    let bindings = BINDINGS.read().unwrap();
+
+    let mut sorted = bindings.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (key, action) in sorted {
+        info!("\"{}\" - {}", key, action.name());
+    }
+}
+
+/// Returns the commands which would restore the current set of bindings, starting with an unbindall
+pub fn binding_commands() -> Vec<String> {
+    let bindings = BINDINGS.read().unwrap();
+
+    let mut commands = bindings.iter()
+        .map(|(key, action)| join(&["bind", key, action.name()]))
+        .collect::<Vec<_>>();
+
+    commands.sort();
+    commands.insert(0, "unbindall".to_string());
+    commands
+}
+
+fn action_names() -> Vec<String> {
+    ALL_ACTIONS.iter().map(|x| x.name().to_string()).collect()
+}
+
+fn bound_keys() -> Vec<String> {
+    BINDINGS.read().unwrap().keys().cloned().collect()
+}
+
+/// Register the console commands for editing key bindings
+pub fn register_commands() {
+    register_command(Command::new("bind")
+        .about("Bind a key, mouse button (mouse1-mouse5), gamepad button (pad_a, pad_b, etc) or gamepad axis direction (pad_leftx+, pad_lefty-, etc) to an action")
+        .arg(clap::arg!(<KEY> "Name of the key"))
+        .arg(clap::arg!(<ACTION> "Action to bind (forward, back, moveleft, moveright, lookup, lookdown, left, right, jump, crouch)")),
+        |args, _| {
+            let key = args.get_one::<String>("KEY").unwrap();
+            let action = args.get_one::<String>("ACTION").unwrap();
+
+            bind(key, action);
+        }
+    );
+    register_arg_completer("bind", "ACTION", action_names);
+
+    register_command(Command::new("unbind")
+        .about("Remove the binding for a key")
+        .arg(clap::arg!(<KEY> "Name of the key")),
+        |args, _| {
+            let key = args.get_one::<String>("KEY").unwrap();
+            unbind(key);
+        }
+    );
+    register_arg_completer("unbind", "KEY", bound_keys);
+
+    register_command(Command::new("unbindall")
+        .about("Remove all key bindings"),
+        |_, _| {
+            unbind_all();
+        }
+    );
+
+    register_command(Command::new("bindlist")
+        .about("List all key bindings"),
+        |_, _| {
+            print_bindings();
+        }
+    );
+}
+
+fn apply_deadzone(value: f32) -> f32 {
+    if value.abs() < STICK_DEADZONE {
+        0.0
+    }
+    else {
+        ((value.abs() - STICK_DEADZONE) / (1.0 - STICK_DEADZONE)) * value.signum()
+    }
+}
+
+/// Tracks held keys, mouse buttons, gamepad buttons & accumulated mouse motion, and maps them (along with gamepad axes) onto each local player's InputState via the current bindings
+pub struct InputMapper {
+    held_keys: HashSet<String>,
+    /// Held gamepad buttons, by joystick instance ID
+    held_buttons: HashMap<u32, HashSet<String>>,
+    mouse_delta_x: f32,
+    mouse_delta_y: f32,
+}
+
+impl Default for InputMapper {
+    fn default() -> Self {
+        InputMapper::new()
+    }
+}
+
+impl InputMapper {
+    pub fn new() -> InputMapper {
+        InputMapper {
+            held_keys: HashSet::new(),
+            held_buttons: HashMap::new(),
+            mouse_delta_x: 0.0,
+            mouse_delta_y: 0.0,
+        }
+    }
+
+    pub fn handle_event(self: &mut Self, event: &Event) {
+        match event {
+            Event::KeyDown { keycode: Some(k), repeat: false, .. } => {
+                self.held_keys.insert(k.name().to_lowercase());
+            }
+            Event::KeyUp { keycode: Some(k), .. } => {
+                self.held_keys.remove(&k.name().to_lowercase());
+            }
+            Event::MouseButtonDown { mouse_btn, .. } => {
+                if let Some(name) = mouse_button_name(*mouse_btn) {
+                    self.held_keys.insert(name.to_string());
+                }
+            }
+            Event::MouseButtonUp { mouse_btn, .. } => {
+                if let Some(name) = mouse_button_name(*mouse_btn) {
+                    self.held_keys.remove(name);
+                }
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.held_buttons.entry(*which).or_default().insert(gamepad_button_name(*button));
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(buttons) = self.held_buttons.get_mut(which) {
+                    buttons.remove(&gamepad_button_name(*button));
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.held_buttons.remove(which);
+            }
+            Event::MouseMotion { xrel, yrel, .. } => {
+                self.mouse_delta_x += *xrel as f32;
+                self.mouse_delta_y += *yrel as f32;
+            }
+            _ => {}
+        }
+    }
+
+    /// Release all held keys & buttons and discard accumulated mouse motion (for example, when the console is opened)
+    pub fn clear(self: &mut Self) {
+        self.held_keys.clear();
+        self.held_buttons.clear();
+        self.mouse_delta_x = 0.0;
+        self.mouse_delta_y = 0.0;
+    }
+
+    /// Build the input state of each local player for a tick of the given length, where gamepads holds the gamepad assigned to each player (if any).
+    /// Player 0 also receives keyboard & mouse input. Accumulated mouse motion is consumed by this call
+    pub fn build_input_states(self: &mut Self, gamepads: &[Option<GameController>], delta: f32) -> Vec<InputState> {
+        let input_states = gamepads.iter()
+            .enumerate()
+            .map(|(player_index, gamepad)| self.build_input_state(player_index == 0, gamepad.as_ref(), delta))
+            .collect();
+
+        self.mouse_delta_x = 0.0;
+        self.mouse_delta_y = 0.0;
+
+        input_states
+    }
+
+    // build the input state for a single player from their gamepad, and optionally the keyboard & mouse
+    fn build_input_state(self: &Self, keyboard_mouse: bool, gamepad: Option<&GameController>, delta: f32) -> InputState {
+        let mut input_state = InputState::default();
+
+        let no_buttons = HashSet::new();
+        let held_keys = if keyboard_mouse { &self.held_keys } else { &no_buttons };
+        let held_buttons = gamepad.and_then(|x| self.held_buttons.get(&x.instance_id())).unwrap_or(&no_buttons);
+
+        // digital actions
+        let bindings = BINDINGS.read().unwrap();
+        let actions = held_keys.iter()
+            .chain(held_buttons.iter())
+            .filter_map(|x| bindings.get(x).copied())
+            .collect::<HashSet<_>>();
+
+        // analog actions, which take the furthest any of their bound axes are pushed
+        let mut analog_actions = HashMap::new();
+
+        if let Some(gp) = gamepad {
+            for axis in ALL_AXES {
+                let value = apply_deadzone((gp.axis(axis) as f32 / 32767.0).clamp(-1.0, 1.0));
+
+                if value == 0.0 {
+                    continue;
+                }
+
+                if let Some(action) = bindings.get(&gamepad_axis_name(axis, value > 0.0)) {
+                    let action_value = analog_actions.entry(*action).or_insert(0.0_f32);
+                    *action_value = action_value.max(value.abs());
+                }
+            }
+        }
+
+        let digital_axis = |pos: InputAction, neg: InputAction| {
+            (if actions.contains(&pos) { 1.0 } else { 0.0 }) - (if actions.contains(&neg) { 1.0 } else { 0.0 })
+        };
+
+        let analog = |action: InputAction| analog_actions.get(&action).copied().unwrap_or(0.0);
+        let analog_axis = |pos: InputAction, neg: InputAction| analog(pos) - analog(neg);
+
+        let invert_y = if get_cvar::<bool>("joy_invert_y") { -1.0 } else { 1.0 };
+
+        input_state.move_x = digital_axis(InputAction::MoveRight, InputAction::MoveLeft) + analog_axis(InputAction::MoveRight, InputAction::MoveLeft);
+        input_state.move_y = digital_axis(InputAction::MoveForward, InputAction::MoveBack) + analog_axis(InputAction::MoveForward, InputAction::MoveBack);
+        input_state.look_x = digital_axis(InputAction::LookRight, InputAction::LookLeft) + analog_axis(InputAction::LookRight, InputAction::LookLeft);
+        input_state.look_y = digital_axis(InputAction::LookUp, InputAction::LookDown) + analog_axis(InputAction::LookUp, InputAction::LookDown) * invert_y;
+
+        // axes bound to digital actions (e.g. a trigger bound to jump) act like a button pressed halfway down
+        input_state.jump = actions.contains(&InputAction::Jump) || analog(InputAction::Jump) > 0.5;
+        input_state.crouch = actions.contains(&InputAction::Crouch) || analog(InputAction::Crouch) > 0.5;
+
+        // don't let diagonal movement exceed full speed
+        let move_len = (input_state.move_x * input_state.move_x + input_state.move_y * input_state.move_y).sqrt();
+        if move_len > 1.0 {
+            input_state.move_x /= move_len;
+            input_state.move_y /= move_len;
+        }
+
+        input_state.look_x = input_state.look_x.clamp(-1.0, 1.0);
+        input_state.look_y = input_state.look_y.clamp(-1.0, 1.0);
+
+        // mouse look is converted into the equivalent look rate for this tick, so it isn't clamped
+        if keyboard_mouse && delta > 0.0 {
+            let sensitivity = get_cvar::<f32>("m_sensitivity");
+            let invert_y = if get_cvar::<bool>("m_invert_y") { -1.0 } else { 1.0 };
+
+            input_state.look_x += self.mouse_delta_x * sensitivity / (LOOK_SPEED * delta);
+            input_state.look_y -= self.mouse_delta_y * sensitivity * invert_y / (LOOK_SPEED * delta);
+        }
+
+        input_state
+    }
+}
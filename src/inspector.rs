@@ -0,0 +1,291 @@
+use hecs::{Entity, EntityRef, World};
+use imgui::Ui;
+
+use crate::{bsp::bspfile::{BspFile, MASK_SOLID}, component::{basicanim::{BasicAnim, BasicLerpAnim}, camera::{Camera, FPCamera, ThirdPersonCamera}, charactercontroller::{CharacterController, CharacterInputState, CharacterState}, collider::ColliderBounds, door::{Door, DoorLink, DoorOpener}, effect::Effect, flycam::FlyCam, fpview::FPView, health::Health, light::{Light, SwitchableLight}, mapmodel::MapModel, meshpose::MeshPose, plat::Plat, playerinput::PlayerInput, rendermesh::{RenderMesh, SkinnedMesh}, rotator::Rotator, soundsource::SoundSource, spawnindex::SpawnIndex, train::{PathCorner, Train}, transform3d::Transform3D, triggerable::{TriggerLink, TriggerState, TriggerVolume}}, math::{Quaternion, Vector3}, misc::AABB, physics::PhysicsWorld};
+
+// extents of the box used to pick entities which have a position but no collision bounds (lights, effects, etc)
+const PICK_EXTENTS: f32 = 8.0;
+
+/// ImGui window which lists every entity in the world & allows editing a selection of their components live
+pub struct EntityInspector {
+    selected: Option<Entity>,
+    filter: String,
+}
+
+// names of all known components attached to an entity
+fn component_names(entity: &EntityRef) -> Vec<&'static str> {
+    let components = [
+        ("Transform3D", entity.has::<Transform3D>()),
+        ("SpawnIndex", entity.has::<SpawnIndex>()),
+        ("Camera", entity.has::<Camera>()),
+        ("FPCamera", entity.has::<FPCamera>()),
+        ("ThirdPersonCamera", entity.has::<ThirdPersonCamera>()),
+        ("FPView", entity.has::<FPView>()),
+        ("PlayerInput", entity.has::<PlayerInput>()),
+        ("FlyCam", entity.has::<FlyCam>()),
+        ("CharacterController", entity.has::<CharacterController>()),
+        ("CharacterState", entity.has::<CharacterState>()),
+        ("CharacterInputState", entity.has::<CharacterInputState>()),
+        ("MapModel", entity.has::<MapModel>()),
+        ("Rotator", entity.has::<Rotator>()),
+        ("Door", entity.has::<Door>()),
+        ("DoorLink", entity.has::<DoorLink>()),
+        ("DoorOpener", entity.has::<DoorOpener>()),
+        ("Plat", entity.has::<Plat>()),
+        ("Train", entity.has::<Train>()),
+        ("PathCorner", entity.has::<PathCorner>()),
+        ("TriggerState", entity.has::<TriggerState>()),
+        ("TriggerLink", entity.has::<TriggerLink>()),
+        ("TriggerVolume", entity.has::<TriggerVolume>()),
+        ("ColliderBounds", entity.has::<ColliderBounds>()),
+        ("Health", entity.has::<Health>()),
+        ("Light", entity.has::<Light>()),
+        ("SwitchableLight", entity.has::<SwitchableLight>()),
+        ("RenderMesh", entity.has::<RenderMesh>()),
+        ("SkinnedMesh", entity.has::<SkinnedMesh>()),
+        ("MeshPose", entity.has::<MeshPose>()),
+        ("BasicAnim", entity.has::<BasicAnim>()),
+        ("BasicLerpAnim", entity.has::<BasicLerpAnim>()),
+        ("Effect", entity.has::<Effect>()),
+        ("SoundSource", entity.has::<SoundSource>()),
+    ];
+
+    components.into_iter().filter(|(_, has)| *has).map(|(name, _)| name).collect()
+}
+
+fn edit_vector3(ui: &Ui, label: &str, value: &mut Vector3, speed: f32) -> bool {
+    let mut arr = [value.x, value.y, value.z];
+
+    if imgui::Drag::new(label).speed(speed).build_array(ui, &mut arr) {
+        *value = Vector3::new(arr[0], arr[1], arr[2]);
+        true
+    }
+    else {
+        false
+    }
+}
+
+fn edit_f32(ui: &Ui, label: &str, value: &mut f32, speed: f32) -> bool {
+    imgui::Drag::new(label).speed(speed).build(ui, value)
+}
+
+fn edit_transform(ui: &Ui, transform: &mut Transform3D) {
+    edit_vector3(ui, "Position", &mut transform.position, 1.0);
+
+    // rotation is edited as euler angles in degrees
+    let euler = transform.rotation.to_euler();
+    let mut euler_deg = Vector3::new(euler.x.to_degrees(), euler.y.to_degrees(), euler.z.to_degrees());
+    if edit_vector3(ui, "Rotation", &mut euler_deg, 1.0) {
+        transform.rotation = Quaternion::from_euler(Vector3::new(euler_deg.x.to_radians(), euler_deg.y.to_radians(), euler_deg.z.to_radians()));
+    }
+
+    edit_vector3(ui, "Scale", &mut transform.scale, 0.01);
+}
+
+fn edit_door(ui: &Ui, door: &mut Door) {
+    ui.checkbox("Auto Open", &mut door.auto_open);
+    edit_vector3(ui, "Close Position", &mut door.close_pos, 1.0);
+    edit_vector3(ui, "Open Position", &mut door.open_pos, 1.0);
+    edit_f32(ui, "Move Speed", &mut door.move_speed, 1.0);
+    edit_f32(ui, "Damage", &mut door.dmg, 1.0);
+    ui.checkbox("Crusher", &mut door.crusher);
+}
+
+fn edit_rotator(ui: &Ui, rotator: &mut Rotator) {
+    edit_vector3(ui, "Axis", &mut rotator.rot_axis, 0.01);
+    edit_f32(ui, "Speed", &mut rotator.rot_speed, 1.0);
+}
+
+fn edit_character_controller(ui: &Ui, cc: &mut CharacterController) {
+    edit_f32(ui, "Radius", &mut cc.radius, 0.5);
+    edit_f32(ui, "Height Offset", &mut cc.height_offset, 0.5);
+    edit_f32(ui, "Move Speed", &mut cc.move_speed, 1.0);
+    edit_f32(ui, "Jump Force", &mut cc.jump_force, 1.0);
+    edit_f32(ui, "Main Height", &mut cc.main_height, 0.5);
+    edit_f32(ui, "Crouch Height", &mut cc.crouch_height, 0.5);
+}
+
+fn edit_light(ui: &Ui, light: &mut Light) {
+    edit_vector3(ui, "Color", &mut light.color, 0.01);
+    edit_f32(ui, "Max Radius", &mut light.max_radius, 1.0);
+}
+
+fn edit_camera(ui: &Ui, camera: &mut Camera) {
+    ui.slider("FOV", 1.0, 179.0, &mut camera.fov);
+    edit_f32(ui, "Near", &mut camera.near, 0.1);
+    edit_f32(ui, "Far", &mut camera.far, 10.0);
+
+    if let Some(player_index) = camera.player_index {
+        ui.text(format!("Player {}", player_index + 1));
+    }
+}
+
+fn edit_thirdperson_camera(ui: &Ui, cam: &mut ThirdPersonCamera) {
+    edit_vector3(ui, "Target Offset", &mut cam.target_offset, 0.5);
+    edit_f32(ui, "Distance", &mut cam.distance, 1.0);
+    edit_f32(ui, "Shoulder Offset", &mut cam.shoulder_offset, 0.5);
+    ui.slider("Min Pitch", -90.0, 90.0, &mut cam.min_pitch);
+    ui.slider("Max Pitch", -90.0, 90.0, &mut cam.max_pitch);
+    edit_f32(ui, "Smoothing", &mut cam.smoothing, 0.01);
+    edit_f32(ui, "Return Speed", &mut cam.return_speed, 1.0);
+}
+
+fn edit_basic_lerp_anim(ui: &Ui, anim: &mut BasicLerpAnim) {
+    ui.slider("Mix", 0.0, 1.0, &mut anim.mix);
+}
+
+impl Default for EntityInspector {
+    fn default() -> Self {
+        EntityInspector::new()
+    }
+}
+
+impl EntityInspector {
+    pub fn new() -> EntityInspector {
+        EntityInspector {
+            selected: None,
+            filter: String::new(),
+        }
+    }
+
+    pub fn select(self: &mut Self, entity: Option<Entity>) {
+        self.selected = entity;
+    }
+
+    pub fn draw(self: &mut Self, ui: &Ui, world: &mut World) {
+        // selected entity may have been despawned (or the map changed) since it was selected
+        if self.selected.is_some_and(|x| !world.contains(x)) {
+            self.selected = None;
+        }
+
+        let Some(inspector_win) = ui.window("Entity Inspector")
+            .position([16.0, 160.0], imgui::Condition::FirstUseEver)
+            .size([640.0, 480.0], imgui::Condition::FirstUseEver)
+            .begin()
+        else {
+            return;
+        };
+
+        ui.text(format!("{} entities (click in the viewport to select)", world.len()));
+        ui.input_text("Filter", &mut self.filter).hint("Component name").build();
+
+        // entity list
+        if let Some(list) = ui.child_window("ENTITY_LIST")
+            .size([240.0, 0.0])
+            .border(true)
+            .begin()
+        {
+            for entity_ref in world.iter() {
+                let e = entity_ref.entity();
+                let components = component_names(&entity_ref);
+
+                if !self.filter.is_empty() && !components.iter().any(|x| x.to_lowercase().contains(&self.filter.to_lowercase())) {
+                    continue;
+                }
+
+                // label each entity with the last of its known components in the list above
+                let label = format!("{} - {}##{}", e.id(), components.last().unwrap_or(&"(empty)"), e.to_bits());
+                if ui.selectable_config(label).selected(self.selected == Some(e)).build() {
+                    self.selected = Some(e);
+                }
+            }
+
+            list.end();
+        }
+
+        ui.same_line();
+
+        // component editor
+        if let Some(details) = ui.child_window("ENTITY_DETAILS").begin() {
+            match self.selected {
+                Some(e) => {
+                    ui.text(format!("Entity {}", e.id()));
+                    ui.text_wrapped(format!("Components: {}", component_names(&world.entity(e).unwrap()).join(", ")));
+                    ui.separator();
+
+                    if let Ok(mut transform) = world.get::<&mut Transform3D>(e) {
+                        if ui.collapsing_header("Transform3D", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_transform(ui, &mut transform);
+                        }
+                    }
+
+                    if let Ok(mut door) = world.get::<&mut Door>(e) {
+                        if ui.collapsing_header("Door", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_door(ui, &mut door);
+                        }
+                    }
+
+                    if let Ok(mut rotator) = world.get::<&mut Rotator>(e) {
+                        if ui.collapsing_header("Rotator", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_rotator(ui, &mut rotator);
+                        }
+                    }
+
+                    if let Ok(mut cc) = world.get::<&mut CharacterController>(e) {
+                        if ui.collapsing_header("CharacterController", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_character_controller(ui, &mut cc);
+                        }
+                    }
+
+                    if let Ok(mut light) = world.get::<&mut Light>(e) {
+                        if ui.collapsing_header("Light", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_light(ui, &mut light);
+                        }
+                    }
+
+                    if let Ok(mut camera) = world.get::<&mut Camera>(e) {
+                        if ui.collapsing_header("Camera", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_camera(ui, &mut camera);
+                        }
+                    }
+
+                    if let Ok(mut cam) = world.get::<&mut ThirdPersonCamera>(e) {
+                        if ui.collapsing_header("ThirdPersonCamera", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_thirdperson_camera(ui, &mut cam);
+                        }
+                    }
+
+                    if let Ok(mut anim) = world.get::<&mut BasicLerpAnim>(e) {
+                        if ui.collapsing_header("BasicLerpAnim", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                            edit_basic_lerp_anim(ui, &mut anim);
+                        }
+                    }
+                }
+                None => {
+                    ui.text_disabled("No entity selected");
+                }
+            }
+
+            details.end();
+        }
+
+        inspector_win.end();
+    }
+}
+
+/// Find the closest entity hit by a line from start to end, or None if the line hits the world or nothing at all.
+/// Map models are traced against their brushes, other entities against their collision bounds (or a small box around their position)
+pub fn pick_entity(map: &BspFile, world: &World, start: Vector3, end: Vector3) -> Option<Entity> {
+    let mut trace = PhysicsWorld::new(map, world).raycast(MASK_SOLID, start, end, &[]);
+    let mut hit_entity = trace.entity;
+
+    // entities which don't collide with anything can still be picked by a small box around their position
+    for (e, transform) in world.query::<&Transform3D>().without::<&MapModel>().without::<&ColliderBounds>().iter() {
+        let entity_ref = world.entity(e).unwrap();
+
+        if entity_ref.has::<CharacterController>() && entity_ref.has::<CharacterState>() {
+            continue;
+        }
+
+        let bounds = AABB::center_extents(transform.position, Vector3::new(PICK_EXTENTS, PICK_EXTENTS, PICK_EXTENTS));
+
+        // note: boxes containing the start of the line (such as the player's own bounds) are never hit
+        let mut box_trace = trace;
+        if BspFile::trace_aabb(&bounds, &start, &end, None, &mut box_trace) {
+            trace.fraction = box_trace.fraction;
+            hit_entity = Some(e);
+        }
+    }
+
+    hit_entity
+}
\ No newline at end of file
@@ -0,0 +1,105 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use log::error;
+
+pub const NUM_LIGHTSTYLES: usize = 256;
+
+/// Rate at which lightstyle patterns advance, in characters per second
+pub const LIGHTSTYLE_RATE: f32 = 10.0;
+
+// Quake-style light animation tables ('a' is minimum light, 'm' is normal light, 'z' is maximum light)
+const BUILTIN_LIGHTSTYLES: [&str;13] = [
+    // 0 - NORMAL
+    "m",
+    // 1 - FLICKER 1
+    "mmnmmommommnonmmonqnmmo",
+    // 2 - SLOW STRONG PULSE
+    "abcdefghijklmnopqrstuvwxyzyxwvutsrqponmlkjihgfedcba",
+    // 3 - CANDLE 1
+    "mmmmmaaaaammmmmaaaaaabcdefgabcdefg",
+    // 4 - FAST STROBE
+    "mamamamamama",
+    // 5 - GENTLE PULSE
+    "jklmnopqrstuvwxyzyxwvutsrqponmlkj",
+    // 6 - FLICKER 2
+    "nmonqnmomnmomomno",
+    // 7 - CANDLE 2
+    "mmmaaaabcdefgmmmmaaaammmaamm",
+    // 8 - CANDLE 3
+    "mmmaaammmaaammmabcdefaaaammmmabcdefmmmaaaa",
+    // 9 - SLOW STROBE
+    "aaaaaaaazzzzzzzz",
+    // 10 - FLUORESCENT FLICKER
+    "mmamammmmammamamaaamammma",
+    // 11 - SLOW PULSE, NO BLACK
+    "abcdefghijklmnopqrrqponmlkjihgfedcba",
+    // 12 - FAST PULSE
+    "acegikmoqsuwyywusqomkigeca",
+];
+
+lazy_static! {
+    static ref LIGHTSTYLES: RwLock<Vec<String>> = RwLock::new(default_lightstyles());
+}
+
+// styles without a built-in pattern default to normal light
+fn default_lightstyles() -> Vec<String> {
+    (0..NUM_LIGHTSTYLES)
+        .map(|x| BUILTIN_LIGHTSTYLES.get(x).copied().unwrap_or("m").to_string())
+        .collect()
+}
+
+/// Returns whether the given string is a valid lightstyle pattern (a non-empty string of the characters a-z)
+pub fn is_valid_pattern(pattern: &str) -> bool {
+    !pattern.is_empty() && pattern.bytes().all(|x| x.is_ascii_lowercase())
+}
+
+/// Override the pattern of the given lightstyle
+pub fn set_lightstyle(index: usize, pattern: &str) {
+    if index >= NUM_LIGHTSTYLES {
+        error!("Invalid lightstyle index: {} (must be less than {})", index, NUM_LIGHTSTYLES);
+        return;
+    }
+
+    if !is_valid_pattern(pattern) {
+        error!("Invalid lightstyle pattern: \"{}\" (must only contain the characters a-z)", pattern);
+        return;
+    }
+
+    LIGHTSTYLES.write().unwrap()[index] = pattern.to_string();
+}
+
+pub fn get_lightstyle(index: usize) -> String {
+    LIGHTSTYLES.read().unwrap()[index].clone()
+}
+
+/// Restore every lightstyle to its default pattern (called whenever a new map is loaded)
+pub fn reset_lightstyles() {
+    *LIGHTSTYLES.write().unwrap() = default_lightstyles();
+}
+
+/// Sample a lightstyle pattern at the given time, blending between adjacent characters
+pub fn sample_pattern(pattern: &str, time: f32) -> f32 {
+    let pattern = pattern.as_bytes();
+
+    if pattern.is_empty() {
+        return 0.0;
+    }
+
+    let frame = (time * LIGHTSTYLE_RATE) as usize;
+    let frame_lerp = (time * LIGHTSTYLE_RATE).fract();
+
+    let a = (pattern[frame % pattern.len()] - b'a') as f32 / 25.0;
+    let b = (pattern[(frame + 1) % pattern.len()] - b'a') as f32 / 25.0;
+
+    (a * (1.0 - frame_lerp)) + (b * frame_lerp)
+}
+
+/// Evaluate every lightstyle at the given time
+pub fn evaluate_lightstyles(time: f32, out_light_styles: &mut [f32;NUM_LIGHTSTYLES]) {
+    let lightstyles = LIGHTSTYLES.read().unwrap();
+
+    for (idx, pattern) in lightstyles.iter().enumerate() {
+        out_light_styles[idx] = sample_pattern(pattern, time);
+    }
+}
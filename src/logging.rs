@@ -0,0 +1,255 @@
+use std::{cmp::Reverse, fmt::Display, fs::{self, File}, io::{BufWriter, Write}, path::{Path, PathBuf}, sync::{Mutex, RwLock}, time::Instant};
+
+use lazy_static::lazy_static;
+use log::{error, info, warn, Level, LevelFilter};
+
+use crate::cvar::{CVarValue, GetCVar};
+
+const LOG_FILE_NAME: &str = "nanogame3d.log";
+
+/// Number of previous log files which are kept when rotating
+const MAX_OLD_LOG_FILES: usize = 3;
+
+/// Size at which the log file is rotated mid-session
+const MAX_LOG_FILE_SIZE: usize = 4 * 1024 * 1024;
+
+lazy_static! {
+    static ref LOG_FILTER: RwLock<LogFilter> = RwLock::new(LogFilter::default());
+    static ref LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+    static ref LOG_START: Instant = Instant::now();
+}
+
+#[derive(Debug)]
+pub enum LogFilterError {
+    InvalidLevel(String),
+    InvalidDirective(String),
+}
+
+impl Display for LogFilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFilterError::InvalidLevel(v) => write!(f, "Invalid log level: {} (expected off, error, warn, info, debug or trace)", v),
+            LogFilterError::InvalidDirective(v) => write!(f, "Invalid log filter directive: {} (expected LEVEL or TARGET=LEVEL)", v),
+        }
+    }
+}
+
+/// Default log level along with per-target overrides, parsed from a string such as "info,nanogame3d::bsp=debug"
+pub struct LogFilter {
+    default_level: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            default_level: LevelFilter::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn parse(filter: &str) -> Result<LogFilter, LogFilterError> {
+        let mut result = LogFilter::default();
+
+        for directive in filter.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let parse_level = |x: &str| x.parse::<LevelFilter>().map_err(|_| LogFilterError::InvalidLevel(x.to_string()));
+
+            match directive.split_once('=') {
+                Some((target, level)) if !target.is_empty() => {
+                    result.targets.push((target.to_string(), parse_level(level)?));
+                }
+                Some(_) => {
+                    return Err(LogFilterError::InvalidDirective(directive.to_string()));
+                }
+                None => {
+                    result.default_level = parse_level(directive)?;
+                }
+            }
+        }
+
+        // longest (most specific) targets are matched first
+        result.targets.sort_by_key(|x| Reverse(x.0.len()));
+
+        Ok(result)
+    }
+
+    /// Most verbose level enabled by any target
+    pub fn max_level(self: &Self) -> LevelFilter {
+        self.targets.iter().map(|x| x.1).fold(self.default_level, |a, b| a.max(b))
+    }
+
+    pub fn enabled(self: &Self, target: &str, level: Level) -> bool {
+        let target_level = self.targets.iter()
+            .find(|(prefix, _)| target == prefix || target.starts_with(&format!("{}::", prefix)))
+            .map_or(self.default_level, |x| x.1);
+
+        level <= target_level
+    }
+}
+
+/// Whether a record with the given target & level passes the current log filter
+pub fn log_enabled(target: &str, level: Level) -> bool {
+    LOG_FILTER.read().unwrap().enabled(target, level)
+}
+
+/// CVAR change callback for log_level
+pub fn on_log_level_changed(value: &CVarValue) {
+    let filter_str: String = value.get().unwrap_or_default();
+
+    match LogFilter::parse(&filter_str) {
+        Ok(filter) => {
+            log::set_max_level(filter.max_level());
+            *LOG_FILTER.write().unwrap() = filter;
+        }
+        Err(e) => {
+            error!("{} (keeping previous log filter)", e);
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    size: usize,
+}
+
+// path of a previous log file, where index 1 is the most recent
+fn old_log_path(path: &Path, index: usize) -> PathBuf {
+    path.with_extension(format!("{}.log", index))
+}
+
+// shift previous log files along by one, dropping the oldest, & move the current log file into the first slot
+fn rotate_log_files(path: &Path) {
+    let _ = fs::remove_file(old_log_path(path, MAX_OLD_LOG_FILES));
+
+    for i in (1..MAX_OLD_LOG_FILES).rev() {
+        let _ = fs::rename(old_log_path(path, i), old_log_path(path, i + 1));
+    }
+
+    let _ = fs::rename(path, old_log_path(path, 1));
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> std::io::Result<LogFile> {
+        rotate_log_files(&path);
+
+        Ok(LogFile {
+            writer: BufWriter::new(File::create(&path)?),
+            path,
+            size: 0,
+        })
+    }
+
+    fn write(self: &mut Self, line: &str, flush: bool) -> std::io::Result<()> {
+        if self.size + line.len() > MAX_LOG_FILE_SIZE {
+            self.writer.flush()?;
+            *self = LogFile::open(self.path.clone())?;
+        }
+
+        writeln!(self.writer, "{}", line)?;
+        self.size += line.len() + 1;
+
+        if flush {
+            self.writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Open the log file in the user's preferences directory, rotating any previous log files
+pub fn open_log_file() {
+    let dir = match sdl2::filesystem::pref_path("GlaireDaggers", "NanoGame3D") {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Failed to find user directory for log file: {}", e);
+            return;
+        }
+    };
+
+    let path = PathBuf::from(dir).join(LOG_FILE_NAME);
+
+    match LogFile::open(path.clone()) {
+        Ok(v) => {
+            *LOG_FILE.lock().unwrap() = Some(v);
+            info!("Logging to {}", path.display());
+        }
+        Err(e) => {
+            warn!("Failed to open log file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Flush & close the log file
+pub fn close_log_file() {
+    *LOG_FILE.lock().unwrap() = None;
+}
+
+/// Write a log record to the log file, if one is open
+pub fn write_log_file(record: &log::Record) {
+    let mut log_file = LOG_FILE.lock().unwrap();
+
+    if let Some(file) = log_file.as_mut() {
+        let line = format!("[{:10.3}] {:5} {} - {}", LOG_START.elapsed().as_secs_f32(), record.level(), record.target(), record.args());
+
+        // flush warnings & errors immediately, so they aren't lost if the game crashes
+        if let Err(e) = file.write(&line, record.level() <= Level::Warn) {
+            // note: can't log this error, as that would re-enter the log file lock
+            eprintln!("Failed writing to log file, closing it: {}", e);
+            *log_file = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_default_level() {
+        let filter = LogFilter::parse("debug").unwrap();
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+        assert!(filter.enabled("nanogame3d", Level::Debug));
+        assert!(!filter.enabled("nanogame3d", Level::Trace));
+
+        // an empty filter keeps the default of info
+        let filter = LogFilter::parse("").unwrap();
+        assert!(filter.enabled("nanogame3d", Level::Info));
+        assert!(!filter.enabled("nanogame3d", Level::Debug));
+    }
+
+    #[test]
+    fn parse_target_directives() {
+        let filter = LogFilter::parse(" warn , nanogame3d::bsp=trace,").unwrap();
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+        assert!(filter.enabled("nanogame3d::bsp", Level::Trace));
+        assert!(filter.enabled("nanogame3d::bsp::bspfile", Level::Trace));
+        assert!(!filter.enabled("nanogame3d::system", Level::Info));
+        assert!(filter.enabled("nanogame3d::system", Level::Warn));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(LogFilter::parse("loud"), Err(LogFilterError::InvalidLevel(_))));
+        assert!(matches!(LogFilter::parse("nanogame3d=loud"), Err(LogFilterError::InvalidLevel(_))));
+        assert!(matches!(LogFilter::parse("=debug"), Err(LogFilterError::InvalidDirective(_))));
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let filter = LogFilter::parse("nanogame3d=error,nanogame3d::bsp::bspfile=trace,nanogame3d::bsp=warn").unwrap();
+        assert!(filter.enabled("nanogame3d::bsp::bspfile", Level::Trace));
+        assert!(filter.enabled("nanogame3d::bsp::bsprenderer", Level::Warn));
+        assert!(!filter.enabled("nanogame3d::bsp::bsprenderer", Level::Info));
+        assert!(!filter.enabled("nanogame3d::system", Level::Warn));
+    }
+
+    #[test]
+    fn prefix_matches_whole_path_segments() {
+        let filter = LogFilter::parse("error,nanogame3d::bsp=debug").unwrap();
+        assert!(!filter.enabled("nanogame3d::bspx", Level::Debug));
+        assert!(filter.enabled("nanogame3d::bsp", Level::Debug));
+    }
+}
@@ -1,15 +1,24 @@
 use core::f32;
-use std::ffi::CStr;
-use cvar::{define_cvar, get_cvar};
-use log::info;
-
+use std::{ffi::CStr, path::Path, process::exit};
+use clap::{arg, value_parser, Command};
+use config::{write_config, AUTOEXEC_PATH, CONFIG_PATH};
+use cvar::{define_cvar, define_cvar_with_options, get_cvar, toggle_cvar, CVarOptions, CVarValue, GetCVar, CVAR_ARCHIVE, CVAR_READONLY};
+use log::{error, info, warn};
+use logging::{close_log_file, on_log_level_changed, open_log_file};
+
+use audio::output::open_audio_device;
 use consolewin::{ConsoleWindow, ConsoleWindowLogger};
 use frametimer::FrameTimer;
-use gamestate::{GameState, WindowData};
+use gamestate::{GameState, WindowData, MAX_LOCAL_PLAYERS};
+use input::InputMapper;
+use inspector::EntityInspector;
 use imgui::ConfigFlags;
 use imgui_render::Renderer;
 use imgui_sdl2_support::SdlPlatform;
-use sdl2::keyboard::Keycode;
+use sdl2::{controller::GameController, keyboard::Keycode};
+use system::ccmd_system::register_builtin_commands;
+use shellwords::join;
+use surface::{load_surface_table, SURFACE_TABLE_PATH};
 use ui::uiscript::UiScript;
 
 const TICK_INTERVAL: f32 = 1.0 / 60.0;
@@ -36,8 +45,19 @@ pub mod gamestate;
 pub mod imgui_render;
 pub mod frametimer;
 pub mod consolewin;
+pub mod inspector;
 pub mod cvar;
+pub mod config;
 pub mod ui;
+pub mod spawn;
+pub mod savegame;
+pub mod demo;
+pub mod input;
+pub mod lightstyle;
+pub mod logging;
+pub mod audio;
+pub mod surface;
+pub mod physics;
 
 static LOGGER: ConsoleWindowLogger = ConsoleWindowLogger {
 };
@@ -45,14 +65,58 @@ static LOGGER: ConsoleWindowLogger = ConsoleWindowLogger {
 fn main() {
     log::set_logger(&LOGGER).unwrap();
     log::set_max_level(log::LevelFilter::Info);
+    open_log_file();
 
     // define CVARs
+    define_cvar_with_options::<String>("version", env!("CARGO_PKG_VERSION").to_string(), "Engine version", CVarOptions::new().with_flags(CVAR_READONLY));
+    define_cvar_with_options::<String>("log_level", "info".to_string(), "Log level, optionally followed by per-target overrides (e.g. info,nanogame3d::bsp=debug)",
+        CVarOptions::new().with_flags(CVAR_ARCHIVE).with_on_change(on_log_level_changed));
+    define_cvar::<bool>("sv_cheats", false, "Allow changing cheat protected CVARs");
     define_cvar::<bool>("show_fps", false, "Show FPS & frame time stats overlay");
+    define_cvar::<bool>("show_inspector", false, "Show the entity inspector (toggled with F2)");
+    define_cvar_with_options::<bool>("r_vsync", true, "Synchronize buffer swaps to the display refresh rate",
+        CVarOptions::new().with_flags(CVAR_ARCHIVE).with_on_change(on_vsync_changed));
+    define_cvar_with_options::<i32>("r_dynamic_lights", 4, "Maximum number of dynamic lights affecting each mesh or map surface (0 - 4)",
+        CVarOptions::new().with_flags(CVAR_ARCHIVE).with_min(0.0).with_max(4.0));
+    define_cvar_with_options::<f32>("m_sensitivity", 0.15, "Mouse look sensitivity, in degrees per pixel of mouse movement",
+        CVarOptions::new().with_flags(CVAR_ARCHIVE).with_min(0.0));
+    define_cvar_with_options::<bool>("m_invert_y", false, "Invert vertical mouse look", CVarOptions::new().with_flags(CVAR_ARCHIVE));
+    define_cvar_with_options::<bool>("joy_invert_y", false, "Invert vertical gamepad look", CVarOptions::new().with_flags(CVAR_ARCHIVE));
+    define_cvar_with_options::<i32>("cl_splitscreen", 1, "Number of local split-screen players (1 - 4). Takes effect when the next map is loaded",
+        CVarOptions::new().with_flags(CVAR_ARCHIVE).with_min(1.0).with_max(MAX_LOCAL_PLAYERS as f32));
+    define_cvar_with_options::<f32>("s_volume", 0.7, "Master sound volume (0 - 1)",
+        CVarOptions::new().with_flags(CVAR_ARCHIVE).with_min(0.0).with_max(1.0));
+
+    // register console commands
+    register_builtin_commands();
+    input::register_commands();
+
+    // gameplay surface types, used for footsteps, impacts & friction
+    load_surface_table(SURFACE_TABLE_PATH);
+
+    // parse command line (console commands prefixed with + are split out first, e.g. +set m_sensitivity 0.2)
+    let (cmdline_args, cmdline_commands) = split_cmdline_commands(std::env::args());
+
+    let args = Command::new("nanogame3d")
+        .arg(arg!(--headless "Run the simulation without creating a window or GL context"))
+        .arg(arg!(--map <NAME> "Map to load on startup").default_value("e1m1"))
+        .arg(arg!(--ticks <COUNT> "Number of ticks to simulate when running headless").value_parser(value_parser!(u32)))
+        .arg(arg!(--playdemo <NAME> "Demo to play back on startup"))
+        .get_matches_from(cmdline_args);
+
+    let start_map = args.get_one::<String>("map").unwrap();
+    let start_demo = args.get_one::<String>("playdemo");
+
+    if args.get_flag("headless") {
+        run_headless(start_map, args.get_one::<u32>("ticks").copied(), start_demo, cmdline_commands);
+        return;
+    }
 
     let sdl = sdl2::init().unwrap();
     let sdl_video = sdl.video().unwrap();
     let sdl_timer = sdl.timer().unwrap();
     let sdl_gamecontroller = sdl.game_controller().unwrap();
+    let sdl_mouse = sdl.mouse();
 
     #[cfg(feature = "gles2")]
     {
@@ -86,7 +150,7 @@ fn main() {
         gl::DepthRangef(0.0, 1.0);
     }
 
-    sdl_video.gl_set_swap_interval(1).unwrap();
+    on_vsync_changed(&CVarValue::from(get_cvar::<bool>("r_vsync")));
 
     // init basis decoder
     basis_universal::transcoder_init();
@@ -108,19 +172,47 @@ fn main() {
     let mut test_ui_script = UiScript::new("content/scripts/test.rn", "TestUi");
 
     // create game state
-    let mut game_state = GameState::new();
+    let mut game_state = GameState::new(false);
+
+    // apply saved config, user scripts & command line overrides before loading the starting map, so that cvars such as cl_splitscreen affect it
+    exec_startup_commands(&mut game_state, cmdline_commands);
+
+    // load the starting map, unless a startup command already loaded one (a map which fails to load just leaves the console open)
+    if game_state.map_name().is_empty() {
+        game_state.changelevel(start_map);
+    }
+
+    if let Some(demo) = start_demo {
+        game_state.play_demo(demo);
+    }
+
+    // play the game's mixer through the default audio device. The game still runs without sound if this fails
+    let _audio_device = match sdl.audio().and_then(|audio| open_audio_device(&audio, game_state.mixer())) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!("Failed opening audio device, sound is disabled: {}", e);
+            None
+        }
+    };
 
     let mut prev_tick = sdl_timer.performance_counter();
     let timer_freq = 1.0 / (sdl_timer.performance_frequency() as f64);
     let mut delta_accum = 0.0;
 
-    let mut gamepad = None;
+    // gamepad assigned to each local player slot. Gamepads are assigned to the first free slot as they are connected
+    let mut gamepads: Vec<Option<GameController>> = (0..MAX_LOCAL_PLAYERS).map(|_| None).collect();
+    let mut input_mapper = InputMapper::new();
 
     let mut fps_timer = FrameTimer::new();
     let mut frame_timer = FrameTimer::new();
 
     let mut show_console = false;
     let mut console_window = ConsoleWindow::new(&imgui);
+    let mut inspector = EntityInspector::new();
+
+    // capture mouse for mouse look while the console & inspector are hidden
+    let mut mouse_captured = true;
+    sdl_mouse.set_relative_mouse_mode(mouse_captured);
 
     let mut event_pump = sdl.event_pump().unwrap();
     'main: loop {
@@ -130,25 +222,48 @@ fn main() {
             // pass event to ImGui
             platform.handle_event(&mut imgui, &event);
 
+            // pass event to input mapping, unless the console or inspector has focus
+            if mouse_captured {
+                input_mapper.handle_event(&event);
+            }
+
             match event {
                 sdl2::event::Event::Quit {..} => break 'main,
                 sdl2::event::Event::ControllerDeviceAdded { timestamp: _, which } => {
-                    match gamepad {
-                        None => {
-                            let new_gamepad = sdl_gamecontroller.open(which).unwrap();
-                            info!("Opened gamepad: {}", new_gamepad.name());
-                            gamepad = Some(new_gamepad);
+                    match gamepads.iter().position(|x| x.is_none()) {
+                        Some(player_index) => {
+                            match sdl_gamecontroller.open(which) {
+                                Ok(new_gamepad) => {
+                                    info!("Opened gamepad for player {}: {}", player_index + 1, new_gamepad.name());
+                                    gamepads[player_index] = Some(new_gamepad);
+                                }
+                                Err(e) => {
+                                    error!("Failed opening gamepad: {}", e);
+                                }
+                            }
                         }
-                        _ => {
+                        None => {
+                            warn!("Ignoring gamepad: all {} player slots already have a gamepad", MAX_LOCAL_PLAYERS);
                         }
                     }
                 }
+                sdl2::event::Event::ControllerDeviceRemoved { timestamp: _, which } => {
+                    let slot = gamepads.iter_mut().enumerate().find(|(_, x)| x.as_ref().is_some_and(|x| x.instance_id() == which));
+
+                    if let Some((player_index, gamepad)) = slot {
+                        info!("Gamepad disconnected from player {}", player_index + 1);
+                        *gamepad = None;
+                    }
+                }
                 sdl2::event::Event::KeyDown { timestamp: _, window_id: _, keycode, scancode: _, keymod: _, repeat: _ } => {
                     if let Some(k) = keycode {
                         match k {
                             Keycode::Backquote => {
                                 show_console = !show_console;
                             }
+                            Keycode::F2 => {
+                                toggle_cvar("show_inspector");
+                            }
                             _ => {}
                         }
                     }
@@ -157,6 +272,15 @@ fn main() {
             }
         }
 
+        // release mouse while the console or inspector is open
+        let show_inspector = get_cvar::<bool>("show_inspector");
+        if mouse_captured == (show_console || show_inspector) {
+            mouse_captured = !mouse_captured;
+
+            input_mapper.clear();
+            sdl_mouse.set_relative_mouse_mode(mouse_captured);
+        }
+
         // prepare new ImGui frame
         platform.prepare_frame(&mut imgui, &window, &event_pump);
 
@@ -175,7 +299,8 @@ fn main() {
         // update
         while delta_accum >= TICK_INTERVAL {
             delta_accum -= TICK_INTERVAL;
-            game_state.tick(TICK_INTERVAL, gamepad.as_ref());
+            let input_states = input_mapper.build_input_states(&gamepads[..game_state.num_players()], TICK_INTERVAL);
+            game_state.tick(TICK_INTERVAL, input_states);
             test_ui_script.update(TICK_INTERVAL);
         }
 
@@ -184,7 +309,7 @@ fn main() {
 
         // render
         let win_size = window.size();
-        game_state.render(WindowData { width: win_size.0 as i32, height: win_size.1 as i32 });
+        game_state.render(WindowData { width: win_size.0 as i32, height: win_size.1 as i32 }, delta_accum / TICK_INTERVAL);
 
         // draw UI
         test_ui_script.paint(win_size);
@@ -243,6 +368,16 @@ fn main() {
             }
         }
 
+        if show_inspector {
+            inspector.draw(ui, game_state.world_mut());
+
+            // select entities by clicking in the viewport
+            if ui.is_mouse_clicked(imgui::MouseButton::Left) && !ui.io().want_capture_mouse {
+                let [mouse_x, mouse_y] = ui.io().mouse_pos;
+                inspector.select(game_state.pick_entity(mouse_x, mouse_y, &WindowData { width: win_size.0 as i32, height: win_size.1 as i32 }));
+            }
+        }
+
         if show_console {
             console_window.draw((win_size.0 as f32, win_size.1 as f32), ui);
         }
@@ -253,4 +388,94 @@ fn main() {
     }
 
     info!("=== SHUTTING DOWN ===");
+
+    if let Err(e) = write_config() {
+        error!("Failed to write {}: {}", CONFIG_PATH, e);
+    }
+
+    close_log_file();
+}
+
+fn on_vsync_changed(value: &CVarValue) {
+    // no GL context when running headless
+    if unsafe { sdl2::sys::SDL_GL_GetCurrentContext() }.is_null() {
+        return;
+    }
+
+    let interval = if GetCVar::<bool>::get(value).unwrap_or(true) { 1 } else { 0 };
+
+    if unsafe { sdl2::sys::SDL_GL_SetSwapInterval(interval) } != 0 {
+        error!("Failed setting swap interval: {}", sdl2::get_error());
+    }
+}
+
+/// Split console commands out of the command line arguments. Each argument starting with + begins a new command,
+/// which takes all following arguments up until the next + or -- argument
+fn split_cmdline_commands<I>(args: I) -> (Vec<String>, Vec<String>) where I : Iterator::<Item = String> {
+    let mut cmdline_args = Vec::new();
+    let mut commands: Vec<Vec<String>> = Vec::new();
+    let mut in_command = false;
+
+    for arg in args {
+        if let Some(cmd) = arg.strip_prefix('+') {
+            commands.push(vec![cmd.to_string()]);
+            in_command = true;
+        }
+        else if arg.starts_with("--") {
+            cmdline_args.push(arg);
+            in_command = false;
+        }
+        else if in_command {
+            commands.last_mut().unwrap().push(arg);
+        }
+        else {
+            cmdline_args.push(arg);
+        }
+    }
+
+    (cmdline_args, commands.iter().map(|x| join(&x.iter().map(|x| x.as_str()).collect::<Vec<_>>())).collect())
+}
+
+/// Execute the saved config & autoexec scripts (if they exist), followed by any commands given on the command line
+fn exec_startup_commands(game_state: &mut GameState, cmdline_commands: Vec<String>) {
+    for path in [CONFIG_PATH, AUTOEXEC_PATH] {
+        if Path::new(path).exists() {
+            game_state.exec_commands(std::iter::once(join(&["exec", path])));
+        }
+    }
+
+    game_state.exec_commands(cmdline_commands.into_iter());
 }
+
+/// Run the simulation without a window for a fixed number of ticks, or until the given demo finishes playing
+fn run_headless(start_map: &str, max_ticks: Option<u32>, demo: Option<&String>, cmdline_commands: Vec<String>) {
+    if max_ticks.is_none() && demo.is_none() {
+        error!("Headless mode requires either --ticks or --playdemo");
+        exit(1);
+    }
+
+    // user config & autoexec scripts are skipped, so that headless runs only depend on the command line
+    let mut game_state = GameState::new(true);
+    game_state.exec_commands(cmdline_commands.into_iter());
+
+    if game_state.map_name().is_empty() && !game_state.changelevel(start_map) {
+        exit(1);
+    }
+
+    if let Some(demo) = demo {
+        game_state.play_demo(demo);
+
+        if !game_state.is_playing_demo() {
+            exit(1);
+        }
+    }
+
+    let mut num_ticks = 0;
+    while max_ticks.is_none_or(|x| num_ticks < x) && (demo.is_none() || game_state.is_playing_demo()) {
+        game_state.tick(TICK_INTERVAL, Vec::new());
+        num_ticks += 1;
+    }
+
+    info!("Headless simulation finished after {} ticks", num_ticks);
+    close_log_file();
+}
\ No newline at end of file
@@ -572,6 +572,15 @@ impl Quaternion {
         };
     }
 
+    /// Decompose the quaternion into rotations about each axis (the inverse of from_euler)
+    pub fn to_euler(&self) -> Vector3 {
+        let x = (2.0 * (self.w * self.x + self.y * self.z)).atan2(1.0 - 2.0 * (self.x * self.x + self.y * self.y));
+        let y = (2.0 * (self.w * self.y - self.z * self.x)).clamp(-1.0, 1.0).asin();
+        let z = (2.0 * (self.w * self.z + self.x * self.y)).atan2(1.0 - 2.0 * (self.y * self.y + self.z * self.z));
+
+        return Vector3::new(x, y, z);
+    }
+
     /// Produce a normalized copy of the quaternion
     pub fn normalized(&self) -> Quaternion {
         let mag = 1.0 / (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt();
@@ -0,0 +1,216 @@
+use hecs::{Entity, World};
+
+use crate::{bsp::{bspcollision::Trace, bspcommon::{aabb_aabb_intersects, transform_aabb}, bspfile::{BspFile, CONTENTS_SOLID}}, component::{charactercontroller::{CharacterController, CharacterState}, collider::ColliderBounds, mapmodel::MapModel, transform3d::Transform3D}, math::{Matrix4x4, Vector3}, misc::AABB};
+
+struct PhysicsMapModel {
+    entity: Entity,
+    model_idx: usize,
+    local2world: Matrix4x4,
+    world2local: Matrix4x4,
+}
+
+/// Snapshot of everything which can be collided with in the world: the map itself, map model entities, and the bounds of characters & entities with
+/// a ColliderBounds component. Entity bounds are treated as CONTENTS_SOLID.
+///
+/// Entity positions are captured when the PhysicsWorld is created, so it should be recreated whenever entities may have moved
+pub struct PhysicsWorld<'a> {
+    map: &'a BspFile,
+    mapmodels: Vec<PhysicsMapModel>,
+    bounds: Vec<(Entity, AABB)>,
+}
+
+impl<'a> PhysicsWorld<'a> {
+    pub fn new(map: &'a BspFile, world: &World) -> PhysicsWorld<'a> {
+        let mapmodels = world.query::<(&MapModel, &Transform3D)>().iter()
+            .map(|(entity, (mapmodel, transform))| PhysicsMapModel {
+                entity,
+                model_idx: mapmodel.model_idx,
+                local2world: Matrix4x4::scale(transform.scale)
+                    * Matrix4x4::rotation(transform.rotation)
+                    * Matrix4x4::translation(transform.position),
+                world2local: Matrix4x4::translation(transform.position * -1.0)
+                    * Matrix4x4::rotation(transform.rotation.inverted())
+                    * Matrix4x4::scale(1.0 / transform.scale),
+            })
+            .collect();
+
+        let mut bounds = Vec::new();
+
+        for (entity, (cc, cstate, transform)) in world.query::<(&CharacterController, &CharacterState, &Transform3D)>().iter() {
+            let center = transform.position + Vector3::new(0.0, 0.0, cc.height_offset);
+            bounds.push((entity, AABB::center_extents(center, Vector3::new(cc.radius, cc.radius, cstate.height))));
+        }
+
+        for (entity, (cbounds, transform)) in world.query::<(&ColliderBounds, &Transform3D)>().iter() {
+            let local2world = Matrix4x4::scale(transform.scale)
+                * Matrix4x4::rotation(transform.rotation)
+                * Matrix4x4::translation(transform.position);
+
+            bounds.push((entity, transform_aabb(&cbounds.bounds, local2world)));
+        }
+
+        PhysicsWorld {
+            map,
+            mapmodels,
+            bounds,
+        }
+    }
+
+    pub fn map(self: &Self) -> &BspFile {
+        self.map
+    }
+
+    // trace a line (or a box, if box_extents is given) against a single map model, returning the results in world space
+    fn trace_mapmodel(self: &Self, mapmodel: &PhysicsMapModel, content_mask: u32, start: Vector3, end: Vector3, box_extents: Option<Vector3>) -> Trace {
+        let local_start = mapmodel.world2local.transform_point(start);
+        let local_end = mapmodel.world2local.transform_point(end);
+
+        let mut trace = match box_extents {
+            Some(v) => self.map.boxtrace(mapmodel.model_idx + 1, content_mask, local_start, local_end, v),
+            None => self.map.linetrace(mapmodel.model_idx + 1, content_mask, local_start, local_end)
+        };
+
+        trace.end_pos = mapmodel.local2world.transform_point(trace.end_pos);
+        trace.hit_normal = mapmodel.local2world.transform_direction(trace.hit_normal).normalized();
+        trace.entity = Some(mapmodel.entity);
+        trace
+    }
+
+    // trace a line (or a box, if box_extents is given) against the map, map models & entity bounds, returning the nearest hit
+    fn trace(self: &Self, content_mask: u32, start: Vector3, end: Vector3, box_extents: Option<Vector3>, ignore: &[Entity]) -> Trace {
+        let mut trace = match box_extents {
+            Some(v) => self.map.boxtrace(0, content_mask, start, end, v),
+            None => self.map.linetrace(0, content_mask, start, end)
+        };
+
+        for mapmodel in &self.mapmodels {
+            if ignore.contains(&mapmodel.entity) {
+                continue;
+            }
+
+            let tr = self.trace_mapmodel(mapmodel, content_mask, start, end, box_extents);
+
+            if tr.fraction < trace.fraction {
+                trace = tr;
+            }
+        }
+
+        if content_mask & CONTENTS_SOLID != 0 {
+            for (e, bounds) in &self.bounds {
+                if ignore.contains(e) {
+                    continue;
+                }
+
+                if BspFile::trace_aabb(bounds, &start, &end, box_extents.as_ref(), &mut trace) {
+                    trace.entity = Some(*e);
+                }
+            }
+        }
+
+        trace
+    }
+
+    /// Trace a line from start to end, returning the nearest hit
+    pub fn raycast(self: &Self, content_mask: u32, start: Vector3, end: Vector3, ignore: &[Entity]) -> Trace {
+        self.trace(content_mask, start, end, None, ignore)
+    }
+
+    /// Sweep a box with the given extents (half its total size) from start to end, returning the nearest hit
+    pub fn boxcast(self: &Self, content_mask: u32, start: Vector3, end: Vector3, box_extents: Vector3, ignore: &[Entity]) -> Trace {
+        self.trace(content_mask, start, end, Some(box_extents), ignore)
+    }
+
+    /// Sweep a box with the given extents from start to end against a single entity (a map model or an entity's bounds), ignoring everything else
+    pub fn boxcast_entity(self: &Self, entity: Entity, content_mask: u32, start: Vector3, end: Vector3, box_extents: Vector3) -> Trace {
+        if let Some(mapmodel) = self.mapmodels.iter().find(|x| x.entity == entity) {
+            return self.trace_mapmodel(mapmodel, content_mask, start, end, Some(box_extents));
+        }
+
+        let mut trace = Trace { end_pos: end, ..Trace::default() };
+
+        if content_mask & CONTENTS_SOLID != 0 {
+            for (e, bounds) in self.bounds.iter().filter(|(e, _)| *e == entity) {
+                if BspFile::trace_aabb(bounds, &start, &end, Some(&box_extents), &mut trace) {
+                    trace.entity = Some(*e);
+                }
+            }
+        }
+
+        trace
+    }
+
+    /// Check whether a box overlaps anything. The returned trace has start_solid set if so, along with the contents & entity (if any) which were overlapped
+    pub fn overlap_box(self: &Self, content_mask: u32, center: Vector3, box_extents: Vector3, ignore: &[Entity]) -> Trace {
+        let mut trace = self.map.boxtrace(0, content_mask, center, center, box_extents);
+
+        if trace.start_solid {
+            return trace;
+        }
+
+        for mapmodel in &self.mapmodels {
+            if ignore.contains(&mapmodel.entity) {
+                continue;
+            }
+
+            let local_center = mapmodel.world2local.transform_point(center);
+            let tr = self.map.boxtrace(mapmodel.model_idx + 1, content_mask, local_center, local_center, box_extents);
+
+            if tr.start_solid {
+                trace = tr;
+                trace.end_pos = center;
+                trace.entity = Some(mapmodel.entity);
+                return trace;
+            }
+        }
+
+        if content_mask & CONTENTS_SOLID != 0 {
+            let box_bounds = AABB::center_extents(center, box_extents);
+
+            for (e, bounds) in &self.bounds {
+                if !ignore.contains(e) && aabb_aabb_intersects(&box_bounds, bounds) {
+                    trace.start_solid = true;
+                    trace.contents = CONTENTS_SOLID;
+                    trace.entity = Some(*e);
+                    return trace;
+                }
+            }
+        }
+
+        trace
+    }
+
+    /// Get the CONTENTS_* flags at the given point, combining the map, map models & entity bounds, masked by the given content mask
+    pub fn point_contents(self: &Self, content_mask: u32, point: Vector3, ignore: &[Entity]) -> u32 {
+        let mut contents = self.map.point_contents(&point);
+
+        for mapmodel in &self.mapmodels {
+            if !ignore.contains(&mapmodel.entity) {
+                contents |= self.map.model_point_contents(mapmodel.model_idx + 1, &mapmodel.world2local.transform_point(point));
+            }
+        }
+
+        let point_bounds = AABB::center_extents(point, Vector3::zero());
+
+        for (e, bounds) in &self.bounds {
+            if !ignore.contains(e) && aabb_aabb_intersects(&point_bounds, bounds) {
+                contents |= CONTENTS_SOLID;
+            }
+        }
+
+        contents & content_mask
+    }
+
+    /// Sweep a box through the world, stopping at the first surface it hits. See BspFile::trace_move
+    pub fn sweep(self: &Self, content_mask: u32, start_pos: Vector3, velocity: Vector3, delta: f32, box_extents: Vector3, ignore: &[Entity]) -> (Vector3, Vector3, Trace) {
+        self.map.trace_move(&start_pos, &velocity, delta, false, box_extents, |_, start, end, box_extents| {
+            self.boxcast(content_mask, *start, *end, *box_extents, ignore)
+        })
+    }
+
+    /// Sweep a box through the world, sliding along any surfaces it hits. See BspFile::trace_move
+    pub fn slide(self: &Self, content_mask: u32, start_pos: Vector3, velocity: Vector3, delta: f32, box_extents: Vector3, ignore: &[Entity]) -> (Vector3, Vector3, Trace) {
+        self.map.trace_move(&start_pos, &velocity, delta, true, box_extents, |_, start, end, box_extents| {
+            self.boxcast(content_mask, *start, *end, *box_extents, ignore)
+        })
+    }
+}
@@ -0,0 +1,478 @@
+use std::{collections::{HashMap, HashSet}, fmt::Display, fs};
+
+use hecs::{Entity, World};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{component::{basicanim::{BasicAnim, BasicLerpAnim}, camera::FPCamera, charactercontroller::{CharacterInputState, CharacterState}, door::{Door, DoorLink}, effect::Effect, fpview::FPView, health::Health, light::SwitchableLight, plat::Plat, rotator::Rotator, soundsource::SoundSource, spawnindex::SpawnIndex, train::Train, transform3d::Transform3D, triggerable::{TriggerLink, TriggerState, TriggerVolume}}, gamestate::TimeData, math::{Quaternion, Vector3}};
+
+pub const SAVE_DIR: &str = "saves";
+
+#[derive(Debug)]
+pub enum SaveError {
+    IOError(std::io::Error),
+    SerializeError(ron::Error),
+    DeserializeError(ron::error::SpannedError),
+    InvalidSlot(String),
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::IOError(e) => write!(f, "IO error: {}", e),
+            SaveError::SerializeError(e) => write!(f, "Failed serializing save: {}", e),
+            SaveError::DeserializeError(e) => write!(f, "Failed parsing save: {}", e),
+            SaveError::InvalidSlot(slot) => write!(f, "Invalid save slot name: {}", slot),
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(value: std::io::Error) -> Self {
+        SaveError::IOError(value)
+    }
+}
+
+/// Snapshot of game state which can be written to & restored from a save slot
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub map_name: String,
+    pub time_data: TimeData,
+    /// Number of local players, which must match when loading so that player entities are respawned with the same IDs
+    #[serde(default = "default_num_players")]
+    pub num_players: usize,
+    pub entities: Vec<SavedEntity>,
+}
+
+fn default_num_players() -> usize {
+    1
+}
+
+/// Saved component state of a single entity. Entities & entity references are stored as spawn indices, which stay valid across save & load
+/// because loading respawns the map's entities in the same order they were originally spawned in
+#[derive(Serialize, Deserialize, Default)]
+pub struct SavedEntity {
+    pub spawn_index: u32,
+    pub transform: Option<SavedTransform>,
+    pub character_state: Option<SavedCharacterState>,
+    pub fpview: Option<FPView>,
+    pub health: Option<Health>,
+    pub door: Option<Door>,
+    pub door_link: Option<Vec<u32>>,
+    pub trigger_state: Option<TriggerState>,
+    pub trigger_link: Option<SavedTriggerLink>,
+    pub trigger_volume: Option<SavedTriggerVolume>,
+    pub plat: Option<Plat>,
+    pub switchable_light: Option<SwitchableLight>,
+    pub train: Option<SavedTrain>,
+    pub rotator: Option<Rotator>,
+    pub fpcamera_follow: Option<u32>,
+    pub basic_anim_time: Option<f32>,
+    pub basic_lerp_anim: Option<SavedBasicLerpAnim>,
+    pub effect_emit: Option<bool>,
+    pub sound_source: Option<SavedSoundSource>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedTransform {
+    pub position: Vector3,
+    pub scale: Vector3,
+    pub rotation: [f32;4],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedCharacterState {
+    pub height: f32,
+    pub velocity: Vector3,
+    pub grounded: bool,
+    pub crouched: bool,
+    pub ground_entity: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedTriggerLink {
+    pub targets: Vec<u32>,
+    pub killtargets: Vec<u32>,
+    pub delay: f32,
+    pub delay_timer: f32,
+    pub pending: bool,
+    pub output: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedTriggerVolume {
+    pub wait_timer: f32,
+    pub fired: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedTrain {
+    pub move_speed: f32,
+    pub cur_speed: f32,
+    pub target: Option<u32>,
+    pub corner_offset: Vector3,
+    pub wait_timer: f32,
+    pub stopped: bool,
+    pub started: bool,
+    pub prev_triggered: bool,
+    pub dmg: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedBasicLerpAnim {
+    pub animation1_time: f32,
+    pub animation2_time: f32,
+    pub mix: f32,
+}
+
+/// Playback state of a sound source. Active sounds restart from the beginning when loaded
+#[derive(Serialize, Deserialize)]
+pub struct SavedSoundSource {
+    pub active: bool,
+    pub looping: bool,
+    pub prev_triggered: bool,
+}
+
+// spawn index of an entity, or None if it was spawned at runtime rather than when the map was loaded
+fn spawn_index(world: &World, e: Entity) -> Option<u32> {
+    world.get::<&SpawnIndex>(e).ok().map(|x| x.index)
+}
+
+// look up an entity by saved spawn index, returning None if it no longer exists
+fn resolve_entity(indices: &HashMap<u32, Entity>, index: u32) -> Option<Entity> {
+    indices.get(&index).copied()
+}
+
+fn resolve_entities(indices: &HashMap<u32, Entity>, ids: &[u32]) -> Vec<Entity> {
+    ids.iter().filter_map(|x| resolve_entity(indices, *x)).collect()
+}
+
+/// Get the path of the file backing a save slot. Slots must be plain file names so that saves can't be written outside of the saves directory
+pub fn save_path(slot: &str) -> Result<String, SaveError> {
+    if slot.is_empty() || slot.contains(['/', '\\']) || slot.contains("..") {
+        return Err(SaveError::InvalidSlot(slot.to_string()));
+    }
+
+    Ok(format!("{}/{}.ron", SAVE_DIR, slot))
+}
+
+/// Capture the state of every entity in the world which was spawned when the map was loaded
+pub fn capture_world(world: &World, map_name: &str, time_data: &TimeData, num_players: usize) -> SaveGame {
+    let mut entities = Vec::new();
+
+    for entity_ref in world.iter() {
+        // entities spawned at runtime can't be matched up with anything after reloading the map, so they aren't saved
+        let index = match entity_ref.get::<&SpawnIndex>() {
+            Some(v) => v.index,
+            None => continue
+        };
+
+        let mut saved = SavedEntity { spawn_index: index, ..Default::default() };
+
+        if let Some(transform) = entity_ref.get::<&Transform3D>() {
+            saved.transform = Some(SavedTransform {
+                position: transform.position,
+                scale: transform.scale,
+                rotation: [transform.rotation.x, transform.rotation.y, transform.rotation.z, transform.rotation.w],
+            });
+        }
+
+        if let Some(cstate) = entity_ref.get::<&CharacterState>() {
+            saved.character_state = Some(SavedCharacterState {
+                height: cstate.height,
+                velocity: cstate.velocity,
+                grounded: cstate.grounded,
+                crouched: cstate.crouched,
+                ground_entity: cstate.ground_entity.and_then(|x| spawn_index(world, x)),
+            });
+        }
+
+        if let Some(fpview) = entity_ref.get::<&FPView>() {
+            saved.fpview = Some(*fpview);
+        }
+
+        if let Some(health) = entity_ref.get::<&Health>() {
+            saved.health = Some((*health).clone());
+        }
+
+        if let Some(door) = entity_ref.get::<&Door>() {
+            saved.door = Some((*door).clone());
+        }
+
+        if let Some(door_link) = entity_ref.get::<&DoorLink>() {
+            saved.door_link = Some(door_link.links.iter().filter_map(|x| spawn_index(world, *x)).collect());
+        }
+
+        if let Some(state) = entity_ref.get::<&TriggerState>() {
+            saved.trigger_state = Some((*state).clone());
+        }
+
+        if let Some(link) = entity_ref.get::<&TriggerLink>() {
+            saved.trigger_link = Some(SavedTriggerLink {
+                targets: link.targets.iter().filter_map(|x| spawn_index(world, *x)).collect(),
+                killtargets: link.killtargets.iter().filter_map(|x| spawn_index(world, *x)).collect(),
+                delay: link.delay,
+                delay_timer: link.delay_timer,
+                pending: link.pending,
+                output: link.output,
+            });
+        }
+
+        if let Some(volume) = entity_ref.get::<&TriggerVolume>() {
+            saved.trigger_volume = Some(SavedTriggerVolume { wait_timer: volume.wait_timer, fired: volume.fired });
+        }
+
+        if let Some(plat) = entity_ref.get::<&Plat>() {
+            saved.plat = Some((*plat).clone());
+        }
+
+        if let Some(light) = entity_ref.get::<&SwitchableLight>() {
+            saved.switchable_light = Some((*light).clone());
+        }
+
+        if let Some(train) = entity_ref.get::<&Train>() {
+            saved.train = Some(SavedTrain {
+                move_speed: train.move_speed,
+                cur_speed: train.cur_speed,
+                target: train.target.and_then(|x| spawn_index(world, x)),
+                corner_offset: train.corner_offset,
+                wait_timer: train.wait_timer,
+                stopped: train.stopped,
+                started: train.started,
+                prev_triggered: train.prev_triggered,
+                dmg: train.dmg,
+            });
+        }
+
+        if let Some(rotator) = entity_ref.get::<&Rotator>() {
+            saved.rotator = Some(*rotator);
+        }
+
+        if let Some(fpcam) = entity_ref.get::<&FPCamera>() {
+            saved.fpcamera_follow = spawn_index(world, fpcam.follow_entity);
+        }
+
+        if let Some(anim) = entity_ref.get::<&BasicAnim>() {
+            saved.basic_anim_time = Some(anim.animation_time);
+        }
+
+        if let Some(anim) = entity_ref.get::<&BasicLerpAnim>() {
+            saved.basic_lerp_anim = Some(SavedBasicLerpAnim {
+                animation1_time: anim.animation1_time,
+                animation2_time: anim.animation2_time,
+                mix: anim.mix,
+            });
+        }
+
+        if let Some(effect) = entity_ref.get::<&Effect>() {
+            saved.effect_emit = Some(effect.instance.enable_emit);
+        }
+
+        if let Some(source) = entity_ref.get::<&SoundSource>() {
+            saved.sound_source = Some(SavedSoundSource {
+                active: source.active,
+                looping: source.looping,
+                prev_triggered: source.prev_triggered,
+            });
+        }
+
+        entities.push(saved);
+    }
+
+    SaveGame {
+        map_name: map_name.to_owned(),
+        time_data: *time_data,
+        num_players,
+        entities,
+    }
+}
+
+/// Apply saved entity state to a world which has just had the saved map loaded into it.
+/// Entities which were spawned by the map but no longer existed when the game was saved are despawned
+pub fn restore_world(save: &SaveGame, world: &mut World) {
+    // despawn any entities which didn't exist at save time
+    let saved_ids = save.entities.iter().map(|x| x.spawn_index).collect::<HashSet<_>>();
+    let removed = world.query_mut::<&SpawnIndex>()
+        .into_iter()
+        .filter(|(_, x)| !saved_ids.contains(&x.index))
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+
+    for e in removed {
+        world.despawn(e).unwrap();
+    }
+
+    let indices = world.query_mut::<&SpawnIndex>()
+        .into_iter()
+        .map(|(e, x)| (x.index, e))
+        .collect::<HashMap<_, _>>();
+
+    for saved in &save.entities {
+        let e = match resolve_entity(&indices, saved.spawn_index) {
+            Some(v) => v,
+            None => {
+                warn!("Saved entity {} does not exist after reloading map, skipping", saved.spawn_index);
+                continue;
+            }
+        };
+
+        if let Some(transform) = &saved.transform {
+            let [x, y, z, w] = transform.rotation;
+            world.insert_one(e, Transform3D {
+                position: transform.position,
+                scale: transform.scale,
+                rotation: Quaternion::new(x, y, z, w),
+            }).unwrap();
+        }
+
+        if let Some(cstate) = &saved.character_state {
+            let ground_entity = cstate.ground_entity.and_then(|x| resolve_entity(&indices, x));
+            world.insert(e, (
+                CharacterState {
+                    height: cstate.height,
+                    velocity: cstate.velocity,
+                    grounded: cstate.grounded,
+                    crouched: cstate.crouched,
+                    ground_entity,
+                    ..CharacterState::new(cstate.height)
+                },
+                CharacterInputState::default()
+            )).unwrap();
+        }
+
+        if let Some(fpview) = saved.fpview {
+            world.insert_one(e, fpview).unwrap();
+        }
+
+        if let Some(health) = &saved.health {
+            world.insert_one(e, health.clone()).unwrap();
+        }
+
+        if let Some(door) = &saved.door {
+            world.insert_one(e, door.clone()).unwrap();
+        }
+
+        if let Some(links) = &saved.door_link {
+            let links = resolve_entities(&indices, links);
+            world.insert_one(e, DoorLink { links }).unwrap();
+        }
+
+        if let Some(state) = &saved.trigger_state {
+            world.insert_one(e, state.clone()).unwrap();
+        }
+
+        if let Some(link) = &saved.trigger_link {
+            let targets = resolve_entities(&indices, &link.targets);
+            let killtargets = resolve_entities(&indices, &link.killtargets);
+
+            world.insert_one(e, TriggerLink {
+                targets,
+                killtargets,
+                delay: link.delay,
+                delay_timer: link.delay_timer,
+                pending: link.pending,
+                output: link.output,
+            }).unwrap();
+        }
+
+        if let Some(saved_volume) = &saved.trigger_volume {
+            if let Ok(mut volume) = world.get::<&mut TriggerVolume>(e) {
+                volume.wait_timer = saved_volume.wait_timer;
+                volume.fired = saved_volume.fired;
+            }
+        }
+
+        if let Some(plat) = &saved.plat {
+            world.insert_one(e, plat.clone()).unwrap();
+        }
+
+        if let Some(light) = &saved.switchable_light {
+            world.insert_one(e, light.clone()).unwrap();
+        }
+
+        if let Some(train) = &saved.train {
+            let target = train.target.and_then(|x| resolve_entity(&indices, x));
+            world.insert_one(e, Train {
+                move_speed: train.move_speed,
+                cur_speed: train.cur_speed,
+                target,
+                corner_offset: train.corner_offset,
+                wait_timer: train.wait_timer,
+                stopped: train.stopped,
+                started: train.started,
+                prev_triggered: train.prev_triggered,
+                dmg: train.dmg,
+            }).unwrap();
+        }
+
+        if let Some(rotator) = saved.rotator {
+            world.insert_one(e, rotator).unwrap();
+        }
+
+        if let Some(follow) = saved.fpcamera_follow {
+            match resolve_entity(&indices, follow) {
+                Some(follow_entity) => {
+                    world.insert_one(e, FPCamera::new(follow_entity)).unwrap();
+                }
+                None => {
+                    warn!("FPCamera follow target {} does not exist after reloading map", follow);
+                }
+            }
+        }
+
+        if let Some(anim_time) = saved.basic_anim_time {
+            if let Ok(mut anim) = world.get::<&mut BasicAnim>(e) {
+                anim.animation_time = anim_time;
+            }
+        }
+
+        if let Some(saved_anim) = &saved.basic_lerp_anim {
+            if let Ok(mut anim) = world.get::<&mut BasicLerpAnim>(e) {
+                anim.animation1_time = saved_anim.animation1_time;
+                anim.animation2_time = saved_anim.animation2_time;
+                anim.mix = saved_anim.mix;
+            }
+        }
+
+        if let Some(enable_emit) = saved.effect_emit {
+            if let Ok(mut effect) = world.get::<&mut Effect>(e) {
+                effect.instance.enable_emit = enable_emit;
+            }
+        }
+
+        if let Some(saved_source) = &saved.sound_source {
+            if let Ok(mut source) = world.get::<&mut SoundSource>(e) {
+                source.active = saved_source.active;
+                source.looping = saved_source.looping;
+                source.prev_triggered = saved_source.prev_triggered;
+                source.voice = None;
+            }
+        }
+    }
+}
+
+/// Write a save to the given slot, creating the saves directory if necessary
+pub fn write_save(slot: &str, save: &SaveGame) -> Result<(), SaveError> {
+    let path = save_path(slot)?;
+    fs::create_dir_all(SAVE_DIR)?;
+
+    let save_str = match ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default()) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(SaveError::SerializeError(e));
+        }
+    };
+
+    fs::write(path, save_str)?;
+    Ok(())
+}
+
+/// Read a save from the given slot
+pub fn read_save(slot: &str) -> Result<SaveGame, SaveError> {
+    let save_str = fs::read_to_string(save_path(slot)?)?;
+
+    match ron::from_str::<SaveGame>(&save_str) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(SaveError::DeserializeError(e))
+    }
+}
@@ -1,6 +1,6 @@
 use std::{fmt::Formatter, ops::{Deref, DerefMut}};
 
-use serde::{de::Visitor, Deserialize};
+use serde::{de::Visitor, ser::SerializeTuple, Deserialize, Serialize};
 
 use crate::{asset_loader::{load_material, load_model, load_shader, load_texture, MaterialHandle, ModelHandle, ShaderHandle, TextureHandle}, graphics::anim::{AnimationCurveInterpolationMode, AnimationCurvePoint, Color32Curve, FloatCurve, Vector2Curve, Vector3Curve}, math::{Quaternion, Vector2, Vector3, Vector4}, misc::Color32};
 
@@ -129,6 +129,16 @@ impl<'de> Deserialize<'de> for Vector3 {
     }
 }
 
+impl Serialize for Vector3 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        let mut tuple = serializer.serialize_tuple(3)?;
+        tuple.serialize_element(&self.x)?;
+        tuple.serialize_element(&self.y)?;
+        tuple.serialize_element(&self.z)?;
+        tuple.end()
+    }
+}
+
 struct Vector4Visitor;
 impl<'de> Visitor<'de> for Vector4Visitor {
     type Value = Vector4;
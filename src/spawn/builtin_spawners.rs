@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+
+use log::{info, warn};
+
+use crate::{asset_loader::{load_effect, load_model, load_sound}, component::{door::Door, effect::Effect, light::{Light, SwitchableLight}, mapmodel::MapModel, plat::{Plat, PlatState}, rendermesh::RenderMesh, rotator::Rotator, soundsource::SoundSource, train::{PathCorner, Train}, transform3d::Transform3D, triggerable::{TriggerState, TriggerVolume}}, lightstyle::{is_valid_pattern, set_lightstyle}, math::{Quaternion, Vector3}, misc::AABB, parse_utils};
+
+use super::spawn_registry::{SpawnContext, SpawnRegistry};
+
+pub fn register_builtin_spawners(registry: &mut SpawnRegistry) {
+    registry.register("info_player_start", spawn_info_player_start);
+    registry.register("info_player_coop", spawn_info_player_coop);
+    registry.register("worldspawn", spawn_worldspawn);
+    registry.register("prop_dynamic", spawn_prop_dynamic);
+    registry.register("env_effect", spawn_env_effect);
+    registry.register("light", spawn_light);
+    registry.register("func_door", spawn_func_door);
+    registry.register("func_explosive", spawn_func_static);
+    registry.register("func_wall", spawn_func_static);
+    registry.register("func_object", spawn_func_static);
+    registry.register("func_plat", spawn_func_plat);
+    registry.register("func_rotating", spawn_func_rotating);
+    registry.register("func_train", spawn_func_train);
+    registry.register("path_corner", spawn_path_corner);
+    registry.register("trigger_once", spawn_trigger_once);
+    registry.register("trigger_multiple", spawn_trigger_multiple);
+    registry.register("trigger_relay", spawn_trigger_relay);
+    registry.register("target_speaker", spawn_target_speaker);
+}
+
+fn parse_player_start(entity_data: &HashMap<&str, &str>) -> (Vector3, f32) {
+    let pos = parse_utils::parse_prop_vec3(entity_data, "origin", Vector3::zero());
+    let rot = parse_utils::parse_prop::<f32>(entity_data, "angle", 0.0) + 180.0;
+
+    (pos, rot)
+}
+
+fn spawn_info_player_start(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    ctx.player_starts.push(parse_player_start(entity_data));
+}
+
+fn spawn_info_player_coop(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    ctx.coop_starts.push(parse_player_start(entity_data));
+}
+
+fn spawn_worldspawn(entity_data: &HashMap<&str, &str>, _ctx: &mut SpawnContext) {
+    for (key, val) in entity_data {
+        info!("worldspawn: {} = {}", key, val);
+
+        // lightstyle<N> keys override the pattern of lightstyle N
+        if let Some(style) = key.strip_prefix("lightstyle") {
+            match style.parse::<usize>() {
+                Ok(v) => set_lightstyle(v, val),
+                Err(_) => warn!("Invalid worldspawn lightstyle key: {}", key)
+            }
+        }
+    }
+}
+
+fn spawn_prop_dynamic(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let pos = parse_utils::parse_prop_vec3(entity_data, "origin", Vector3::zero());
+    let angles = parse_utils::parse_prop_vec3(entity_data, "angles", Vector3::zero());
+    let model_path = parse_utils::get_prop_str(entity_data, "model", "");
+    let scale = parse_utils::parse_prop_vec3(entity_data, "scale", Vector3::new(1.0, 1.0, 1.0));
+
+    let rot = Quaternion::from_euler(Vector3::new(angles.x.to_radians(), angles.z.to_radians(), angles.y.to_radians()));
+
+    let e = ctx.world.spawn((
+        Transform3D::default().with_position(pos).with_rotation(rot).with_scale(scale),
+    ));
+
+    // the model is purely visual (and needs GL to load), so leave it off when running headless
+    if !ctx.map_data.is_headless() {
+        let model = load_model(format!("content/{}", model_path).as_str()).unwrap();
+        ctx.world.insert_one(e, RenderMesh::new(model)).unwrap();
+    }
+}
+
+fn spawn_env_effect(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let pos = parse_utils::parse_prop_vec3(entity_data, "origin", Vector3::zero());
+    let angles = parse_utils::parse_prop_vec3(entity_data, "angles", Vector3::zero());
+    let effect_path = parse_utils::get_prop_str(entity_data, "effect", "");
+    let scale = parse_utils::parse_prop_vec3(entity_data, "scale", Vector3::new(1.0, 1.0, 1.0));
+    let world_space = parse_utils::parse_prop::<i32>(entity_data, "worldspace", 1) != 0;
+
+    let rot = Quaternion::from_euler(Vector3::new(angles.x.to_radians(), angles.z.to_radians(), angles.y.to_radians()));
+
+    let e = ctx.world.spawn((
+        Transform3D::default().with_position(pos).with_rotation(rot).with_scale(scale),
+    ));
+
+    // effect instances allocate GL buffers, so leave the effect off when running headless
+    if !ctx.map_data.is_headless() {
+        let effect = load_effect(format!("content/{}", effect_path).as_str()).unwrap();
+        ctx.world.insert_one(e, Effect::new(&effect, true, world_space)).unwrap();
+    }
+}
+
+fn spawn_light(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let light_pos = parse_utils::parse_prop_vec3(entity_data, "origin", Vector3::zero());
+    let light_intensity = parse_utils::parse_prop::<f32>(entity_data, "light", 300.0);
+    let light_color = parse_utils::parse_prop_vec3(entity_data, "_color", Vector3::new(1.0, 1.0, 1.0));
+    let dynamic = parse_utils::parse_prop::<i32>(entity_data, "dynamic", 1) != 0;
+
+    // lights are also lit at runtime unless opted out with "dynamic" "0" (e.g. for lights which only need to be baked into the lightmap & light probes)
+    if dynamic {
+        ctx.world.spawn((
+            Transform3D::default().with_position(light_pos),
+            Light { color: light_color, max_radius: light_intensity }
+        ));
+    }
+
+    // lights with a targetname were baked into their own switchable light layer
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+    if target_name.is_empty() {
+        return;
+    }
+
+    let style = parse_utils::parse_prop::<usize>(entity_data, "style", 0);
+    let layer = match ctx.switchable_light_layer(target_name, style) {
+        Some(v) => v,
+        None => return
+    };
+
+    let spawn_flags = parse_utils::parse_prop::<u32>(entity_data, "spawnflags", 0);
+    let start_off = spawn_flags & 1 != 0;
+
+    let pattern = parse_utils::get_prop_str(entity_data, "pattern", "");
+    let off_pattern = parse_utils::get_prop_str(entity_data, "offpattern", "a");
+    let fade_time = parse_utils::parse_prop::<f32>(entity_data, "fade", 0.0);
+
+    if !pattern.is_empty() && !is_valid_pattern(pattern) {
+        warn!("Light {} has invalid pattern: {}", target_name, pattern);
+    }
+
+    if !is_valid_pattern(off_pattern) {
+        warn!("Light {} has invalid offpattern: {}", target_name, off_pattern);
+    }
+
+    let e = ctx.world.spawn((
+        SwitchableLight {
+            layer,
+            pattern: if is_valid_pattern(pattern) { Some(pattern.to_owned()) } else { None },
+            off_pattern: if is_valid_pattern(off_pattern) { off_pattern.to_owned() } else { "a".to_owned() },
+            fade_time,
+            on: !start_off,
+            fade: if start_off { 0.0 } else { 1.0 },
+            prev_triggered: false,
+        },
+        TriggerState { triggered: false },
+    ));
+
+    ctx.register_targetname(target_name, e);
+}
+
+fn spawn_func_door(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let model_idx = parse_utils::parse_prop_modelindex(entity_data, "model", usize::MAX);
+    let submodel = &ctx.map_data.map.submodel_lump.submodels[model_idx + 1];
+    let pos = submodel.origin;
+    let size = submodel.maxs - submodel.mins;
+
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+    let target = parse_utils::get_prop_str(entity_data, "target", "");
+    let killtarget = parse_utils::get_prop_str(entity_data, "killtarget", "");
+    let delay = parse_utils::parse_prop::<f32>(entity_data, "delay", 0.0);
+
+    let auto_open = target_name.is_empty();
+
+    let angle = parse_utils::parse_prop::<i32>(entity_data, "angle", 0);
+    let speed = parse_utils::parse_prop::<f32>(entity_data, "speed", 100.0);
+    let lip = parse_utils::parse_prop::<f32>(entity_data, "lip", 0.0);
+    let dmg = parse_utils::parse_prop::<f32>(entity_data, "dmg", 2.0);
+    let crusher = parse_utils::parse_prop::<i32>(entity_data, "crusher", 0) != 0;
+
+    let spawn_flags = parse_utils::parse_prop::<u32>(entity_data, "spawnflags", 0);
+
+    let move_dir = if angle == -1 {
+        Vector3::new(0.0, 0.0, 1.0)
+    }
+    else if angle == -2 {
+        Vector3::new(0.0, 0.0, -1.0)
+    }
+    else {
+        let r = (angle as f32).to_radians();
+        let sx = r.cos();
+        let sy = r.sin();
+
+        Vector3::new(sx, sy, 0.0)
+    };
+
+    // calculate move distance along direction
+    let move_dist = (move_dir.x.abs() * size.x +
+        move_dir.y.abs() * size.y +
+        move_dir.z.abs() * size.z) - lip;
+
+    let open_pos = pos + (move_dir * move_dist);
+
+    let e = ctx.world.spawn((
+        Transform3D::default().with_position(pos),
+        Door { auto_open, open_pos, close_pos: pos, move_speed: speed, dmg, crusher, reverse_timer: 0.0 },
+        TriggerState { triggered: false },
+        MapModel { model_idx }
+    ));
+
+    ctx.link_targets(e, target, killtarget, delay);
+    ctx.register_targetname(target_name, e);
+
+    // don't link doors if they have the "don't link" spawn flag set
+    if spawn_flags & 4 == 0 {
+        ctx.door_bounds.push((e, AABB::min_max(submodel.mins, submodel.maxs)));
+    }
+}
+
+fn spawn_func_plat(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let model_idx = parse_utils::parse_prop_modelindex(entity_data, "model", usize::MAX);
+    let submodel = &ctx.map_data.map.submodel_lump.submodels[model_idx + 1];
+    let pos = submodel.origin;
+
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+
+    let speed = parse_utils::parse_prop::<f32>(entity_data, "speed", 150.0);
+    let wait = parse_utils::parse_prop::<f32>(entity_data, "wait", 3.0);
+    let lip = parse_utils::parse_prop::<f32>(entity_data, "lip", 8.0);
+    let dmg = parse_utils::parse_prop::<f32>(entity_data, "dmg", 2.0);
+
+    // plats are built in their raised position & lower by either their height or their own size minus lip
+    let height = parse_utils::parse_prop::<f32>(entity_data, "height", (submodel.maxs.z - submodel.mins.z) - lip);
+    let bottom_pos = pos - (Vector3::unit_z() * height);
+
+    let e = ctx.world.spawn((
+        Transform3D::default().with_position(pos),
+        Plat { top_pos: pos, bottom_pos, move_speed: speed, wait, wait_timer: 0.0, state: PlatState::Top, dmg },
+        MapModel { model_idx }
+    ));
+
+    if !target_name.is_empty() {
+        ctx.world.insert_one(e, TriggerState { triggered: false }).unwrap();
+        ctx.register_targetname(target_name, e);
+    }
+}
+
+fn spawn_func_train(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let model_idx = parse_utils::parse_prop_modelindex(entity_data, "model", usize::MAX);
+    let submodel = &ctx.map_data.map.submodel_lump.submodels[model_idx + 1];
+    let pos = submodel.origin;
+
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+    let target = parse_utils::get_prop_str(entity_data, "target", "");
+
+    let speed = parse_utils::parse_prop::<f32>(entity_data, "speed", 100.0);
+    let dmg = parse_utils::parse_prop::<f32>(entity_data, "dmg", 100.0);
+
+    let e = ctx.world.spawn((
+        Transform3D::default().with_position(pos),
+        Train {
+            move_speed: speed,
+            cur_speed: speed,
+            target: None,
+            corner_offset: submodel.mins,
+            wait_timer: 0.0,
+            stopped: false,
+            started: false,
+            prev_triggered: false,
+            dmg,
+        },
+        MapModel { model_idx }
+    ));
+
+    if !target.is_empty() {
+        ctx.pending_path_targets.push((e, target.to_owned()));
+    }
+
+    // trains with a targetname don't move until triggered
+    if !target_name.is_empty() {
+        ctx.world.insert_one(e, TriggerState { triggered: false }).unwrap();
+        ctx.register_targetname(target_name, e);
+    }
+}
+
+fn spawn_path_corner(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let pos = parse_utils::parse_prop_vec3(entity_data, "origin", Vector3::zero());
+
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+    let target = parse_utils::get_prop_str(entity_data, "target", "");
+
+    let wait = parse_utils::parse_prop::<f32>(entity_data, "wait", 0.0);
+    let speed = parse_utils::parse_prop::<f32>(entity_data, "speed", 0.0);
+
+    let e = ctx.world.spawn((
+        Transform3D::default().with_position(pos),
+        PathCorner { next: None, wait, speed }
+    ));
+
+    if !target.is_empty() {
+        ctx.pending_path_targets.push((e, target.to_owned()));
+    }
+
+    ctx.register_targetname(target_name, e);
+}
+
+fn spawn_trigger_volume(entity_data: &HashMap<&str, &str>, wait: f32, ctx: &mut SpawnContext) {
+    let model_idx = parse_utils::parse_prop_modelindex(entity_data, "model", usize::MAX);
+    let submodel = &ctx.map_data.map.submodel_lump.submodels[model_idx + 1];
+    let bounds = AABB::min_max(submodel.mins, submodel.maxs);
+
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+    let target = parse_utils::get_prop_str(entity_data, "target", "");
+    let killtarget = parse_utils::get_prop_str(entity_data, "killtarget", "");
+    let delay = parse_utils::parse_prop::<f32>(entity_data, "delay", 0.0);
+
+    let e = ctx.world.spawn((
+        TriggerVolume { bounds, wait, wait_timer: 0.0, fired: false },
+        TriggerState { triggered: false },
+    ));
+
+    ctx.link_targets(e, target, killtarget, delay);
+    ctx.register_targetname(target_name, e);
+}
+
+fn spawn_trigger_once(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    spawn_trigger_volume(entity_data, -1.0, ctx);
+}
+
+fn spawn_trigger_multiple(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let wait = parse_utils::parse_prop::<f32>(entity_data, "wait", 0.2);
+    spawn_trigger_volume(entity_data, wait, ctx);
+}
+
+// relays pass on their own trigger state to their targets after a delay
+fn spawn_trigger_relay(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+    let target = parse_utils::get_prop_str(entity_data, "target", "");
+    let killtarget = parse_utils::get_prop_str(entity_data, "killtarget", "");
+    let delay = parse_utils::parse_prop::<f32>(entity_data, "delay", 0.0);
+
+    let e = ctx.world.spawn((
+        TriggerState { triggered: false },
+    ));
+
+    ctx.link_targets(e, target, killtarget, delay);
+    ctx.register_targetname(target_name, e);
+}
+
+// speakers play a sound from content/sounds. Looped speakers can start on or off & toggle when triggered, while
+// non-looped speakers play once each time they are triggered
+fn spawn_target_speaker(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let pos = parse_utils::parse_prop_vec3(entity_data, "origin", Vector3::zero());
+    let noise = parse_utils::get_prop_str(entity_data, "noise", "");
+    let volume = parse_utils::parse_prop::<f32>(entity_data, "volume", 1.0);
+    let attenuation = parse_utils::parse_prop::<f32>(entity_data, "attenuation", 1.0);
+    let spawn_flags = parse_utils::parse_prop::<u32>(entity_data, "spawnflags", 0);
+    let target_name = parse_utils::get_prop_str(entity_data, "targetname", "");
+
+    let sound = match load_sound(format!("content/sounds/{}", noise).as_str()) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Speaker failed to load sound {}: {:?}", noise, e);
+            return;
+        }
+    };
+
+    let looped_on = spawn_flags & 1 != 0;
+    let looped_off = spawn_flags & 2 != 0;
+
+    // attenuation -1 plays everywhere, otherwise larger values make the sound fall off faster
+    let radius = if attenuation <= 0.0 { 0.0 } else { 1000.0 / attenuation };
+
+    let e = ctx.world.spawn((
+        Transform3D::default().with_position(pos),
+        SoundSource::new(sound, looped_on || looped_off, volume, radius).with_active(looped_on),
+        TriggerState { triggered: false },
+    ));
+
+    ctx.register_targetname(target_name, e);
+}
+
+// brush entities which don't do anything yet besides existing in the world
+fn spawn_func_static(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let model_idx = parse_utils::parse_prop_modelindex(entity_data, "model", usize::MAX);
+    let submodel = &ctx.map_data.map.submodel_lump.submodels[model_idx + 1];
+    let pos = submodel.origin;
+
+    ctx.world.spawn((
+        Transform3D::default().with_position(pos),
+        MapModel { model_idx }
+    ));
+}
+
+fn spawn_func_rotating(entity_data: &HashMap<&str, &str>, ctx: &mut SpawnContext) {
+    let model_idx = parse_utils::parse_prop_modelindex(entity_data, "model", usize::MAX);
+    let submodel = &ctx.map_data.map.submodel_lump.submodels[model_idx + 1];
+    let spawn_flags = parse_utils::parse_prop::<u32>(entity_data, "spawnflags", 0);
+    let pos = parse_utils::parse_prop_vec3(entity_data, "origin", submodel.origin);
+    let speed = parse_utils::parse_prop::<f32>(entity_data, "speed", 0.0);
+    let dmg = parse_utils::parse_prop::<f32>(entity_data, "dmg", 0.0);
+
+    let axis = if spawn_flags & 4 != 0 {
+        Vector3::unit_x()
+    }
+    else if spawn_flags & 8 != 0 {
+        Vector3::unit_y()
+    }
+    else {
+        Vector3::unit_z()
+    };
+
+    ctx.world.spawn((
+        Transform3D::default().with_position(pos),
+        Rotator::new(axis, speed).with_dmg(dmg),
+        MapModel { model_idx }
+    ));
+}
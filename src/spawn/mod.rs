@@ -0,0 +1,2 @@
+pub mod spawn_registry;
+pub mod builtin_spawners;
@@ -0,0 +1,260 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use hecs::{CommandBuffer, Entity, World};
+use lazy_static::lazy_static;
+use log::warn;
+
+use crate::{bsp::{bspcommon::aabb_aabb_intersects, bspfile::BspFile}, component::{door::DoorLink, train::{PathCorner, Train}, triggerable::TriggerLink}, gamestate::MapData, math::Vector3, misc::AABB, system::render_system::{CUSTOM_LIGHT_LAYER_START, NUM_CUSTOM_LIGHT_LAYERS}};
+
+use super::builtin_spawners;
+
+lazy_static! {
+    static ref SPAWN_REGISTRY: RwLock<SpawnRegistry> = RwLock::new(SpawnRegistry::new());
+}
+
+/// Function which spawns an entity from a set of parsed map entity key/value pairs
+pub type SpawnFn = fn(&HashMap<&str, &str>, &mut SpawnContext);
+
+/// State shared between spawn functions while spawning a map's entities
+pub struct SpawnContext<'a> {
+    pub world: &'a mut World,
+    pub map_data: &'a MapData,
+    /// Entities by targetname
+    pub targetmap: HashMap<String, Vec<Entity>>,
+    /// Entities whose TriggerLink "target" should be resolved once all entities are spawned
+    pub pending_resolve_targets: Vec<(Entity, String)>,
+    /// Entities whose TriggerLink "killtarget" should be resolved once all entities are spawned
+    pub pending_resolve_killtargets: Vec<(Entity, String)>,
+    /// Trains & path corners whose "target" should be resolved into the next path corner once all entities are spawned
+    pub pending_path_targets: Vec<(Entity, String)>,
+    /// Doors which should be linked to any other touching doors once all entities are spawned
+    pub door_bounds: Vec<(Entity, AABB)>,
+    /// Custom light layers assigned to switchable lights, by targetname
+    pub switchable_light_layers: HashMap<String, usize>,
+    /// Custom light layer which will be given to the next switchable light whose compiler didn't assign it a style.
+    /// Starts above the highest style used by the map's baked lighting, so it can't collide with compiler assigned styles
+    pub next_fallback_light_layer: usize,
+    /// Positions & yaw rotations of info_player_start entities, in the order they were spawned
+    pub player_starts: Vec<(Vector3, f32)>,
+    /// Positions & yaw rotations of info_player_coop entities, used by additional local players
+    pub coop_starts: Vec<(Vector3, f32)>,
+}
+
+/// Maps entity classnames to the functions used to spawn them
+pub struct SpawnRegistry {
+    spawners: HashMap<String, SpawnFn>
+}
+
+impl Default for SpawnRegistry {
+    fn default() -> Self {
+        SpawnRegistry::new()
+    }
+}
+
+impl SpawnRegistry {
+    pub fn new() -> SpawnRegistry {
+        let mut registry = SpawnRegistry {
+            spawners: HashMap::new()
+        };
+
+        builtin_spawners::register_builtin_spawners(&mut registry);
+        registry
+    }
+
+    /// Register a spawn function for the given classname, replacing any previously registered one
+    pub fn register(self: &mut Self, classname: &str, spawn_fn: SpawnFn) {
+        self.spawners.insert(classname.to_owned(), spawn_fn);
+    }
+
+    pub fn get(self: &Self, classname: &str) -> Option<SpawnFn> {
+        self.spawners.get(classname).copied()
+    }
+}
+
+// find the highest light style referenced by any of the map's baked lighting (lightmaps, static prop vertex lighting, or light probes)
+fn highest_light_style(bsp: &BspFile) -> Option<usize> {
+    let face_styles = bsp.face_lump.faces.iter().flat_map(|x| x.lightmap_styles);
+    let sprop_styles = bsp.sprop_vertices_lump.vertices.iter().flat_map(|x| x.light_styles);
+    let probe_styles = bsp.lsh_grid_lump.probes.iter().flat_map(|x| x.styles);
+
+    face_styles.chain(sprop_styles)
+        .chain(probe_styles)
+        .filter(|x| *x != 255)
+        .max()
+        .map(|x| x as usize)
+}
+
+impl<'a> SpawnContext<'a> {
+    pub fn new(world: &'a mut World, map_data: &'a MapData) -> SpawnContext<'a> {
+        let next_fallback_light_layer = match highest_light_style(&map_data.map) {
+            Some(v) if v >= CUSTOM_LIGHT_LAYER_START => v + 1 - CUSTOM_LIGHT_LAYER_START,
+            _ => 0
+        };
+
+        SpawnContext {
+            world,
+            map_data,
+            targetmap: HashMap::new(),
+            pending_resolve_targets: Vec::new(),
+            pending_resolve_killtargets: Vec::new(),
+            pending_path_targets: Vec::new(),
+            door_bounds: Vec::new(),
+            switchable_light_layers: HashMap::new(),
+            next_fallback_light_layer,
+            player_starts: Vec::new(),
+            coop_starts: Vec::new(),
+        }
+    }
+
+    /// Register an entity under the given targetname, if any
+    pub fn register_targetname(self: &mut Self, target_name: &str, e: Entity) {
+        if !target_name.is_empty() {
+            self.targetmap.entry(target_name.to_owned()).or_default().push(e);
+        }
+    }
+
+    /// Attach a TriggerLink to the given entity which fires every entity named by target & removes every entity named by killtarget
+    pub fn link_targets(self: &mut Self, e: Entity, target: &str, killtarget: &str, delay: f32) {
+        if target.is_empty() && killtarget.is_empty() {
+            return;
+        }
+
+        self.world.insert_one(e, TriggerLink::new(delay)).unwrap();
+
+        if !target.is_empty() {
+            self.pending_resolve_targets.push((e, target.to_owned()));
+        }
+
+        if !killtarget.is_empty() {
+            self.pending_resolve_killtargets.push((e, killtarget.to_owned()));
+        }
+    }
+
+    /// Get the custom light layer a switchable light was baked into. Map compilers assign switchable lights a style of 32 or above,
+    /// with one style per unique targetname in the order they appear. Lights whose compiler didn't write that style back into the entity
+    /// share the layer of another light with the same targetname, or are assigned one after all of the styles used by the map
+    pub fn switchable_light_layer(self: &mut Self, target_name: &str, style: usize) -> Option<usize> {
+        let layer = if style >= CUSTOM_LIGHT_LAYER_START {
+            let layer = style - CUSTOM_LIGHT_LAYER_START;
+            self.switchable_light_layers.entry(target_name.to_owned()).or_insert(layer);
+            layer
+        }
+        else {
+            match self.switchable_light_layers.get(target_name) {
+                Some(v) => *v,
+                None => {
+                    let layer = self.next_fallback_light_layer;
+                    self.next_fallback_light_layer += 1;
+                    self.switchable_light_layers.insert(target_name.to_owned(), layer);
+                    layer
+                }
+            }
+        };
+
+        if layer >= NUM_CUSTOM_LIGHT_LAYERS {
+            warn!("Too many switchable lights, light {} will not be switchable", target_name);
+            return None;
+        }
+
+        Some(layer)
+    }
+
+    // resolve triggerable entity targets, chain trains & path corners together, & link touching doors together
+    fn resolve_links(self: &mut Self) {
+        let mut cmd_buf = CommandBuffer::new();
+        for (e, targetname) in &self.pending_resolve_targets {
+            match self.targetmap.get(targetname) {
+                Some(targets) => {
+                    if let Ok(mut link) = self.world.get::<&mut TriggerLink>(*e) {
+                        link.targets.extend(targets.iter().copied());
+                    }
+                }
+                None => {
+                    warn!("Couldn't find trigger target: {}", targetname);
+                }
+            }
+        }
+
+        for (e, targetname) in &self.pending_resolve_killtargets {
+            match self.targetmap.get(targetname) {
+                Some(targets) => {
+                    if let Ok(mut link) = self.world.get::<&mut TriggerLink>(*e) {
+                        link.killtargets.extend(targets.iter().copied());
+                    }
+                }
+                None => {
+                    warn!("Couldn't find kill target: {}", targetname);
+                }
+            }
+        }
+
+        // path targets only follow the first entity with a matching targetname
+        for (e, targetname) in &self.pending_path_targets {
+            match self.targetmap.get(targetname).and_then(|x| x.first()) {
+                Some(target_ent) => {
+                    if let Ok(mut corner) = self.world.get::<&mut PathCorner>(*e) {
+                        corner.next = Some(*target_ent);
+                    }
+
+                    if let Ok(mut train) = self.world.get::<&mut Train>(*e) {
+                        train.target = Some(*target_ent);
+                    }
+                }
+                None => {
+                    warn!("Couldn't find path target: {}", targetname);
+                }
+            }
+        }
+
+        for (e, bounds) in &self.door_bounds {
+            let mut links = Vec::new();
+            for (e2, bounds2) in &self.door_bounds {
+                if e2 != e && aabb_aabb_intersects(bounds, bounds2) {
+                    links.push(*e2);
+                }
+            }
+
+            cmd_buf.insert_one(*e, DoorLink {
+                links
+            });
+        }
+
+        cmd_buf.run_on(self.world);
+    }
+}
+
+/// Register a spawn function for the given entity classname
+pub fn register_spawner(classname: &str, spawn_fn: SpawnFn) {
+    let mut registry = SPAWN_REGISTRY.write().unwrap();
+    registry.register(classname, spawn_fn);
+}
+
+/// Spawns entities described by the map's entity lump, returning the position & rotation of each player spawn point.
+/// info_player_start entities come first, followed by info_player_coop entities
+pub fn spawn_map_entities(world: &mut World, map_data: &MapData) -> Vec<(Vector3, f32)> {
+    let mut ctx = SpawnContext::new(world, map_data);
+
+    map_data.map.entity_lump.parse(|entity_data| {
+        let classname = entity_data.get("classname").copied().unwrap_or("");
+        let spawn_fn = SPAWN_REGISTRY.read().unwrap().get(classname);
+
+        match spawn_fn {
+            Some(f) => {
+                f(&entity_data, &mut ctx);
+            }
+            None => {
+                let mut keys = entity_data.keys()
+                    .filter(|x| **x != "classname")
+                    .copied()
+                    .collect::<Vec<_>>();
+                keys.sort();
+
+                warn!("No spawner registered for entity classname \"{}\" - unhandled keys: {}", classname, keys.join(", "));
+            }
+        }
+    });
+
+    ctx.resolve_links();
+
+    ctx.player_starts.into_iter().chain(ctx.coop_starts).collect()
+}
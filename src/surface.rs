@@ -0,0 +1,113 @@
+use std::{collections::HashMap, fs, sync::RwLock};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::bsp::{bspcollision::Trace, bspfile::BspFile};
+
+/// Path to the table of surface types which is loaded on startup
+pub const SURFACE_TABLE_PATH: &str = "content/surfaces.ron";
+
+lazy_static! {
+    static ref SURFACE_TABLE: RwLock<SurfaceTable> = RwLock::new(SurfaceTable::default());
+}
+
+fn default_friction() -> f32 {
+    1.0
+}
+
+/// Gameplay properties of a kind of surface (metal, wood, water, etc)
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SurfaceType {
+    /// Multiplier applied to ground friction for characters standing on this surface
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+    /// Sound played for footsteps on this surface, relative to content/sounds
+    #[serde(default)]
+    pub footstep_sound: Option<String>,
+    /// Effect spawned where projectiles hit this surface, relative to content
+    #[serde(default)]
+    pub impact_effect: Option<String>,
+}
+
+/// Surface types by name, along with the texture names which map to them
+#[derive(Default, Serialize, Deserialize)]
+pub struct SurfaceTable {
+    pub types: HashMap<String, SurfaceType>,
+    /// Surface type names by texture name. Names ending in * match any texture starting with the preceding prefix
+    pub textures: HashMap<String, String>,
+    /// Surface type used for textures which don't match any entry, if any
+    #[serde(default)]
+    pub default_type: Option<String>,
+}
+
+impl SurfaceTable {
+    /// Find the name of the surface type used by the given texture. Exact matches take priority, followed by the longest matching prefix
+    pub fn surface_type_name(self: &Self, texture_name: &str) -> Option<&str> {
+        if let Some(v) = self.textures.get(texture_name) {
+            return Some(v);
+        }
+
+        self.textures.iter()
+            .filter_map(|(pattern, type_name)| pattern.strip_suffix('*').map(|prefix| (prefix, type_name)))
+            .filter(|(prefix, _)| texture_name.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, type_name)| type_name.as_str())
+            .or(self.default_type.as_deref())
+    }
+
+    pub fn surface_type(self: &Self, texture_name: &str) -> Option<&SurfaceType> {
+        self.surface_type_name(texture_name).and_then(|x| self.types.get(x))
+    }
+}
+
+/// Load the table of surface types from the given RON file, replacing the current table
+pub fn load_surface_table(path: &str) {
+    let table_str = match fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Failed loading surface types from {}: {}", path, e);
+            return;
+        }
+    };
+
+    let table = match ron::from_str::<SurfaceTable>(&table_str) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Failed parsing surface types from {}: {}", path, e);
+            return;
+        }
+    };
+
+    for (texture_name, type_name) in &table.textures {
+        if !table.types.contains_key(type_name) {
+            warn!("Texture {} uses undefined surface type {}", texture_name, type_name);
+        }
+    }
+
+    info!("Loaded {} surface types", table.types.len());
+    *SURFACE_TABLE.write().unwrap() = table;
+}
+
+/// Name of the surface type used by the given texture, if any
+pub fn surface_type_name(texture_name: &str) -> Option<String> {
+    SURFACE_TABLE.read().unwrap().surface_type_name(texture_name).map(|x| x.to_owned())
+}
+
+/// Surface type used by the given texture, if any
+pub fn surface_type(texture_name: &str) -> Option<SurfaceType> {
+    SURFACE_TABLE.read().unwrap().surface_type(texture_name).cloned()
+}
+
+/// Name of the texture on the surface hit by a trace, if the trace hit a textured brush side
+pub fn trace_texture_name<'a>(map: &'a BspFile, trace: &Trace) -> Option<&'a str> {
+    trace.tex_info
+        .and_then(|x| map.tex_info_lump.textures.get(x))
+        .map(|x| x.texture_name.as_str())
+}
+
+/// Surface type of the surface hit by a trace, if any
+pub fn trace_surface_type(map: &BspFile, trace: &Trace) -> Option<SurfaceType> {
+    trace_texture_name(map, trace).and_then(surface_type)
+}
@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+
+use hecs::World;
+
+use crate::{audio::mixer::{Mixer, VoiceParams}, component::{camera::Camera, soundsource::SoundSource, transform3d::Transform3D, triggerable::TriggerState}, cvar::get_cvar, gamestate::MapData, math::{Matrix4x4, Vector3}};
+
+// visibility cluster containing the given position, or None if the position is outside the map or the map has no vis data
+fn find_cluster(map_data: &MapData, position: &Vector3) -> Option<usize> {
+    let bsp = &map_data.map;
+
+    if bsp.vis_lump.clusters.is_empty() {
+        return None;
+    }
+
+    let leaf_index = bsp.calc_leaf_index(position);
+    let cluster = bsp.leaf_lump.leaves[leaf_index as usize].cluster;
+
+    if cluster == u16::MAX {
+        None
+    }
+    else {
+        Some(cluster as usize)
+    }
+}
+
+// volume of a sound heard from the given distance, fading out linearly to silence at radius (or never fading if radius is 0)
+fn distance_attenuation(dist: f32, radius: f32) -> f32 {
+    if radius > 0.0 {
+        (1.0 - (dist / radius)).clamp(0.0, 1.0)
+    }
+    else {
+        1.0
+    }
+}
+
+// a camera which sounds are heard from, along with its right vector (for panning) & potentially visible set (for muffling)
+struct Listener {
+    position: Vector3,
+    right: Vector3,
+    vis: Option<Vec<bool>>,
+}
+
+// with several cameras (splitscreen) the mixer only has a single stereo output, so each voice is heard from whichever camera is nearest to it
+fn nearest_listener(listeners: &[Listener], position: Vector3) -> Option<&Listener> {
+    listeners.iter().min_by(|a, b| (a.position - position).length_sq().total_cmp(&(b.position - position).length_sq()))
+}
+
+/// System which starts & stops voices for sound sources, and updates their volume, pan & muffling relative to the nearest camera
+pub fn audio_system_update(map_data: &MapData, mixer: &mut Mixer, world: &mut World) {
+    mixer.set_master_volume(get_cvar::<f32>("s_volume"));
+
+    let listeners: Vec<_> = world.query_mut::<(&Transform3D, &Camera)>().into_iter()
+        .map(|(_, (transform, _))| {
+            // unpack the listener's potentially visible set, so that sounds outside of it can be muffled
+            let vis = find_cluster(map_data, &transform.position).map(|cluster| {
+                let mut vis = vec![false;map_data.map.vis_lump.clusters.len()];
+                map_data.map.vis_lump.unpack_vis(cluster, &mut vis);
+                vis
+            });
+
+            Listener {
+                position: transform.position,
+                right: Matrix4x4::rotation(transform.rotation).transform_direction(Vector3::unit_x()),
+                vis,
+            }
+        })
+        .collect();
+
+    let mut live_voices = HashSet::new();
+
+    for (_, (transform, source, trigger)) in world.query_mut::<(&Transform3D, &mut SoundSource, Option<&TriggerState>)>() {
+        // looping sounds toggle each time they are triggered, one-shot sounds restart
+        let triggered = trigger.is_some_and(|x| x.triggered);
+
+        if triggered && !source.prev_triggered {
+            if source.looping {
+                source.active = !source.active;
+            }
+            else {
+                if let Some(voice) = source.voice.take() {
+                    mixer.stop(voice);
+                }
+                source.active = true;
+            }
+        }
+        source.prev_triggered = triggered;
+
+        // one-shot sounds deactivate once they finish playing
+        if let Some(voice) = source.voice {
+            if !mixer.is_playing(voice) {
+                source.voice = None;
+                source.active = source.looping && source.active;
+            }
+        }
+
+        let params = match nearest_listener(&listeners, transform.position) {
+            Some(listener) => {
+                let offset = transform.position - listener.position;
+                let dist = offset.length();
+
+                let attenuation = distance_attenuation(dist, source.radius);
+
+                let pan = if dist > 0.0 {
+                    (offset / dist).dot(listener.right)
+                }
+                else {
+                    0.0
+                };
+
+                let muffled = match &listener.vis {
+                    Some(vis) => find_cluster(map_data, &transform.position).is_some_and(|cluster| vis.get(cluster).is_some_and(|x| !*x)),
+                    None => false
+                };
+
+                VoiceParams { volume: source.volume * attenuation, pan, muffled }
+            }
+            None => VoiceParams { volume: 0.0, pan: 0.0, muffled: false }
+        };
+
+        match (source.active, source.voice) {
+            (true, None) => {
+                source.voice = Some(mixer.play(&source.sound, params, source.looping));
+            }
+            (true, Some(voice)) => {
+                mixer.set_params(voice, params);
+            }
+            (false, Some(voice)) => {
+                mixer.stop(voice);
+                source.voice = None;
+            }
+            (false, None) => {}
+        }
+
+        if let Some(voice) = source.voice {
+            live_voices.insert(voice);
+        }
+    }
+
+    // stop any voices whose sound source has been despawned
+    mixer.retain_voices(|x| live_voices.contains(&x));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attenuation_fades_linearly_to_radius() {
+        assert_eq!(distance_attenuation(0.0, 100.0), 1.0);
+        assert_eq!(distance_attenuation(25.0, 100.0), 0.75);
+        assert_eq!(distance_attenuation(100.0, 100.0), 0.0);
+        assert_eq!(distance_attenuation(500.0, 100.0), 0.0);
+    }
+
+    #[test]
+    fn attenuation_disabled_without_radius() {
+        assert_eq!(distance_attenuation(0.0, 0.0), 1.0);
+        assert_eq!(distance_attenuation(10000.0, 0.0), 1.0);
+    }
+    #[test]
+    fn nearest_listener_is_chosen() {
+        let listener = |x: f32| Listener { position: Vector3::new(x, 0.0, 0.0), right: Vector3::unit_x(), vis: None };
+        let listeners = [listener(0.0), listener(100.0)];
+
+        assert_eq!(nearest_listener(&listeners, Vector3::new(10.0, 0.0, 0.0)).unwrap().position.x, 0.0);
+        assert_eq!(nearest_listener(&listeners, Vector3::new(90.0, 0.0, 0.0)).unwrap().position.x, 100.0);
+        assert!(nearest_listener(&[], Vector3::zero()).is_none());
+    }
+}
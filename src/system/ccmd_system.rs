@@ -1,104 +1,449 @@
-use clap::{value_parser, Command};
-use log::error;
+use std::{collections::{BTreeMap, HashMap}, fs, sync::{Arc, RwLock}};
 
-use hecs::{Entity, World};
+use clap::{value_parser, ArgMatches, Command};
+use lazy_static::lazy_static;
+use log::{error, info};
+
+use hecs::World;
 use shellwords::split;
 
-use crate::{asset_loader::{clear_all, load_effect}, component::{effect::Effect, transform3d::Transform3D}, cvar::{print_cvars, set_cvar}, math::Vector3};
+use crate::{asset_loader::{clear_all, load_effect}, component::{camera::{FPCamera, ThirdPersonCamera, ThirdPersonMode}, effect::Effect, transform3d::Transform3D}, config::read_script, cvar::{cvar_names, print_cvars, reset_cvar, set_cvar, toggle_cvar, try_get_cvar}, demo::DEMO_DIR, lightstyle::set_lightstyle, math::Vector3, savegame::SAVE_DIR};
+
+// limit on nested exec commands, so that scripts which exec themselves don't recurse forever
+const MAX_EXEC_DEPTH: usize = 16;
+
+// marks the effect spawned by test-vfx, so that it can be replaced by the next one
+struct TestVfx {
+}
+
+/// Commands which have to be handled by the game state rather than acting on the world directly
+pub enum GameCommand {
+    ChangeLevel(String),
+    Save(String),
+    Load(String),
+    RecordDemo(String),
+    PlayDemo(String),
+    StopDemo,
+}
+
+/// State which console command handlers can act on
+pub struct CommandContext<'a> {
+    pub world: &'a mut World,
+    /// Command system executing the command, for issuing game commands & executing further commands
+    pub ccmd: &'a mut ConsoleCommandSystem,
+}
+
+/// Handler for a registered console command, invoked with the parsed arguments & the context the command is executed in
+pub type CommandHandler = dyn Fn(&ArgMatches, &mut CommandContext) + Send + Sync;
+
+/// Returns the possible values of a command argument, for tab completion
+pub type ArgCompleter = fn() -> Vec<String>;
+
+struct RegisteredCommand {
+    command: Command,
+    handler: Arc<CommandHandler>,
+    completers: HashMap<String, ArgCompleter>,
+}
+
+lazy_static! {
+    static ref COMMANDS: RwLock<BTreeMap<String, RegisteredCommand>> = RwLock::new(BTreeMap::new());
+}
+
+/// Register a console command, replacing any existing command with the same name.
+/// The command is parsed with the given clap command, whose name is the name typed into the console
+pub fn register_command<F>(command: Command, handler: F) where F : Fn(&ArgMatches, &mut CommandContext) + Send + Sync + 'static {
+    let name = command.get_name().to_string();
+
+    COMMANDS.write().unwrap().insert(name, RegisteredCommand {
+        command,
+        handler: Arc::new(handler),
+        completers: HashMap::new(),
+    });
+}
+
+/// Register a function providing the possible values of a positional argument of a registered command, for tab completion
+pub fn register_arg_completer(command: &str, arg: &str, completer: ArgCompleter) {
+    match COMMANDS.write().unwrap().get_mut(command) {
+        Some(cmd) => {
+            cmd.completers.insert(arg.to_string(), completer);
+        }
+        None => {
+            error!("Cannot register completer for unknown command: {}", command);
+        }
+    }
+}
+
+/// Candidates for completing the last word of a console command line
+pub struct Completion {
+    /// Byte offset of the start of the word being completed
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+/// Find completions for the last word of a console command line: command & CVAR names for the first word,
+/// or the possible values of the corresponding argument (if the command defines them) for following words
+pub fn complete_command_line(line: &str) -> Completion {
+    let start = line.rfind(char::is_whitespace).map_or(0, |x| x + 1);
+    let partial = &line[start..];
+    let preceding = line[..start].split_whitespace().collect::<Vec<_>>();
+
+    let mut candidates = if preceding.is_empty() {
+        let mut names = COMMANDS.read().unwrap().keys().cloned().collect::<Vec<_>>();
+        names.extend(cvar_names());
+        names
+    }
+    else {
+        let commands = COMMANDS.read().unwrap();
+
+        commands.get(preceding[0])
+            .and_then(|cmd| {
+                let arg = cmd.command.get_positionals().nth(preceding.len() - 1)?;
+                cmd.completers.get(arg.get_id().as_str())
+            })
+            .map_or(Vec::new(), |completer| completer())
+    };
+
+    candidates.retain(|x| x.starts_with(partial));
+    candidates.sort();
+    candidates.dedup();
+
+    Completion { start, candidates }
+}
+
+// names of the files in a directory with the given extension, with the extension removed
+fn files_with_extension(dir: &str, extension: &str) -> Vec<String> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|x| x.ok())
+            .filter_map(|x| x.file_name().to_str().and_then(|x| x.strip_suffix(extension)).map(|x| x.to_string()))
+            .collect(),
+        Err(_) => Vec::new()
+    }
+}
+
+fn complete_map_names() -> Vec<String> {
+    files_with_extension("content/maps", ".bsp")
+}
+
+fn complete_effect_paths() -> Vec<String> {
+    files_with_extension("content/effects", ".fx.ron").iter()
+        .map(|x| format!("content/effects/{}.fx.ron", x))
+        .collect()
+}
+
+fn complete_save_slots() -> Vec<String> {
+    files_with_extension(SAVE_DIR, ".ron")
+}
+
+fn complete_demo_names() -> Vec<String> {
+    files_with_extension(DEMO_DIR, ".dem")
+}
+
+fn complete_scripts() -> Vec<String> {
+    files_with_extension(".", ".cfg").iter()
+        .map(|x| format!("{}.cfg", x))
+        .collect()
+}
+
+/// Register the engine's built-in console commands
+pub fn register_builtin_commands() {
+    register_command(Command::new("cmdlist")
+        .about("List all console commands")
+        .arg(clap::arg!([PREFIX] "Only list commands whose names start with this prefix")),
+        |args, _| {
+            let prefix = args.get_one::<String>("PREFIX").map_or("", |x| x.as_str());
+            let commands = COMMANDS.read().unwrap();
+
+            for (name, cmd) in commands.iter().filter(|(name, _)| name.starts_with(prefix)) {
+                info!("{} - {}", name, cmd.command.get_about().map_or(String::new(), |x| x.to_string()));
+            }
+        }
+    );
+
+    register_command(Command::new("test-vfx")
+        .about("Spawn a test effect instance in the world, erasing the previous test effect if any. Also clears resource caches.")
+        .arg(clap::arg!(<PATH> "Path to the VFX to spawn"))
+        .arg(clap::arg!(<POS_X> "X Position to spawn the VFX at").value_parser(value_parser!(f32)))
+        .arg(clap::arg!(<POS_Y> "Y Position to spawn the VFX at").value_parser(value_parser!(f32)))
+        .arg(clap::arg!(<POS_Z> "Z Position to spawn the VFX at").value_parser(value_parser!(f32))),
+        |args, ctx| {
+            clear_all();
+
+            let path = args.get_one::<String>("PATH").unwrap();
+            let pos_x = args.get_one::<f32>("POS_X").unwrap();
+            let pos_y = args.get_one::<f32>("POS_Y").unwrap();
+            let pos_z = args.get_one::<f32>("POS_Z").unwrap();
+
+            if let Ok(effect) = load_effect(path) {
+                let prev_effects = ctx.world.query_mut::<&TestVfx>().into_iter().map(|(e, _)| e).collect::<Vec<_>>();
+
+                for e in prev_effects {
+                    ctx.world.despawn(e).unwrap();
+                }
+
+                ctx.world.spawn((
+                    Transform3D::default().with_position(Vector3::new(*pos_x, *pos_y, *pos_z)),
+                    Effect::new(&effect, true, false),
+                    TestVfx {},
+                ));
+            }
+        }
+    );
+    register_arg_completer("test-vfx", "PATH", complete_effect_paths);
+
+    register_command(Command::new("delete-entity")
+        .about("Delete entity by ID")
+        .arg(clap::arg!(<ID> "ID of the entity to delete").value_parser(value_parser!(u32))),
+        |args, ctx| {
+            let id = args.get_one::<u32>("ID").unwrap();
+            let e = unsafe { ctx.world.find_entity_from_id(*id) };
+            ctx.world.despawn(e).unwrap();
+        }
+    );
+
+    register_command(Command::new("thirdperson")
+        .about("Toggle the player's camera between first & third person"),
+        |_, ctx| {
+            let fp_cameras = ctx.world.query_mut::<&FPCamera>().into_iter().map(|(e, cam)| (e, cam.follow_entity)).collect::<Vec<_>>();
+            let tp_cameras = ctx.world.query_mut::<&ThirdPersonCamera>().into_iter()
+                .filter(|(_, cam)| cam.mode == ThirdPersonMode::Follow)
+                .map(|(e, cam)| (e, cam.follow_entity))
+                .collect::<Vec<_>>();
+
+            for (e, follow_entity) in fp_cameras {
+                ctx.world.remove_one::<FPCamera>(e).unwrap();
+                ctx.world.insert_one(e, ThirdPersonCamera::new(follow_entity, ThirdPersonMode::Follow)).unwrap();
+            }
+
+            for (e, follow_entity) in tp_cameras {
+                ctx.world.remove_one::<ThirdPersonCamera>(e).unwrap();
+                ctx.world.insert_one(e, FPCamera::new(follow_entity)).unwrap();
+            }
+        }
+    );
+
+    register_command(Command::new("clear-cache")
+        .about("Clear all resource caches"),
+        |_, _| {
+            clear_all();
+        }
+    );
+
+    register_command(Command::new("set")
+        .arg(clap::arg!(<NAME> "Name of the CVAR"))
+        .arg(clap::arg!(<VALUE> "Value to set"))
+        .about("Set CVAR by name"),
+        |args, _| {
+            let name = args.get_one::<String>("NAME").unwrap();
+            let value = args.get_one::<String>("VALUE").unwrap();
+
+            set_cvar(name, value);
+        }
+    );
+    register_arg_completer("set", "NAME", cvar_names);
+
+    register_command(Command::new("reset")
+        .arg(clap::arg!(<NAME> "Name of the CVAR"))
+        .about("Reset CVAR to its default value"),
+        |args, _| {
+            let name = args.get_one::<String>("NAME").unwrap();
+            reset_cvar(name);
+        }
+    );
+    register_arg_completer("reset", "NAME", cvar_names);
+
+    register_command(Command::new("toggle")
+        .arg(clap::arg!(<NAME> "Name of the CVAR"))
+        .about("Toggle a bool or int CVAR between true/1 and false/0"),
+        |args, _| {
+            let name = args.get_one::<String>("NAME").unwrap();
+            toggle_cvar(name);
+        }
+    );
+    register_arg_completer("toggle", "NAME", cvar_names);
+
+    register_command(Command::new("cvarlist")
+        .about("List all defined CVARs with their current & default values")
+        .arg(clap::arg!([PREFIX] "Only list CVARs whose names start with this prefix")),
+        |args, _| {
+            let prefix = args.get_one::<String>("PREFIX").map_or("", |x| x.as_str());
+            print_cvars(prefix);
+        }
+    );
+
+    register_command(Command::new("exec")
+        .about("Execute a script file of console commands, one per line")
+        .arg(clap::arg!(<FILE> "Path to the script file")),
+        |args, ctx| {
+            let path = args.get_one::<String>("FILE").unwrap();
+            ctx.ccmd.exec_script(path, ctx.world);
+        }
+    );
+    register_arg_completer("exec", "FILE", complete_scripts);
+
+    register_command(Command::new("lightstyle")
+        .about("Override the pattern of a lightstyle until the map changes")
+        .arg(clap::arg!(<INDEX> "Index of the lightstyle").value_parser(value_parser!(usize)))
+        .arg(clap::arg!(<PATTERN> "Lightstyle pattern, from a (dark) through m (normal) to z (bright)")),
+        |args, _| {
+            let index = args.get_one::<usize>("INDEX").unwrap();
+            let pattern = args.get_one::<String>("PATTERN").unwrap();
+
+            set_lightstyle(*index, pattern);
+        }
+    );
+
+    register_command(Command::new("map")
+        .about("Unload the current map & load a new one")
+        .arg(clap::arg!(<NAME> "Name of the map to load (without extension)")),
+        |args, ctx| {
+            let name = args.get_one::<String>("NAME").unwrap();
+            ctx.ccmd.push_game_command(GameCommand::ChangeLevel(name.clone()));
+        }
+    );
+    register_arg_completer("map", "NAME", complete_map_names);
+
+    register_command(Command::new("save")
+        .about("Save the game to a slot")
+        .arg(clap::arg!(<SLOT> "Name of the save slot")),
+        |args, ctx| {
+            let slot = args.get_one::<String>("SLOT").unwrap();
+            ctx.ccmd.push_game_command(GameCommand::Save(slot.clone()));
+        }
+    );
+    register_arg_completer("save", "SLOT", complete_save_slots);
+
+    register_command(Command::new("load")
+        .about("Load the game from a slot")
+        .arg(clap::arg!(<SLOT> "Name of the save slot")),
+        |args, ctx| {
+            let slot = args.get_one::<String>("SLOT").unwrap();
+            ctx.ccmd.push_game_command(GameCommand::Load(slot.clone()));
+        }
+    );
+    register_arg_completer("load", "SLOT", complete_save_slots);
+
+    register_command(Command::new("record")
+        .about("Restart the current map & record a demo of all input until stopped")
+        .arg(clap::arg!(<NAME> "Name of the demo")),
+        |args, ctx| {
+            let name = args.get_one::<String>("NAME").unwrap();
+            ctx.ccmd.push_game_command(GameCommand::RecordDemo(name.clone()));
+        }
+    );
+
+    register_command(Command::new("playdemo")
+        .about("Play back a recorded demo")
+        .arg(clap::arg!(<NAME> "Name of the demo")),
+        |args, ctx| {
+            let name = args.get_one::<String>("NAME").unwrap();
+            ctx.ccmd.push_game_command(GameCommand::PlayDemo(name.clone()));
+        }
+    );
+    register_arg_completer("playdemo", "NAME", complete_demo_names);
+
+    register_command(Command::new("stopdemo")
+        .about("Stop recording or playing back a demo"),
+        |_, ctx| {
+            ctx.ccmd.push_game_command(GameCommand::StopDemo);
+        }
+    );
+}
 
 pub struct ConsoleCommandSystem {
-    test_fx_id: Option<Entity>
+    pending_game_commands: Vec<GameCommand>,
+    exec_depth: usize,
+}
+
+impl Default for ConsoleCommandSystem {
+    fn default() -> Self {
+        ConsoleCommandSystem::new()
+    }
 }
 
 impl ConsoleCommandSystem {
     pub fn new() -> ConsoleCommandSystem {
         ConsoleCommandSystem {
-            test_fx_id: None,
+            pending_game_commands: Vec::new(),
+            exec_depth: 0,
         }
     }
 
+    /// Queue a command to be handled by the game state once the current batch of console commands has executed
+    pub fn push_game_command(self: &mut ConsoleCommandSystem, command: GameCommand) {
+        self.pending_game_commands.push(command);
+    }
+
+    /// Returns any game commands issued since the last call, in the order they were issued
+    pub fn take_game_commands(self: &mut ConsoleCommandSystem) -> Vec<GameCommand> {
+        std::mem::take(&mut self.pending_game_commands)
+    }
+
     pub fn exec_commands<I>(self: &mut ConsoleCommandSystem, commands: I, world: &mut World) where I : Iterator::<Item = String> {
         for cmd in commands {
-            let args = split(&cmd).unwrap();
-    
-            let cmd = Command::new("")
-                .no_binary_name(true)
-                .subcommand_required(true)
-                .subcommand(Command::new("test-vfx")
-                    .about("Spawn a test effect instance in the world, erasing the previous test effect if any. Also clears resource caches.")
-                    .arg(clap::arg!(<PATH> "Path to the VFX to spawn"))
-                    .arg(clap::arg!(<POS_X> "X Position to spawn the VFX at").value_parser(value_parser!(f32)))
-                    .arg(clap::arg!(<POS_Y> "Y Position to spawn the VFX at").value_parser(value_parser!(f32)))
-                    .arg(clap::arg!(<POS_Z> "Z Position to spawn the VFX at").value_parser(value_parser!(f32)))
-                )
-                .subcommand(Command::new("delete-entity")
-                    .about("Delete entity by ID")
-                    .arg(clap::arg!(<ID> "ID of the entity to delete").value_parser(value_parser!(u32)))
-                )
-                .subcommand(Command::new("clear-cache")
-                    .about("Clear all resource caches")
-                )
-                .subcommand(Command::new("set")
-                    .arg(clap::arg!(<NAME> "Name of the CVAR"))
-                    .arg(clap::arg!(<VALUE> "Value to set"))
-                    .about("Set CVAR by name")
-                )
-                .subcommand(Command::new("cvarlist")
-                    .about("List all defined CVARs")
-                );
-    
-            match cmd.try_get_matches_from(args) {
-                Ok(m) => {
-                    match m.subcommand() {
-                        Some(("test-vfx", sub_args)) => {
-                            clear_all();
-
-                            let path = sub_args.get_one::<String>("PATH").unwrap();
-                            let pos_x = sub_args.get_one::<f32>("POS_X").unwrap();
-                            let pos_y = sub_args.get_one::<f32>("POS_Y").unwrap();
-                            let pos_z = sub_args.get_one::<f32>("POS_Z").unwrap();
-    
-                            match load_effect(&path) {
-                                Ok(effect) => {
-                                    if let Some(prev_entity) = self.test_fx_id {
-                                        world.despawn(prev_entity).unwrap();
-                                    }
-
-                                    let entity = world.spawn((
-                                        Transform3D::default().with_position(Vector3::new(*pos_x, *pos_y, *pos_z)),
-                                        Effect::new(&effect, true, false), 
-                                    ));
-
-                                    self.test_fx_id = Some(entity);
-                                }
-                                Err(_) => {
-                                }
-                            }
+            let args = match split(&cmd) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("{}: {}", e, cmd);
+                    continue;
+                }
+            };
+
+            let Some(name) = args.first() else {
+                continue;
+            };
+
+            // note: the command is cloned out of the registry so that handlers may execute further commands
+            let registered = COMMANDS.read().unwrap().get(name).map(|x| (x.command.clone(), x.handler.clone()));
+
+            match registered {
+                Some((command, handler)) => {
+                    match command.try_get_matches_from(&args) {
+                        Ok(m) => {
+                            handler(&m, &mut CommandContext { world, ccmd: self });
                         }
-                        Some(("delete-entity", sub_args)) => {
-                            let id = sub_args.get_one::<u32>("ID").unwrap();
-                            let e = unsafe { world.find_entity_from_id(*id) };
-                            world.despawn(e).unwrap();
+                        Err(e) => {
+                            error!("{}", e);
                         }
-                        Some(("clear-cache", _)) => {
-                            clear_all();
+                    }
+                }
+                None => {
+                    // CVARs can also be queried with "<name>" or set with "<name> <value>"
+                    match (try_get_cvar::<String>(name), args.len()) {
+                        (Some(value), 1) => {
+                            info!("{} = \"{}\"", name, value);
                         }
-                        Some(("set", sub_args)) => {
-                            let name = sub_args.get_one::<String>("NAME").unwrap();
-                            let value = sub_args.get_one::<String>("VALUE").unwrap();
-
-                            set_cvar(&name, &value);
+                        (Some(_), 2) => {
+                            set_cvar(name, &args[1]);
+                        }
+                        (Some(_), _) => {
+                            error!("Usage: {} [VALUE]", name);
                         }
-                        Some(("cvarlist", _)) => {
-                            print_cvars();
+                        (None, _) => {
+                            error!("Unknown command: {}", name);
                         }
-                        _ => unreachable!()
                     }
-                },
-                Err(e) => {
-                    error!("{}", e);
-                },
-            };
+                }
+            }
+        }
+    }
+
+    /// Execute each command in a script file
+    pub fn exec_script(self: &mut ConsoleCommandSystem, path: &str, world: &mut World) {
+        if self.exec_depth >= MAX_EXEC_DEPTH {
+            error!("Cannot exec {}: scripts nested too deeply (max depth {})", path, MAX_EXEC_DEPTH);
+            return;
+        }
+
+        match read_script(path) {
+            Ok(commands) => {
+                self.exec_depth += 1;
+                self.exec_commands(commands.into_iter(), world);
+                self.exec_depth -= 1;
+            }
+            Err(e) => {
+                error!("Failed to exec {}: {}", path, e);
+            }
         }
     }
 }
\ No newline at end of file
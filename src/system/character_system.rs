@@ -1,14 +1,38 @@
-use hecs::{CommandBuffer, World};
+use hecs::{CommandBuffer, Entity, World};
 use lazy_static::lazy_static;
 
-use crate::{bsp::{bspcommon::transform_aabb, bspfile::{BspFile, MASK_SOLID}}, component::{charactercontroller::{CharacterController, CharacterInputState, CharacterState}, collider::ColliderBounds, fpview::FPView, mapmodel::MapModel, playerinput::PlayerInput, transform3d::Transform3D}, math::{Matrix4x4, Quaternion, Vector3, Vector4}, misc::AABB, gamestate::{InputState, MapData, TimeData}};
+use crate::{bsp::bspfile::{CONTENTS_LADDER, MASK_SOLID, MASK_WATER, SURF_SLICK}, component::{charactercontroller::{CharacterController, CharacterInputState, CharacterState, WaterLevel}, fpview::FPView, playerinput::PlayerInput, transform3d::Transform3D}, math::{Matrix4x4, Quaternion, Vector3, Vector4}, gamestate::{InputState, MapData, TimeData}, physics::PhysicsWorld, surface::surface_type};
 
 const GROUND_SLOPE_ANGLE: f32 = 45.0;
 const STEP_HEIGHT: f32 = 20.0;
 const GRAVITY: f32 = 300.0;
 const FRICTION: f32 = 0.2;
+/// Multiplier applied to friction while standing on SURF_SLICK surfaces
+const SLICK_FRICTION_SCALE: f32 = 0.1;
 const MAX_ACCEL: f32 = 10.0;
 const AIR_ACCEL: f32 = 1.0;
+const WATER_FRICTION: f32 = 0.1;
+const WATER_ACCEL: f32 = 4.0;
+/// Multiplier applied to move speed while swimming
+const SWIM_SPEED_SCALE: f32 = 0.7;
+/// Speed at which characters sink while swimming without any input
+const SWIM_SINK_SPEED: f32 = 30.0;
+/// Fraction of gravity cancelled out by buoyancy while waist deep & fully submerged. Characters float up while submerged & sink back
+/// down while waist deep, so they settle at the surface
+const WAIST_BUOYANCY: f32 = 0.9;
+const SUBMERGED_BUOYANCY: f32 = 1.1;
+/// Upwards speed given to characters jumping out of liquid onto a ledge
+const WATER_JUMP_SPEED: f32 = 250.0;
+/// Distance checked in front of a swimming character for a ledge to jump out onto
+const WATER_JUMP_DIST: f32 = 30.0;
+/// Multiplier applied to move speed while climbing ladders
+const LADDER_SPEED_SCALE: f32 = 0.5;
+/// Speed at which characters are pushed away from a ladder when jumping off of it
+const LADDER_JUMP_SPEED: f32 = 100.0;
+/// Distance checked towards the ladder to keep climbing while not moving into it
+const LADDER_PROBE_DIST: f32 = 2.0;
+/// Characters moving into a ladder while looking further down than this climb down instead of up (sine of 15 degrees)
+const LADDER_LOOK_DOWN: f32 = 0.26;
 
 lazy_static! {
     static ref GROUND_SLOPE_COS_ANGLE: f32 = GROUND_SLOPE_ANGLE.to_radians().cos();
@@ -31,9 +55,10 @@ pub fn character_rotation_update(world: &mut World) {
     }
 }
 
-/// System which allows characters with a PlayerInput component to receive input
-pub fn character_input_update(input: &InputState, world: &mut World) {
-    for (_, (state, transform, _)) in world.query_mut::<(&mut CharacterInputState, &Transform3D, &PlayerInput)>() {
+/// System which allows characters with a PlayerInput component to receive input from their local player
+pub fn character_input_update(inputs: &[InputState], world: &mut World) {
+    for (_, (state, transform, fpview, player)) in world.query_mut::<(&mut CharacterInputState, &Transform3D, Option<&FPView>, &PlayerInput)>() {
+        let input = InputState::for_player(inputs, player.player_index);
         let rot_matrix = Matrix4x4::rotation(transform.rotation);
 
         let fwd = rot_matrix * Vector4::new(0.0, 1.0, 0.0, 0.0);
@@ -48,21 +73,162 @@ pub fn character_input_update(input: &InputState, world: &mut World) {
         state.input_move_dir = input_velocity;
         state.input_crouch = input.crouch;
         state.input_jump = input.jump;
+
+        // swimming follows view pitch, so looking up & moving forwards swims upwards
+        let swim_fwd = match fpview {
+            Some(v) => Matrix4x4::rotation(Quaternion::from_euler(Vector3::new(v.pitch.to_radians(), 0.0, v.yaw.to_radians())))
+                .transform_direction(Vector3::unit_y()),
+            None => fwd
+        };
+
+        state.input_swim_dir = (swim_fwd * input.move_y) + (right * input.move_x);
+    }
+}
+
+/// System which updates how deep characters are submerged in liquid
+pub fn character_water_update(time: &TimeData, map_data: &MapData, world: &mut World) {
+    for (_, (state, transform, fpview)) in world.query_mut::<(&mut CharacterState, &Transform3D, Option<&FPView>)>() {
+        let eye_offset = fpview.map_or(state.height - 5.0, |x| x.eye_offset);
+
+        let feet = transform.position + Vector3::new(0.0, 0.0, 1.0);
+        let waist = transform.position + Vector3::new(0.0, 0.0, state.height * 0.5);
+        let eyes = transform.position + Vector3::new(0.0, 0.0, eye_offset);
+
+        state.water_level = WaterLevel::None;
+        state.water_type = map_data.map.point_contents(&feet) & MASK_WATER;
+
+        if state.water_type != 0 {
+            state.water_level = WaterLevel::Feet;
+
+            if map_data.map.point_contents(&waist) & MASK_WATER != 0 {
+                state.water_level = WaterLevel::Waist;
+
+                if map_data.map.point_contents(&eyes) & MASK_WATER != 0 {
+                    state.water_level = WaterLevel::Eyes;
+                }
+            }
+        }
+
+        if state.water_level == WaterLevel::Eyes {
+            state.submerged_time += time.delta_time;
+        }
+        else {
+            state.submerged_time = 0.0;
+        }
+    }
+}
+
+// accelerate a character towards the wish velocity, which may point in any direction
+fn accelerate(state: &mut CharacterState, wish_dir: Vector3, wish_speed: f32, accel: f32, delta_time: f32) {
+    let current_speed = wish_dir.dot(state.velocity);
+    let add_speed = (wish_speed - current_speed).clamp(0.0, accel * wish_speed.max(1.0) * delta_time);
+
+    state.velocity = state.velocity + (wish_dir * add_speed);
+}
+
+// movement while at least waist deep in liquid. Returns true if the character jumped out of the liquid onto a ledge
+fn character_swim(time: &TimeData, physics: &PhysicsWorld, entity: Entity, state: &mut CharacterState, cc: &CharacterController, input: &CharacterInputState, transform: &Transform3D) -> bool {
+    state.velocity = state.velocity - (state.velocity * WATER_FRICTION);
+
+    let mut wish_dir = input.input_swim_dir;
+    if input.input_jump {
+        wish_dir.z += 1.0;
+    }
+    if input.input_crouch {
+        wish_dir.z -= 1.0;
+    }
+
+    if wish_dir.length_sq() > 0.1 {
+        let wish_speed = cc.move_speed * SWIM_SPEED_SCALE * wish_dir.length().min(1.0);
+        accelerate(state, wish_dir.normalized(), wish_speed, WATER_ACCEL, time.delta_time);
+    }
+    else {
+        accelerate(state, Vector3::unit_z() * -1.0, SWIM_SINK_SPEED, WATER_ACCEL, time.delta_time);
+    }
+
+    // jump out onto ledges when swimming at the surface into a wall with free space above it
+    let flat_dir = Vector3::new(input.input_move_dir.x, input.input_move_dir.y, 0.0);
+    if state.water_level != WaterLevel::Waist || flat_dir.length_sq() < 0.1 {
+        return false;
+    }
+
+    let flat_dir = flat_dir.normalized();
+    let waist = transform.position + Vector3::new(0.0, 0.0, state.height * 0.5);
+    let head = transform.position + Vector3::new(0.0, 0.0, state.height + 4.0);
+    let check_dist = cc.radius + WATER_JUMP_DIST;
+
+    let wall_trace = physics.raycast(MASK_SOLID, waist, waist + (flat_dir * check_dist), &[entity]);
+    if wall_trace.fraction == 1.0 {
+        return false;
+    }
+
+    let ledge_trace = physics.raycast(MASK_SOLID, head, head + (flat_dir * check_dist), &[entity]);
+    if ledge_trace.fraction < 1.0 {
+        return false;
+    }
+
+    state.velocity = flat_dir * cc.move_speed;
+    state.velocity.z = WATER_JUMP_SPEED;
+    true
+}
+
+// movement while climbing a ladder. Moving into the ladder climbs up (or down while looking down), crouching climbs down & jumping pushes off
+fn character_climb(state: &mut CharacterState, cc: &CharacterController, input: &CharacterInputState) {
+    if input.input_jump {
+        state.on_ladder = false;
+        state.grounded = false;
+        state.velocity = Vector3::new(state.ladder_normal.x, state.ladder_normal.y, 0.0) * LADDER_JUMP_SPEED;
+        state.velocity.z = cc.jump_force * 0.5;
+        return;
+    }
+
+    let into_ladder = input.input_move_dir.dot(state.ladder_normal * -1.0);
+
+    let climb = if input.input_crouch {
+        -1.0
+    }
+    else if into_ladder > 0.1 && input.input_swim_dir.z < -LADDER_LOOK_DOWN {
+        -into_ladder
+    }
+    else if into_ladder > 0.1 {
+        into_ladder
     }
+    else {
+        0.0
+    };
+
+    state.velocity.z = climb * cc.move_speed * LADDER_SPEED_SCALE;
 }
 
 /// System which applies input to characters
 pub fn character_apply_input_update(time: &TimeData, map_data: &MapData, world: &mut World) {
-    for (_, (state, cc, input, transform)) in world.query_mut::<(&mut CharacterState, &mut CharacterController, &CharacterInputState, &Transform3D)>() {
-        if state.grounded {
-            // apply friction
-            state.velocity = state.velocity - (state.velocity * FRICTION);
+    let physics = PhysicsWorld::new(&map_data.map, world);
+
+    for (entity, (state, cc, input, transform)) in world.query_mut::<(&mut CharacterState, &mut CharacterController, &CharacterInputState, &Transform3D)>() {
+        let swimming = state.water_level >= WaterLevel::Waist;
+
+        if swimming {
+            if character_swim(time, &physics, entity, state, cc, input, transform) {
+                state.grounded = false;
+            }
+        }
+        else if state.grounded || state.on_ladder {
+            // apply friction, scaled by the surface type & reduced on slick surfaces
+            let surface_friction = state.ground_tex_info
+                .and_then(|x| map_data.map.tex_info_lump.textures.get(x))
+                .and_then(|x| surface_type(&x.texture_name))
+                .map_or(1.0, |x| x.friction);
+
+            let slick_scale = if state.ground_surface_flags & SURF_SLICK != 0 { SLICK_FRICTION_SCALE } else { 1.0 };
+            let friction = (FRICTION * surface_friction * slick_scale).clamp(0.0, 1.0);
+
+            state.velocity = state.velocity - (state.velocity * friction);
         }
 
         let wish_dir = Vector3::new(input.input_move_dir.x, input.input_move_dir.y, 0.0);
-        let accel = if state.grounded { MAX_ACCEL } else { AIR_ACCEL };
+        let accel = if state.grounded || state.on_ladder { MAX_ACCEL } else { AIR_ACCEL };
         
-        if wish_dir.length_sq() > 0.1 {
+        if !swimming && wish_dir.length_sq() > 0.1 {
             let wish_speed = cc.move_speed * wish_dir.length();
             let wish_dir = wish_dir.normalized();
             let current_speed = wish_dir.dot(state.velocity);
@@ -71,6 +237,10 @@ pub fn character_apply_input_update(time: &TimeData, map_data: &MapData, world:
             state.velocity = state.velocity + (wish_dir * add_speed);
         }
 
+        if !swimming && state.on_ladder {
+            character_climb(state, cc, input);
+        }
+
         if state.crouched && !input.input_crouch {
             // make sure we have enough room to uncrouch before doing so
             let box_extents = Vector3::new(cc.radius, cc.radius, cc.main_height * 0.5);
@@ -82,10 +252,11 @@ pub fn character_apply_input_update(time: &TimeData, map_data: &MapData, world:
             }
         }
         else {
-            state.crouched = input.input_crouch;
+            // crouch swims downwards instead while in liquid
+            state.crouched = input.input_crouch && !swimming;
         }
 
-        if state.grounded && input.input_jump {
+        if !swimming && state.grounded && input.input_jump {
             state.grounded = false;
             state.velocity.z = cc.jump_force;
         }
@@ -97,93 +268,12 @@ pub fn character_apply_input_update(time: &TimeData, map_data: &MapData, world:
 
 /// System which controls movement of characters
 pub fn character_update(time: &TimeData, map_data: &MapData, world: &mut World) {
-    // gather map models
-    let mut mapmodel_iter = world.query::<(&MapModel, &Transform3D)>();
-    let mapmodels = mapmodel_iter
-        .iter()
-        .collect::<Vec<_>>();
-
-    // gather characters
-    let mut character_iter = world.query::<(&CharacterController, &mut CharacterState, &mut Transform3D)>();
-    let characters = character_iter
-        .iter()
-        .collect::<Vec<_>>();
-
-    // gather colliders
-    let mut collider_iter = world.query::<(&ColliderBounds, &Transform3D)>();
-    let colliders = collider_iter
-        .iter()
-        .collect::<Vec<_>>();
-
-    // gather list of collidable entity bounds
-    let mut collider_bounds = Vec::with_capacity(characters.len());
-    for (ent, (cc, cstate, transform)) in &characters {
-        let center = transform.position + Vector3::new(0.0, 0.0, cc.height_offset);
-        let extents = Vector3::new(cc.radius, cc.radius, cstate.height);
-
-        collider_bounds.push((*ent, AABB::center_extents(center, extents)));
-    }
-    for (ent, (cbounds, transform)) in &colliders {
-        let local2world = Matrix4x4::scale(transform.scale)
-            * Matrix4x4::rotation(transform.rotation)
-            * Matrix4x4::translation(transform.position);
-
-        let bounds = transform_aabb(&cbounds.bounds, local2world);
-        collider_bounds.push((*ent, bounds));
-    }
+    // note: collision is checked against the positions entities had at the start of the update
+    let physics = PhysicsWorld::new(&map_data.map, world);
 
     // update character physics
-    for (self_ent, (cc, cstate, transform)) in characters {
-        // trace function which also checks against each map model entity & against other characters
-        let trace_fn = |mask: u32, start: &Vector3, end: &Vector3, box_extents: &Vector3| {
-            let mut trace = map_data.map.boxtrace(0, mask, *start, *end, *box_extents);
-
-            for (e, (mapmodel, transform)) in &mapmodels {
-                // transform trace start + end into model's local space
-                let inv_r = transform.rotation.inverted();
-                let inv_scale = 1.0 / transform.scale;
-
-                let world2local = Matrix4x4::translation(transform.position * -1.0)
-                    * Matrix4x4::rotation(inv_r)
-                    * Matrix4x4::scale(inv_scale);
-
-                let local_start = world2local * Vector4::new(start.x, start.y, start.z, 1.0);
-                let local_end = world2local * Vector4::new(end.x, end.y, end.z, 1.0);
-
-                let local_start = Vector3::new(local_start.x, local_start.y, local_start.z);
-                let local_end = Vector3::new(local_end.x, local_end.y, local_end.z);
-
-                let tr = map_data.map.boxtrace(mapmodel.model_idx + 1, mask, local_start, local_end, *box_extents);
-
-                if tr.fraction < trace.fraction {
-                    // transform trace results back into world space
-
-                    let local2world = Matrix4x4::scale(transform.scale)
-                        * Matrix4x4::rotation(transform.rotation)
-                        * Matrix4x4::translation(transform.position);
-
-                    let trace_end = local2world * Vector4::new(tr.end_pos.x, tr.end_pos.y, tr.end_pos.z, 1.0);
-                    let trace_normal = local2world * Vector4::new(tr.hit_normal.x, tr.hit_normal.y, tr.hit_normal.z, 0.0);
-
-                    trace = tr;
-                    trace.end_pos = Vector3::new(trace_end.x, trace_end.y, trace_end.z);
-                    trace.hit_normal = Vector3::new(trace_normal.x, trace_normal.y, trace_normal.z).normalized();
-                    trace.entity = Some(*e);
-                }
-            }
-
-            for (e, other_bounds) in &collider_bounds {
-                if self_ent == *e {
-                    continue;
-                }
-
-                if BspFile::trace_aabb(other_bounds, start, end, Some(box_extents), &mut trace) {
-                    trace.entity = Some(*e);
-                }
-            }
-
-            return trace;
-        };
+    for (self_ent, (cc, cstate, transform)) in world.query::<(&CharacterController, &mut CharacterState, &mut Transform3D)>().iter() {
+        let ignore = [self_ent];
 
         let box_extents = Vector3::new(cc.radius, cc.radius, cstate.height * 0.5);
         let box_offset = Vector3::unit_z() * cc.height_offset;
@@ -193,47 +283,62 @@ pub fn character_update(time: &TimeData, map_data: &MapData, world: &mut World)
         // sweep character sideways
         let move_vec_xy = Vector3::new(cstate.velocity.x, cstate.velocity.y, 0.0);
 
-        let (box_pos, move_vec_xy) = if cstate.grounded && move_vec_xy.length_sq() > f32::EPSILON {
+        let (box_pos, move_vec_xy, side_trace) = if cstate.grounded && move_vec_xy.length_sq() > f32::EPSILON {
             let original_pos = box_pos;
             let original_move_vec_xy = move_vec_xy;
 
             // while on the ground, sweep up by step height, sweep sideways, then sweep back down by step height.
-            let (box_pos, _, _) = map_data.map.trace_move(&box_pos, &Vector3::new(0.0, 0.0, STEP_HEIGHT), 1.0, false, box_extents, trace_fn);
-            let (box_pos, move_vec_xy, _) = map_data.map.trace_move(&box_pos, &move_vec_xy, time.delta_time, true, box_extents, trace_fn);
-            let (box_pos, _, trace) = map_data.map.trace_move(&box_pos, &Vector3::new(0.0, 0.0, -STEP_HEIGHT), 1.0, false, box_extents, trace_fn);
+            let (box_pos, _, _) = physics.sweep(MASK_SOLID, box_pos, Vector3::new(0.0, 0.0, STEP_HEIGHT), 1.0, box_extents, &ignore);
+            let (box_pos, move_vec_xy, side_trace) = physics.slide(MASK_SOLID, box_pos, move_vec_xy, time.delta_time, box_extents, &ignore);
+            let (box_pos, _, trace) = physics.sweep(MASK_SOLID, box_pos, Vector3::new(0.0, 0.0, -STEP_HEIGHT), 1.0, box_extents, &ignore);
 
             // if we leave the ground, see if the ground is still close enough to step down
-            let (box_pos, move_vec_xy) = if trace.fraction == 1.0 {
-                let (new_pos, _, trace) = map_data.map.trace_move(&box_pos, &Vector3::new(0.0, 0.0, -STEP_HEIGHT), 1.0, false, box_extents, trace_fn);
+            let (box_pos, move_vec_xy, side_trace) = if trace.fraction == 1.0 {
+                let (new_pos, _, trace) = physics.sweep(MASK_SOLID, box_pos, Vector3::new(0.0, 0.0, -STEP_HEIGHT), 1.0, box_extents, &ignore);
 
                 if trace.fraction < 1.0 {
-                    (new_pos, move_vec_xy)
+                    (new_pos, move_vec_xy, side_trace)
                 }
                 else {
-                    (box_pos, move_vec_xy)
+                    (box_pos, move_vec_xy, side_trace)
                 }
             }
             else {
                 // if we stepped onto ground that's too steep, reset back to original pos and just do a normal sweep instead
                 if trace.hit_normal.z < *GROUND_SLOPE_COS_ANGLE {
-                    let (box_pos, move_vec_xy, _) = map_data.map.trace_move(&original_pos, &original_move_vec_xy, time.delta_time, true, box_extents, trace_fn);
-                    (box_pos, move_vec_xy)
+                    physics.slide(MASK_SOLID, original_pos, original_move_vec_xy, time.delta_time, box_extents, &ignore)
                 }
                 else {
-                    (box_pos, move_vec_xy)
+                    (box_pos, move_vec_xy, side_trace)
                 }
             };
 
-            (box_pos, Vector3::new(move_vec_xy.x, move_vec_xy.y, f32::min(move_vec_xy.z, 0.0)))
+            (box_pos, Vector3::new(move_vec_xy.x, move_vec_xy.y, f32::min(move_vec_xy.z, 0.0)), side_trace)
         }
         else {
-            let (box_pos, move_vec_xy, _) = map_data.map.trace_move(&box_pos, &move_vec_xy, time.delta_time, true, box_extents, trace_fn);
-            (box_pos, move_vec_xy)
+            physics.slide(MASK_SOLID, box_pos, move_vec_xy, time.delta_time, box_extents, &ignore)
         };
 
+        // characters start climbing when they move into a ladder, & keep climbing for as long as they stay against it
+        if side_trace.fraction < 1.0 && side_trace.contents & CONTENTS_LADDER != 0 {
+            cstate.on_ladder = true;
+            cstate.ladder_normal = side_trace.hit_normal;
+        }
+        else if cstate.on_ladder {
+            let probe_end = box_pos - (cstate.ladder_normal * LADDER_PROBE_DIST);
+            let probe = physics.boxcast(MASK_SOLID, box_pos, probe_end, box_extents, &ignore);
+
+            cstate.on_ladder = probe.fraction < 1.0 && probe.contents & CONTENTS_LADDER != 0;
+        }
+
         // sweep character down
         let move_vec_z = Vector3::unit_z() * cstate.velocity.z;
-        let (box_pos, mut move_vec_z, trace) = map_data.map.trace_move(&box_pos, &move_vec_z, time.delta_time, !cstate.grounded, box_extents, trace_fn);
+        let (box_pos, mut move_vec_z, trace) = if cstate.grounded {
+            physics.sweep(MASK_SOLID, box_pos, move_vec_z, time.delta_time, box_extents, &ignore)
+        }
+        else {
+            physics.slide(MASK_SOLID, box_pos, move_vec_z, time.delta_time, box_extents, &ignore)
+        };
 
         // if we hit something while moving down, & slope is within threshold, set character to grounded state
         if trace.all_solid {
@@ -244,17 +349,28 @@ pub fn character_update(time: &TimeData, map_data: &MapData, world: &mut World)
         else if cstate.velocity.z < 0.0 && trace.fraction < 1.0 {
             if trace.hit_normal.z >= *GROUND_SLOPE_COS_ANGLE {
                 cstate.grounded = true;
+                cstate.ground_entity = trace.entity;
+                cstate.ground_tex_info = trace.tex_info;
+                cstate.ground_surface_flags = trace.surface_flags;
             }
             else {
                 cstate.grounded = false;
+                cstate.ground_entity = None;
             }
         }
         else if cstate.velocity.z > 0.0 && trace.fraction < 1.0 {
             // clamp velocity if we hit our head
             move_vec_z.z = 0.0;
+            cstate.ground_entity = None;
         }
         else {
             cstate.grounded = false;
+            cstate.ground_entity = None;
+        }
+
+        if !cstate.grounded {
+            cstate.ground_tex_info = None;
+            cstate.ground_surface_flags = 0;
         }
 
         // update transform & character state
@@ -265,8 +381,20 @@ pub fn character_update(time: &TimeData, map_data: &MapData, world: &mut World)
 
         cstate.velocity.z = f32::min(cstate.velocity.z, prev_velocity.z);
         
-        // apply gravity
-        if !cstate.grounded {
+        // apply gravity, partially or fully cancelled out by buoyancy while in liquid
+        let buoyancy = match cstate.water_level {
+            WaterLevel::None | WaterLevel::Feet => 0.0,
+            WaterLevel::Waist => WAIST_BUOYANCY,
+            WaterLevel::Eyes => SUBMERGED_BUOYANCY,
+        };
+
+        if cstate.on_ladder {
+            // climbing suspends gravity
+        }
+        else if buoyancy > 0.0 {
+            cstate.velocity.z -= GRAVITY * (1.0 - buoyancy) * time.delta_time;
+        }
+        else if !cstate.grounded {
             cstate.velocity.z -= GRAVITY * time.delta_time;
         }
         else {
@@ -1,6 +1,6 @@
 use hecs::{CommandBuffer, World};
 
-use crate::{component::{door::{Door, DoorLink, DoorOpener}, mapmodel::MapModel, transform3d::Transform3D, triggerable::TriggerState}, gamestate::{MapData, TimeData}};
+use crate::{component::{door::{Door, DoorLink, DoorOpener}, mapmodel::MapModel, transform3d::Transform3D, triggerable::TriggerState}, gamestate::{MapData, TimeData}, system::mover_system::{damage_entities, try_move_mapmodel, CRUSH_INTERVAL}};
 
 const DOOR_OPEN_RADIUS: f32 = 150.0;
 
@@ -52,11 +52,21 @@ fn door_system_pass2(world: &mut World) {
     cmd_buf.run_on(world);
 }
 
-// final pass: animate triggered doors
-// todo: check if new door position overlaps another entity before moving
-fn door_system_pass3(time: &TimeData, world: &mut World) {
-    for (_, (door, state, transform)) in world.query_mut::<(&Door, &TriggerState, &mut Transform3D)>() {
-        let target_pos = if state.triggered { door.open_pos } else { door.close_pos };
+// final pass: animate triggered doors, pushing any characters in the way
+fn door_system_pass3(time: &TimeData, map: &MapData, world: &mut World) {
+    // gather door moves
+    let mut moves = Vec::new();
+    for (e, (door, state, transform)) in world.query_mut::<(&mut Door, &TriggerState, &Transform3D)>() {
+        // blocked doors head back the way they came for a while
+        let open = if door.reverse_timer > 0.0 {
+            door.reverse_timer -= time.delta_time;
+            !state.triggered
+        }
+        else {
+            state.triggered
+        };
+
+        let target_pos = if open { door.open_pos } else { door.close_pos };
         let delta = target_pos - transform.position;
         let max_delta = door.move_speed * time.delta_time;
 
@@ -67,7 +77,36 @@ fn door_system_pass3(time: &TimeData, world: &mut World) {
             delta
         };
 
-        transform.position = transform.position + delta;
+        if delta.length_sq() > 0.0 {
+            moves.push((e, delta));
+        }
+    }
+
+    for (e, delta) in moves {
+        let blocked = try_move_mapmodel(map, world, e, delta);
+
+        if blocked.is_empty() {
+            continue;
+        }
+
+        let mut door = world.get::<&mut Door>(e).unwrap();
+
+        let dmg = if door.crusher {
+            door.dmg * (time.delta_time / CRUSH_INTERVAL)
+        }
+        else {
+            // reverse for as long as it takes to travel the door's full distance
+            if door.reverse_timer <= 0.0 {
+                door.reverse_timer = (door.open_pos - door.close_pos).length() / door.move_speed;
+                door.dmg
+            }
+            else {
+                0.0
+            }
+        };
+
+        drop(door);
+        damage_entities(world, &blocked, dmg);
     }
 }
 
@@ -75,5 +114,5 @@ fn door_system_pass3(time: &TimeData, world: &mut World) {
 pub fn door_system_update(time: &TimeData, map: &MapData, world: &mut World) {
     door_system_pass1(map, world);
     door_system_pass2(world);
-    door_system_pass3(time, world);
+    door_system_pass3(time, map, world);
 }
\ No newline at end of file
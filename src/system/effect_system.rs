@@ -1,10 +1,10 @@
 use hecs::World;
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 
 use crate::{component::{effect::Effect, transform3d::Transform3D}, math::Matrix4x4, gamestate::TimeData};
 
 /// System which updates particle effects
-pub fn effect_system(time: &TimeData, rng: &mut ThreadRng, world: &mut World) {
+pub fn effect_system(time: &TimeData, rng: &mut StdRng, world: &mut World) {
     let mut effect_iter = world.query::<(&mut Effect, &mut Transform3D)>();
 
     for (_, (effect, transform)) in &mut effect_iter {
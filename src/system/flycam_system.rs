@@ -2,11 +2,13 @@ use hecs::World;
 
 use crate::{bsp::bspfile::BspFile, component::{flycam::FlyCam, fpview::FPView, playerinput::PlayerInput, transform3d::Transform3D}, math::{Matrix4x4, Quaternion, Vector3, Vector4}, gamestate::{InputState, TimeData}};
 
-/// System which allows player to control a FlyCam
-pub fn flycam_system_update(input: &InputState, time: &TimeData, map: &BspFile, world: &mut World) {
+/// System which allows players to control a FlyCam, given each local player's input
+pub fn flycam_system_update(inputs: &[InputState], time: &TimeData, map: &BspFile, world: &mut World) {
     let collider_bounds = Vector3::new(15.0, 15.0, 15.0);
 
-    for (_, (transform, fpview, _, _)) in world.query_mut::<(&mut Transform3D, &FPView, &PlayerInput, &FlyCam)>() {
+    for (_, (transform, fpview, player, _)) in world.query_mut::<(&mut Transform3D, &FPView, &PlayerInput, &FlyCam)>() {
+        let input = InputState::for_player(inputs, player.player_index);
+
         transform.rotation = Quaternion::from_euler(Vector3::new(fpview.pitch.to_radians(), 0.0, fpview.yaw.to_radians()));
         let rot_matrix = Matrix4x4::rotation(transform.rotation);
 
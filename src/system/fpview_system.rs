@@ -2,19 +2,17 @@ use hecs::World;
 
 use crate::{component::{charactercontroller::{CharacterController, CharacterState}, fpview::FPView, playerinput::PlayerInput}, gamestate::{InputState, TimeData}};
 
-const LOOK_SPEED: f32 = 90.0;
+/// Rate in degrees per second that the view turns at full look input
+pub const LOOK_SPEED: f32 = 90.0;
 const CROUCH_SPEED: f32 = 120.0;
 
-/// System which allows player to control yaw/pitch of FPView
-pub fn fpview_input_system_update(input: &InputState, time: &TimeData, world: &mut World) {
-    for (_, (fpview, _)) in world.query_mut::<(&mut FPView, &PlayerInput)>() {
-        if input.look_x.abs() >= 0.1 {
-            fpview.yaw -= ((input.look_x - 0.1) * 1.1111) * LOOK_SPEED * time.delta_time;
-        }
+/// System which allows players to control yaw/pitch of FPView, given each local player's input
+pub fn fpview_input_system_update(inputs: &[InputState], time: &TimeData, world: &mut World) {
+    for (_, (fpview, player)) in world.query_mut::<(&mut FPView, &PlayerInput)>() {
+        let input = InputState::for_player(inputs, player.player_index);
 
-        if input.look_y.abs() >= 0.1 {
-            fpview.pitch += ((input.look_y - 0.1) * 1.1111) * LOOK_SPEED * time.delta_time;
-        }
+        fpview.yaw -= input.look_x * LOOK_SPEED * time.delta_time;
+        fpview.pitch += input.look_y * LOOK_SPEED * time.delta_time;
 
         if fpview.yaw < 0.0 {
             fpview.yaw += 360.0;
@@ -0,0 +1,25 @@
+use hecs::{CommandBuffer, Query, World};
+
+use crate::component::{camera::Camera, charactercontroller::CharacterController, mapmodel::MapModel, rendermesh::SkinnedMesh, rotator::Rotator, transform3d::{PrevTransform3D, Transform3D}};
+
+// start tracking previous transforms for entities with the given components
+fn track_moving<Q: Query>(world: &mut World, cmd_buffer: &mut CommandBuffer) {
+    for (eid, transform) in world.query_mut::<&Transform3D>().with::<Q>().without::<&PrevTransform3D>() {
+        cmd_buffer.insert_one(eid, PrevTransform3D { transform: *transform });
+    }
+}
+
+/// System which remembers the transform of each moving entity at the start of a tick, so rendering can interpolate between ticks
+pub fn prev_transform_update(world: &mut World) {
+    for (_, (transform, prev)) in world.query_mut::<(&Transform3D, &mut PrevTransform3D)>() {
+        prev.transform = *transform;
+    }
+
+    let mut cmd_buffer = CommandBuffer::new();
+    track_moving::<&CharacterController>(world, &mut cmd_buffer);
+    track_moving::<&MapModel>(world, &mut cmd_buffer);
+    track_moving::<&Rotator>(world, &mut cmd_buffer);
+    track_moving::<&SkinnedMesh>(world, &mut cmd_buffer);
+    track_moving::<&Camera>(world, &mut cmd_buffer);
+    cmd_buffer.run_on(world);
+}
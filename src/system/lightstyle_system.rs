@@ -0,0 +1,39 @@
+use hecs::World;
+
+use crate::{component::{light::SwitchableLight, triggerable::TriggerState}, gamestate::{MapData, TimeData}, lightstyle::{get_lightstyle, sample_pattern}, system::render_system::CUSTOM_LIGHT_LAYER_START};
+
+/// System which toggles switchable lights when triggered & writes the resulting brightness into the map's custom light layers
+pub fn lightstyle_system_update(time: &TimeData, map_data: &mut MapData, world: &mut World) {
+    // layers without a switchable light just follow the lightstyle table
+    for (idx, layer) in map_data.light_layers.iter_mut().enumerate() {
+        *layer = sample_pattern(&get_lightstyle(idx + CUSTOM_LIGHT_LAYER_START), time.total_time);
+    }
+
+    for (_, (light, trigger)) in world.query_mut::<(&mut SwitchableLight, Option<&TriggerState>)>() {
+        // lights toggle each time they are triggered
+        let triggered = trigger.is_some_and(|x| x.triggered);
+
+        if triggered && !light.prev_triggered {
+            light.on = !light.on;
+        }
+        light.prev_triggered = triggered;
+
+        let target_fade = if light.on { 1.0 } else { 0.0 };
+
+        if light.fade_time > 0.0 {
+            let max_delta = time.delta_time / light.fade_time;
+            light.fade += (target_fade - light.fade).clamp(-max_delta, max_delta);
+        }
+        else {
+            light.fade = target_fade;
+        }
+
+        let on_value = match &light.pattern {
+            Some(pattern) => sample_pattern(pattern, time.total_time),
+            None => sample_pattern(&get_lightstyle(light.layer + CUSTOM_LIGHT_LAYER_START), time.total_time)
+        };
+        let off_value = sample_pattern(&light.off_pattern, time.total_time);
+
+        map_data.light_layers[light.layer] = (off_value * (1.0 - light.fade)) + (on_value * light.fade);
+    }
+}
@@ -8,4 +8,10 @@ pub mod door_system;
 pub mod triggerable_system;
 pub mod anim_system;
 pub mod effect_system;
-pub mod ccmd_system;
\ No newline at end of file
+pub mod ccmd_system;
+pub mod mover_system;
+pub mod lightstyle_system;
+pub mod audio_system;
+pub mod thirdpersoncam_system;
+pub mod interpolation_system;
+pub mod splitscreen_system;
\ No newline at end of file
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use hecs::{Entity, World};
+use log::info;
+
+use crate::{bsp::bspfile::MASK_SOLID, component::{charactercontroller::{CharacterController, CharacterState}, door::DoorOpener, health::Health, mapmodel::MapModel, plat::{Plat, PlatState}, train::{PathCorner, Train}, transform3d::Transform3D, triggerable::TriggerState}, gamestate::{MapData, TimeData}, math::{Matrix4x4, Quaternion, Vector3}, physics::PhysicsWorld};
+
+// amount mover bounds are shrunk by when checking for blockers, so that characters merely touching a mover don't block it
+const BLOCK_EPSILON: f32 = 0.1;
+
+/// Interval at which crushing movers deal their damage to blocking entities (matches Quake 2's 10Hz server frames)
+pub const CRUSH_INTERVAL: f32 = 0.1;
+
+// compute the movement delta towards a target position at the given speed, & whether the target will be reached
+fn step_towards(position: Vector3, target_pos: Vector3, speed: f32, delta_time: f32) -> (Vector3, bool) {
+    let delta = target_pos - position;
+    let max_delta = speed * delta_time;
+
+    if delta.length_sq() > max_delta * max_delta {
+        (delta.normalized() * max_delta, false)
+    }
+    else {
+        (delta, true)
+    }
+}
+
+// local to world matrix of a map model with the given transform, & its inverse
+fn mapmodel_matrices(transform: &Transform3D) -> (Matrix4x4, Matrix4x4) {
+    let local2world = Matrix4x4::scale(transform.scale)
+        * Matrix4x4::rotation(transform.rotation)
+        * Matrix4x4::translation(transform.position);
+
+    let world2local = Matrix4x4::translation(transform.position * -1.0)
+        * Matrix4x4::rotation(transform.rotation.inverted())
+        * Matrix4x4::scale(1.0 / transform.scale);
+
+    (local2world, world2local)
+}
+
+// move a map model by the given translation & rotation, pushing characters in its way & carrying characters riding on it.
+// Characters are pushed by the distance their position would move if they were attached to the mover, after sweeping the mover
+// along that path so that fast movers can't skip past them
+fn push_mapmodel(map_data: &MapData, world: &mut World, mover: Entity, delta: Vector3, rotation: Quaternion) -> Vec<Entity> {
+    let (model_idx, old_transform) = {
+        let mapmodel = world.get::<&MapModel>(mover).unwrap();
+        let transform = world.get::<&Transform3D>(mover).unwrap();
+
+        (mapmodel.model_idx, *transform)
+    };
+
+    let new_transform = old_transform
+        .with_position(old_transform.position + delta)
+        .with_rotation(old_transform.rotation * rotation);
+
+    let (_, old_world2local) = mapmodel_matrices(&old_transform);
+    let (new_local2world, new_world2local) = mapmodel_matrices(&new_transform);
+
+    let mut pushes = Vec::new();
+    let mut blocked = Vec::new();
+
+    {
+        let physics = PhysicsWorld::new(&map_data.map, world);
+
+        for (e, (cc, cstate, transform)) in world.query::<(&CharacterController, &CharacterState, &Transform3D)>().iter() {
+            let box_extents = Vector3::new(cc.radius, cc.radius, cstate.height * 0.5);
+            let box_pos = transform.position + (Vector3::unit_z() * cc.height_offset);
+
+            // where the character would end up if it were attached to the mover
+            let push = new_local2world.transform_point(old_world2local.transform_point(box_pos)) - box_pos;
+
+            let riding = cstate.ground_entity == Some(mover);
+
+            if !riding {
+                // sweeping the character away from the mover is equivalent to sweeping the mover into the character
+                let trace = physics.boxcast_entity(mover, MASK_SOLID, box_pos, box_pos - push, box_extents);
+
+                if !trace.start_solid && trace.fraction >= 1.0 {
+                    continue;
+                }
+            }
+
+            // push character along with the mover, & check whether that got it out of the way
+            let (new_box_pos, _, _) = physics.sweep(MASK_SOLID, box_pos, push, 1.0, box_extents, &[e, mover]);
+
+            let local_pos = new_world2local.transform_point(new_box_pos);
+            let shrunk_extents = box_extents - Vector3::new(BLOCK_EPSILON, BLOCK_EPSILON, BLOCK_EPSILON);
+
+            if map_data.map.boxtrace(model_idx + 1, MASK_SOLID, local_pos, local_pos, shrunk_extents).start_solid {
+                blocked.push(e);
+            }
+            else {
+                pushes.push((e, new_box_pos - box_pos));
+            }
+        }
+    }
+
+    if blocked.is_empty() {
+        for (e, push) in pushes {
+            let mut transform = world.get::<&mut Transform3D>(e).unwrap();
+            transform.position = transform.position + push;
+        }
+
+        *world.get::<&mut Transform3D>(mover).unwrap() = new_transform;
+    }
+
+    blocked
+}
+
+/// Attempt to move a map model entity by the given delta, pushing any characters in the way or riding on it along with it.
+/// If any characters cannot be pushed out of the way, nothing is moved & the blocking characters are returned
+pub fn try_move_mapmodel(map_data: &MapData, world: &mut World, mover: Entity, delta: Vector3) -> Vec<Entity> {
+    push_mapmodel(map_data, world, mover, delta, Quaternion::identity())
+}
+
+/// Attempt to rotate a map model entity around its origin, pushing any characters in the way or riding on it along with it.
+/// If any characters cannot be pushed out of the way, nothing is moved & the blocking characters are returned
+pub fn try_rotate_mapmodel(map_data: &MapData, world: &mut World, mover: Entity, rotation: Quaternion) -> Vec<Entity> {
+    push_mapmodel(map_data, world, mover, Vector3::zero(), rotation)
+}
+
+/// Apply damage to each of the given entities which has a Health component
+pub fn damage_entities(world: &mut World, entities: &[Entity], damage: f32) {
+    for e in entities {
+        if let Ok(mut health) = world.get::<&mut Health>(*e) {
+            let was_alive = health.health > 0.0;
+            health.health -= damage;
+
+            if was_alive && health.health <= 0.0 {
+                info!("Entity {:?} was crushed", e);
+            }
+        }
+    }
+}
+
+/// System which lowers plats while entities tagged as DoorOpener stand on them, raising them again after a delay
+pub fn plat_system_update(time: &TimeData, map_data: &MapData, world: &mut World) {
+    // gather entities currently being stood on by players
+    let occupied = world.query_mut::<(&DoorOpener, &CharacterState)>()
+        .into_iter()
+        .filter_map(|(_, (_, cstate))| cstate.ground_entity)
+        .collect::<Vec<_>>();
+
+    let mut moves = Vec::new();
+
+    for (e, (plat, transform, trigger)) in world.query_mut::<(&mut Plat, &Transform3D, Option<&TriggerState>)>() {
+        let activated = occupied.contains(&e) || trigger.is_some_and(|x| x.triggered);
+
+        match plat.state {
+            PlatState::Top => {
+                if activated {
+                    plat.state = PlatState::MovingDown;
+                }
+            }
+            PlatState::MovingDown => {
+                let (delta, reached) = step_towards(transform.position, plat.bottom_pos, plat.move_speed, time.delta_time);
+                moves.push((e, delta, reached));
+            }
+            PlatState::Bottom => {
+                // hold plat at the bottom for as long as it's occupied
+                if activated {
+                    plat.wait_timer = plat.wait;
+                }
+                else {
+                    plat.wait_timer -= time.delta_time;
+
+                    if plat.wait_timer <= 0.0 {
+                        plat.state = PlatState::MovingUp;
+                    }
+                }
+            }
+            PlatState::MovingUp => {
+                let (delta, reached) = step_towards(transform.position, plat.top_pos, plat.move_speed, time.delta_time);
+                moves.push((e, delta, reached));
+            }
+        }
+    }
+
+    for (e, delta, reached) in moves {
+        let blocked = try_move_mapmodel(map_data, world, e, delta);
+        let mut plat = world.get::<&mut Plat>(e).unwrap();
+
+        if !blocked.is_empty() {
+            // blocked plats hurt whatever is in the way & head back the way they came
+            let dmg = plat.dmg;
+
+            plat.state = if plat.state == PlatState::MovingUp { PlatState::MovingDown } else { PlatState::MovingUp };
+            drop(plat);
+
+            damage_entities(world, &blocked, dmg);
+        }
+        else if reached {
+            if plat.state == PlatState::MovingDown {
+                plat.state = PlatState::Bottom;
+                plat.wait_timer = plat.wait;
+            }
+            else {
+                plat.state = PlatState::Top;
+            }
+        }
+    }
+}
+
+// set the path corner a train is moving towards, applying that corner's speed override if it has one
+fn set_train_target(train: &mut Train, target: Option<Entity>, corners: &HashMap<Entity, (Vector3, PathCorner)>) {
+    train.target = target;
+
+    if let Some((_, corner)) = target.and_then(|x| corners.get(&x)) {
+        if corner.speed > 0.0 {
+            train.cur_speed = corner.speed;
+        }
+    }
+}
+
+/// System which moves trains along their chain of path corners
+pub fn train_system_update(time: &TimeData, map_data: &MapData, world: &mut World) {
+    // gather path corners
+    let corners = world.query_mut::<(&PathCorner, &Transform3D)>()
+        .into_iter()
+        .map(|(e, (corner, transform))| (e, (transform.position, PathCorner { next: corner.next, wait: corner.wait, speed: corner.speed })))
+        .collect::<HashMap<_, _>>();
+
+    let mut moves = Vec::new();
+
+    for (e, (train, transform, trigger)) in world.query_mut::<(&mut Train, &mut Transform3D, Option<&TriggerState>)>() {
+        // trains without a targetname are always active
+        let triggered = trigger.is_none_or(|x| x.triggered);
+
+        // re-triggering a train restarts it if it was stopped at a path corner
+        if triggered && !train.prev_triggered {
+            train.stopped = false;
+        }
+        train.prev_triggered = triggered;
+
+        if !train.started {
+            // snap train to the first corner in its path
+            train.started = true;
+
+            if let Some((corner_pos, corner)) = train.target.and_then(|x| corners.get(&x)) {
+                transform.position = *corner_pos - train.corner_offset;
+                set_train_target(train, corner.next, &corners);
+            }
+
+            continue;
+        }
+
+        if !triggered || train.stopped {
+            continue;
+        }
+
+        if train.wait_timer > 0.0 {
+            train.wait_timer -= time.delta_time;
+            continue;
+        }
+
+        let corner_pos = match train.target.and_then(|x| corners.get(&x)) {
+            Some((corner_pos, _)) => *corner_pos,
+            None => continue
+        };
+
+        let (delta, reached) = step_towards(transform.position, corner_pos - train.corner_offset, train.cur_speed, time.delta_time);
+        moves.push((e, delta, reached));
+    }
+
+    for (e, delta, reached) in moves {
+        let blocked = try_move_mapmodel(map_data, world, e, delta);
+        let mut train = world.get::<&mut Train>(e).unwrap();
+
+        if !blocked.is_empty() {
+            // trains never give way, & just keep crushing whatever is blocking them
+            let dmg = train.dmg * (time.delta_time / CRUSH_INTERVAL);
+            drop(train);
+
+            damage_entities(world, &blocked, dmg);
+        }
+        else if reached {
+            let (_, corner) = &corners[&train.target.unwrap()];
+
+            // a wait of -1 stops the train until it is triggered again
+            if corner.wait < 0.0 {
+                train.stopped = true;
+            }
+            else {
+                train.wait_timer = corner.wait;
+            }
+
+            set_train_target(&mut train, corner.next, &corners);
+        }
+    }
+}
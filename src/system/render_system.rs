@@ -1,58 +1,16 @@
 use hecs::World;
-use lazy_static::lazy_static;
 use rayon::prelude::*;
 
-use crate::{asset_loader::ModelHandle, bsp::{bspcommon::{aabb_frustum, coord_space_transform, extract_frustum, transform_aabb}, bspfile::{BspFile, LSHProbeSample}, bsprenderer::BspMapRenderer}, component::{camera::Camera, effect::Effect, mapmodel::MapModel, meshpose::MeshPose, rendermesh::{RenderMesh, SkinnedMesh}, transform3d::Transform3D}, gamestate::{MapData, TimeData, WindowData}, graphics::model::{MeshVertex, Model, ModelSkin}, math::{Matrix4x4, Vector4}, misc::AABB};
+use crate::{asset_loader::ModelHandle, bsp::{bspcommon::{aabb_frustum, coord_space_transform, extract_frustum, transform_aabb}, bspfile::{BspFile, LSHProbeSample}, bsprenderer::BspMapRenderer}, component::{camera::Camera, effect::Effect, light::Light, mapmodel::MapModel, meshpose::MeshPose, rendermesh::{RenderMesh, SkinnedMesh}, transform3d::{PrevTransform3D, Transform3D}}, cvar::get_cvar, gamestate::{MapData, TimeData, WindowData}, graphics::{dynamic_light::{DynamicLight, DynamicLightSet, MAX_DYNAMIC_LIGHTS}, model::{MeshVertex, Model, ModelSkin}}, lightstyle::{evaluate_lightstyles, NUM_LIGHTSTYLES}, math::{Matrix4x4, Vector4}, misc::AABB};
 
 pub const NUM_CUSTOM_LIGHT_LAYERS: usize = 30;
 pub const CUSTOM_LIGHT_LAYER_START: usize = 32;
 pub const CUSTOM_LIGHT_LAYER_END: usize = CUSTOM_LIGHT_LAYER_START + NUM_CUSTOM_LIGHT_LAYERS;
 
-lazy_static! {
-    static ref LIGHTSTYLES: [Vec<f32>;13] = [
-        make_light_table(b"m"),
-        // 1 - FLICKER 1
-        make_light_table(b"mmnmmommommnonmmonqnmmo"),
-        // 2 - SLOW STRONG PULSE
-        make_light_table(b"abcdefghijklmnopqrstuvwxyzyxwvutsrqponmlkjihgfedcba"),
-        // 3 - CANDLE 1
-        make_light_table(b"mmmmmaaaaammmmmaaaaaabcdefgabcdefg"),
-        // 4 - FAST STROBE
-        make_light_table(b"mamamamamama"),
-        // 5 - GENTLE PULSE
-        make_light_table(b"jklmnopqrstuvwxyzyxwvutsrqponmlkj"),
-        // 6 - FLICKER 2
-        make_light_table(b"nmonqnmomnmomomno"),
-        // 7 - CANDLE 2
-        make_light_table(b"mmmaaaabcdefgmmmmaaaammmaamm"),
-        // 8 - CANDLE 3
-        make_light_table(b"mmmaaammmaaammmabcdefaaaammmmabcdefmmmaaaa"),
-        // 9 - SLOW STROBE
-        make_light_table(b"aaaaaaaazzzzzzzz"),
-        // 10 - FLUORESCENT FLICKER
-        make_light_table(b"mmamammmmammamamaaamammma"),
-        // 11 - SLOW PULSE, NO BLACK
-        make_light_table(b"abcdefghijklmnopqrrqponmlkjihgfedcba"),
-        // 12 - FAST PULSE
-        make_light_table(b"acegikmoqsuwyywusqomkigeca"),
-    ];
-}
-
-// convert Quake-style light animation table to float array ('a' is minimum light, 'z' is maximum light)
-fn make_light_table(data: &[u8]) -> Vec<f32> {
-    let mut output = vec![0.0;data.len()];
-
-    for i in 0..data.len() {
-        output[i] = (data[i] - 97) as f32 / 25.0;
-    }
-
-    output
-}
-
 fn sort_mesh_iter(renderer: &BspMapRenderer, bsp: &BspFile, frustum: &[Vector4], mesh: &RenderMesh, entity_idx: usize,
     cur_node: &mut usize, parent_transform: Matrix4x4, viewproj: &Matrix4x4, sh: &LSHProbeSample,
-    out_opaque_meshes: &mut Vec<(Matrix4x4, Matrix4x4, Vector4, Vector4, Vector4, ModelHandle, usize, usize, usize, isize, f32)>,
-    out_transparent_meshes: &mut Vec<(Matrix4x4, Matrix4x4, Vector4, Vector4, Vector4, ModelHandle, usize, usize, usize, isize, f32)>
+    out_opaque_meshes: &mut Vec<(Matrix4x4, Matrix4x4, Vector4, Vector4, Vector4, ModelHandle, usize, usize, usize, isize, f32, AABB)>,
+    out_transparent_meshes: &mut Vec<(Matrix4x4, Matrix4x4, Vector4, Vector4, Vector4, ModelHandle, usize, usize, usize, isize, f32, AABB)>
 ) {
     let node = &mesh.mesh.nodes[*cur_node];
     let node_xform = node.transform * parent_transform;
@@ -83,6 +41,7 @@ fn sort_mesh_iter(renderer: &BspMapRenderer, bsp: &BspFile, frustum: &[Vector4],
                         entity_idx,
                         node.skin_index,
                         depth,
+                        bounds,
                     ));
                 }
                 else {
@@ -98,6 +57,7 @@ fn sort_mesh_iter(renderer: &BspMapRenderer, bsp: &BspFile, frustum: &[Vector4],
                         entity_idx,
                         node.skin_index,
                         depth,
+                        bounds,
                     ));
                 }
             }
@@ -205,7 +165,7 @@ fn update_skinned_mesh_iter(mesh: &RenderMesh, pose: &MeshPose, sk: &mut Skinned
     }
 }
 
-fn draw_mesh_part(mesh: &Model, mesh_index: usize, part_index: usize, sk: Option<&SkinnedMesh>, sh_r: Vector4, sh_g: Vector4, sh_b: Vector4, local_to_world: Matrix4x4, mvp: Matrix4x4, skin_index: isize) {
+fn draw_mesh_part(mesh: &Model, mesh_index: usize, part_index: usize, sk: Option<&SkinnedMesh>, sh_r: Vector4, sh_g: Vector4, sh_b: Vector4, lights: &DynamicLightSet, local_to_world: Matrix4x4, mvp: Matrix4x4, skin_index: isize) {
     let part = &mesh.meshes[mesh_index].parts[part_index];
 
     if let Some((vtx_buffer, idx_buffer)) = &part.buffers {
@@ -230,6 +190,7 @@ fn draw_mesh_part(mesh: &Model, mesh_index: usize, part_index: usize, sk: Option
         mat.shader.inner.set_uniform_vec4("shB", sh_b);
         mat.shader.inner.set_uniform_mat4("localToWorld", local_to_world);
         mat.shader.inner.set_uniform_mat4("mvp", mvp);
+        lights.apply(&mat.shader.inner);
 
         unsafe {
             gl::FrontFace(part.winding);
@@ -257,64 +218,85 @@ pub fn skinning_system(world: &mut World) {
     }
 }
 
-/// System which performs all rendering (world + entities)
-pub fn render_system(time: &TimeData, window_data: &WindowData, map_data: &mut MapData, world: &mut World) {
+/// System which performs all rendering (world + entities). Moving entities & cameras are drawn interpolated between their previous & current tick
+/// transforms, where alpha is how far the current frame is between the two (0.0 = previous tick, 1.0 = current tick)
+pub fn render_system(time: &TimeData, alpha: f32, window_data: &WindowData, map_data: &mut MapData, world: &mut World) {
+    let render_data = match &mut map_data.render_data {
+        Some(v) => v,
+        None => return
+    };
+
     // gather map models
-    let mut mapmodel_iter = world.query::<(&MapModel, &Transform3D)>();
+    let mut mapmodel_iter = world.query::<(&MapModel, &Transform3D, Option<&PrevTransform3D>)>();
     let mapmodels = mapmodel_iter
         .iter()
+        .map(|(e, (mapmodel, transform, prev))| (e, (mapmodel, transform.interpolated(prev, alpha))))
         .collect::<Vec<_>>();
 
     // gather static meshes
-    let mut mesh_iter = world.query::<(&RenderMesh, &Transform3D)>().without::<&SkinnedMesh>();
+    let mut mesh_iter = world.query::<(&RenderMesh, &Transform3D, Option<&PrevTransform3D>)>().without::<&SkinnedMesh>();
     let meshes = mesh_iter
         .iter()
+        .map(|(e, (mesh, transform, prev))| (e, (mesh, transform.interpolated(prev, alpha))))
         .collect::<Vec<_>>();
 
     // gather skinned meshes
-    let mut sk_mesh_iter = world.query::<(&RenderMesh, &Transform3D, &SkinnedMesh, &MeshPose)>();
+    let mut sk_mesh_iter = world.query::<(&RenderMesh, &Transform3D, Option<&PrevTransform3D>, &SkinnedMesh, &MeshPose)>();
     let sk_meshes = sk_mesh_iter
         .iter()
+        .map(|(e, (mesh, transform, prev, sk, pose))| (e, (mesh, transform.interpolated(prev, alpha), sk, pose)))
+        .collect::<Vec<_>>();
+
+    // gather lights
+    let mut light_iter = world.query::<(&Light, &Transform3D, Option<&PrevTransform3D>)>();
+    let lights = light_iter
+        .iter()
+        .map(|(_, (light, transform, prev))| DynamicLight { position: transform.interpolated(prev, alpha).position, color: light.color, radius: light.max_radius })
         .collect::<Vec<_>>();
 
+    let max_lights = get_cvar::<i32>("r_dynamic_lights").clamp(0, MAX_DYNAMIC_LIGHTS as i32) as usize;
+
     // gather effects
     let mut effect_iter = world.query::<(&mut Effect, &Transform3D)>();
 
     // gather cameras
-    let mut camera_iter = world.query::<(&Transform3D, &Camera)>();
+    let mut camera_iter = world.query::<(&Transform3D, Option<&PrevTransform3D>, &Camera)>();
     let cameras = camera_iter
         .iter()
+        .map(|(e, (transform, prev, camera))| (e, (transform.interpolated(prev, alpha), camera)))
         .collect::<Vec<_>>();
 
     // compute light layers
-    let lightstyle_frame = (time.total_time * 10.0) as usize;
-    let lightstyle_frame_lerp = (time.total_time * 10.0).fract();
-    let mut light_styles = [0.0;256];
-
-    for (idx, tbl) in LIGHTSTYLES.iter().enumerate() {
-        let a = tbl[lightstyle_frame % tbl.len()];
-        let b = tbl[(lightstyle_frame + 1) % tbl.len()];
-        light_styles[idx] = (a * (1.0 - lightstyle_frame_lerp)) + (b * lightstyle_frame_lerp);
-    }
+    let mut light_styles = [0.0;NUM_LIGHTSTYLES];
+    evaluate_lightstyles(time.total_time, &mut light_styles);
 
     for (idx, sc) in map_data.light_layers.iter().enumerate() {
         light_styles[idx + CUSTOM_LIGHT_LAYER_START] = *sc;
     }
 
+    // clear the whole window first, as camera viewports may not cover all of it (for example, three player split-screen)
+    unsafe {
+        gl::Viewport(0, 0, window_data.width, window_data.height);
+        gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+        gl::ClearDepth(1.0);
+
+        gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+        gl::DepthMask(gl::TRUE);
+
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    }
+
     // draw cameras
     let mut camera_index = 0;
     for (_, (transform, camera)) in cameras {
-        let aspect = match camera.viewport_rect {
-            Some(v) => {
-                unsafe { gl::Viewport(v.x, v.y, v.w, v.h); }
-                v.w as f32 / v.h as f32
-            }
-            None => {
-                unsafe { gl::Viewport(0, 0, window_data.width, window_data.height); }
-                window_data.width as f32 / window_data.height as f32
-            }
+        let (vp_x, vp_y, vp_w, vp_h) = match camera.viewport_rect {
+            Some(v) => (v.x, v.y, v.w, v.h),
+            None => (0, 0, window_data.width, window_data.height)
         };
 
+        unsafe { gl::Viewport(vp_x, vp_y, vp_w, vp_h); }
+        let aspect = vp_w as f32 / vp_h as f32;
+
         // build view & projection matrices
         let cam_rot_inv = transform.rotation.inverted();
 
@@ -336,12 +318,16 @@ pub fn render_system(time: &TimeData, window_data: &WindowData, map_data: &mut M
             gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
             gl::DepthMask(gl::TRUE);
 
+            // glClear ignores the viewport, so scissor it to avoid wiping out other cameras
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Scissor(vp_x, vp_y, vp_w, vp_h);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            gl::Disable(gl::SCISSOR_TEST);
         }
 
         // retrieve map renderer for camera
-        map_data.update_renderer_cache(camera_index);
-        let renderer = &mut map_data.map_renderers[camera_index];
+        render_data.update_renderer_cache(&map_data.map, camera_index);
+        let renderer = &mut render_data.map_renderers[camera_index];
 
         // gather visible models
         let mut visible_model_transforms = Vec::new();
@@ -417,43 +403,58 @@ pub fn render_system(time: &TimeData, window_data: &WindowData, map_data: &mut M
         });
 
         // update models
-        map_data.map_model_renderer.update(&light_styles, &visible_model_indices);
+        render_data.map_model_renderer.update(&light_styles, &visible_model_indices);
 
         // update with new camera position
-        renderer.update(&frustum, &light_styles, &map_data.map, &map_data.map_textures, &map_data.map_lightmap, transform.position);
+        renderer.update(&frustum, &light_styles, &map_data.map, &render_data.map_textures, &render_data.map_lightmap, transform.position);
+
+        // cull lights which don't reach any leaf visible to this camera
+        let visible_lights = if max_lights > 0 {
+            lights.iter()
+                .filter(|x| renderer.check_vis(&map_data.map, &x.bounds()))
+                .copied()
+                .collect::<Vec<_>>()
+        }
+        else {
+            Vec::new()
+        };
 
         // draw opaque map geometry
-        renderer.draw_opaque(&map_data.map_textures, &map_data.map_lightmap, time.total_time, viewproj);
+        renderer.draw_opaque(&render_data.map_textures, &render_data.map_lightmap, time.total_time, viewproj, &visible_lights, max_lights);
 
         for (idx, transform) in visible_model_indices.iter().zip(&visible_model_transforms) {
-            map_data.map_model_renderer.draw_model(false, &map_data.map_textures, &map_data.map_lightmap, *idx, *transform, viewproj);
+            render_data.map_model_renderer.draw_model(false, &render_data.map_textures, &render_data.map_lightmap, *idx, *transform, viewproj, &visible_lights, max_lights);
         }
 
         // draw opaque mesh parts
-        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, _, _, _) in opaque_meshes {
-            draw_mesh_part(&model, mesh_idx, part_idx, None, sh_r, sh_g, sh_b, local_to_world, mvp, -1);
+        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, _, _, _, bounds) in opaque_meshes {
+            let mesh_lights = DynamicLightSet::select(&visible_lights, &bounds, max_lights);
+            draw_mesh_part(&model, mesh_idx, part_idx, None, sh_r, sh_g, sh_b, &mesh_lights, local_to_world, mvp, -1);
         }
 
-        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, entity_idx, skin_index, _) in opaque_sk_meshes {
+        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, entity_idx, skin_index, _, bounds) in opaque_sk_meshes {
             let sk = sk_meshes[entity_idx].1.2;
-            draw_mesh_part(&model, mesh_idx, part_idx, Some(sk), sh_r, sh_g, sh_b, local_to_world, mvp, skin_index);
+            let mesh_lights = DynamicLightSet::select(&visible_lights, &bounds, max_lights);
+            draw_mesh_part(&model, mesh_idx, part_idx, Some(sk), sh_r, sh_g, sh_b, &mesh_lights, local_to_world, mvp, skin_index);
         }
 
         // draw transparent map geometry
-        renderer.draw_transparent(&map_data.map_textures, &map_data.map_lightmap, time.total_time, viewproj);
+        renderer.draw_transparent(&render_data.map_textures, &render_data.map_lightmap, time.total_time, viewproj, &visible_lights, max_lights);
 
         for (idx, transform) in visible_model_indices.iter().zip(&visible_model_transforms) {
-            map_data.map_model_renderer.draw_model(true, &map_data.map_textures, &map_data.map_lightmap, *idx, *transform, viewproj);
+            render_data.map_model_renderer.draw_model(true, &render_data.map_textures, &render_data.map_lightmap, *idx, *transform, viewproj, &visible_lights, max_lights);
         }
 
         // draw transparent mesh parts
-        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, _, _, _) in transparent_meshes {
-            draw_mesh_part(&model, mesh_idx, part_idx, None, sh_r, sh_g, sh_b, local_to_world, mvp, -1);
+        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, _, _, _, bounds) in transparent_meshes {
+            let mesh_lights = DynamicLightSet::select(&visible_lights, &bounds, max_lights);
+            draw_mesh_part(&model, mesh_idx, part_idx, None, sh_r, sh_g, sh_b, &mesh_lights, local_to_world, mvp, -1);
         }
 
-        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, entity_idx, skin_index, _) in transparent_sk_meshes {
+        for (mvp, local_to_world, sh_r, sh_g, sh_b, model, mesh_idx, part_idx, entity_idx, skin_index, _, bounds) in transparent_sk_meshes {
             let sk = sk_meshes[entity_idx].1.2;
-            draw_mesh_part(&model, mesh_idx, part_idx, Some(sk), sh_r, sh_g, sh_b, local_to_world, mvp, skin_index);
+            let mesh_lights = DynamicLightSet::select(&visible_lights, &bounds, max_lights);
+            draw_mesh_part(&model, mesh_idx, part_idx, Some(sk), sh_r, sh_g, sh_b, &mesh_lights, local_to_world, mvp, skin_index);
         }
 
         // draw effects
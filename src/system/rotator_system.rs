@@ -1,15 +1,33 @@
 use hecs::World;
 
-use crate::{component::{rotator::Rotator, transform3d::Transform3D}, gamestate::TimeData, math::Quaternion};
+use crate::{component::{mapmodel::MapModel, rotator::Rotator, transform3d::Transform3D}, gamestate::{MapData, TimeData}, math::Quaternion, system::mover_system::{damage_entities, try_rotate_mapmodel, CRUSH_INTERVAL}};
 
-/// System which rotates entities with a Rotator component
-pub fn rotator_system_update(time: &TimeData, world: &mut World) {
-    for (_, (transform, rotator)) in world.query_mut::<(&mut Transform3D, &Rotator)>() {
-        let a = (rotator.rot_speed * time.delta_time) * 0.5;
-        let sa = a.sin();
-        let ca = a.cos();
-        let rot = Quaternion::new(rotator.rot_axis.x * sa, rotator.rot_axis.y * sa, rotator.rot_axis.z * sa, ca);
+// rotation applied by a rotator over the given time step
+fn rotator_step(rotator: &Rotator, delta_time: f32) -> Quaternion {
+    let a = (rotator.rot_speed * delta_time) * 0.5;
+    let sa = a.sin();
+    let ca = a.cos();
 
-        transform.rotation = transform.rotation * rot;
+    Quaternion::new(rotator.rot_axis.x * sa, rotator.rot_axis.y * sa, rotator.rot_axis.z * sa, ca)
+}
+
+/// System which rotates entities with a Rotator component. Rotating map models push characters out of the way like other movers,
+/// & stop (damaging whatever is in the way) while blocked
+pub fn rotator_system_update(time: &TimeData, map_data: &MapData, world: &mut World) {
+    for (_, (transform, rotator)) in world.query_mut::<(&mut Transform3D, &Rotator)>().without::<&MapModel>() {
+        transform.rotation = transform.rotation * rotator_step(rotator, time.delta_time);
+    }
+
+    let moves = world.query_mut::<(&Rotator, &MapModel)>()
+        .into_iter()
+        .map(|(e, (rotator, _))| (e, rotator_step(rotator, time.delta_time), rotator.dmg))
+        .collect::<Vec<_>>();
+
+    for (e, rotation, dmg) in moves {
+        let blocked = try_rotate_mapmodel(map_data, world, e, rotation);
+
+        if !blocked.is_empty() {
+            damage_entities(world, &blocked, dmg * (time.delta_time / CRUSH_INTERVAL));
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,89 @@
+use hecs::World;
+
+use crate::{component::camera::Camera, gamestate::WindowData, misc::Rectangle};
+
+/// Viewport for the given player when the window is shared between num_players, in GL coordinates (Y pointing up from the bottom of the window).
+/// Two players split the window into top & bottom halves, three or four players split it into quarters. A single player gets the whole window (None)
+pub fn splitscreen_viewport(player_index: usize, num_players: usize, width: i32, height: i32) -> Option<Rectangle> {
+    let bottom_h = height / 2;
+    let top_h = height - bottom_h;
+
+    match num_players {
+        0 | 1 => None,
+        2 => {
+            if player_index == 0 {
+                Some(Rectangle::new(0, bottom_h, width, top_h))
+            }
+            else {
+                Some(Rectangle::new(0, 0, width, bottom_h))
+            }
+        }
+        _ => {
+            let left_w = width / 2;
+            let right_w = width - left_w;
+
+            let (x, w) = if player_index.is_multiple_of(2) { (0, left_w) } else { (left_w, right_w) };
+            let (y, h) = if player_index < 2 { (bottom_h, top_h) } else { (0, bottom_h) };
+
+            Some(Rectangle::new(x, y, w, h))
+        }
+    }
+}
+
+/// System which lays out the viewports of player cameras so that each local player gets their own section of the window
+pub fn splitscreen_viewport_update(window_data: &WindowData, num_players: usize, world: &mut World) {
+    for (_, camera) in world.query_mut::<&mut Camera>() {
+        if let Some(player_index) = camera.player_index {
+            camera.viewport_rect = splitscreen_viewport(player_index, num_players, window_data.width, window_data.height);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(player_index: usize, num_players: usize, width: i32, height: i32) -> (i32, i32, i32, i32) {
+        let rect = splitscreen_viewport(player_index, num_players, width, height).unwrap();
+        (rect.x, rect.y, rect.w, rect.h)
+    }
+
+    #[test]
+    fn single_player_uses_whole_window() {
+        assert!(splitscreen_viewport(0, 1, 1280, 720).is_none());
+        assert!(splitscreen_viewport(0, 0, 1280, 720).is_none());
+    }
+
+    #[test]
+    fn two_players_split_top_and_bottom() {
+        assert_eq!(viewport(0, 2, 1280, 720), (0, 360, 1280, 360));
+        assert_eq!(viewport(1, 2, 1280, 720), (0, 0, 1280, 360));
+    }
+
+    #[test]
+    fn three_players_use_three_quarters() {
+        assert_eq!(viewport(0, 3, 1280, 720), (0, 360, 640, 360));
+        assert_eq!(viewport(1, 3, 1280, 720), (640, 360, 640, 360));
+        assert_eq!(viewport(2, 3, 1280, 720), (0, 0, 640, 360));
+    }
+
+    #[test]
+    fn four_players_split_into_quarters() {
+        assert_eq!(viewport(0, 4, 1280, 720), (0, 360, 640, 360));
+        assert_eq!(viewport(1, 4, 1280, 720), (640, 360, 640, 360));
+        assert_eq!(viewport(2, 4, 1280, 720), (0, 0, 640, 360));
+        assert_eq!(viewport(3, 4, 1280, 720), (640, 0, 640, 360));
+    }
+
+    #[test]
+    fn odd_window_sizes_cover_every_pixel() {
+        // the top & right viewports take the leftover row/column
+        assert_eq!(viewport(0, 2, 1279, 719), (0, 359, 1279, 360));
+        assert_eq!(viewport(1, 2, 1279, 719), (0, 0, 1279, 359));
+
+        assert_eq!(viewport(0, 4, 1279, 719), (0, 359, 639, 360));
+        assert_eq!(viewport(1, 4, 1279, 719), (639, 359, 640, 360));
+        assert_eq!(viewport(2, 4, 1279, 719), (0, 0, 639, 359));
+        assert_eq!(viewport(3, 4, 1279, 719), (639, 0, 640, 359));
+    }
+}
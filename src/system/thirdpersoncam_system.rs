@@ -0,0 +1,67 @@
+use hecs::World;
+
+use crate::{bsp::bspfile::{BspFile, MASK_SOLID}, component::{camera::{ThirdPersonCamera, ThirdPersonMode}, fpview::FPView, transform3d::Transform3D}, gamestate::TimeData, math::{Matrix4x4, Quaternion, Vector3}, physics::PhysicsWorld};
+
+/// Extents of the box swept from the target to the camera, which keeps the near plane from clipping into walls
+const CAMERA_COLLISION_EXTENTS: f32 = 4.0;
+
+/// System which positions third person cameras behind the entity they follow, pulling them in whenever the view is blocked
+pub fn thirdperson_cam_update(time: &TimeData, map: &BspFile, world: &mut World) {
+    let physics = PhysicsWorld::new(map, world);
+
+    let cameras = world.query_mut::<&ThirdPersonCamera>().into_iter()
+        .map(|(e, cam)| (e, cam.follow_entity))
+        .collect::<Vec<_>>();
+
+    for (e, follow_entity) in cameras {
+        let Ok(target_pos) = world.get::<&Transform3D>(follow_entity).map(|x| x.position) else {
+            continue;
+        };
+        let target_view = world.get::<&FPView>(follow_entity).map(|x| (x.yaw, x.pitch)).ok();
+
+        let Ok((cam, cam_transform)) = world.query_one_mut::<(&mut ThirdPersonCamera, &mut Transform3D)>(e) else {
+            continue;
+        };
+
+        if let (ThirdPersonMode::Follow, Some((yaw, pitch))) = (cam.mode, target_view) {
+            cam.yaw = yaw;
+            cam.pitch = pitch;
+        }
+
+        cam.pitch = cam.pitch.clamp(cam.min_pitch, cam.max_pitch);
+        if let Some((min_yaw, max_yaw)) = cam.yaw_limits {
+            cam.yaw = cam.yaw.clamp(min_yaw, max_yaw);
+        }
+
+        // smooth out target movement, framerate independently
+        let target = target_pos + cam.target_offset;
+        let target = match cam.current_target {
+            Some(v) if cam.smoothing > 0.0 => v + ((target - v) * (1.0 - (-time.delta_time / cam.smoothing).exp())),
+            _ => target
+        };
+        cam.current_target = Some(target);
+
+        let rotation = Quaternion::from_euler(Vector3::new(cam.pitch.to_radians(), 0.0, cam.yaw.to_radians()));
+        let rot_matrix = Matrix4x4::rotation(rotation);
+        let fwd = rot_matrix.transform_direction(Vector3::unit_y());
+        let right = rot_matrix.transform_direction(Vector3::unit_x());
+
+        // sweep out to the shoulder, then back to the preferred distance. The camera snaps in immediately when blocked, but moves back out smoothly
+        let box_extents = Vector3::new(CAMERA_COLLISION_EXTENTS, CAMERA_COLLISION_EXTENTS, CAMERA_COLLISION_EXTENTS);
+        let ignore = [follow_entity, e];
+
+        let shoulder = physics.boxcast(MASK_SOLID, target, target + (right * cam.shoulder_offset), box_extents, &ignore).end_pos;
+        let trace = physics.boxcast(MASK_SOLID, shoulder, shoulder - (fwd * cam.distance), box_extents, &ignore);
+        let max_distance = if trace.start_solid { 0.0 } else { trace.fraction * cam.distance };
+
+        if max_distance < cam.current_distance {
+            cam.current_distance = max_distance;
+        }
+        else {
+            cam.current_distance = (cam.current_distance + (cam.return_speed * time.delta_time)).min(max_distance);
+        }
+
+        cam_transform.rotation = rotation;
+        cam_transform.position = shoulder - (fwd * cam.current_distance);
+    }
+}
@@ -1,15 +1,91 @@
+use std::collections::HashMap;
+
 use hecs::{CommandBuffer, World};
 
-use crate::component::triggerable::{TriggerLink, TriggerState};
+use crate::{bsp::bspcommon::aabb_aabb_intersects, component::{charactercontroller::CharacterController, transform3d::Transform3D, triggerable::{TriggerLink, TriggerState, TriggerVolume}}, gamestate::TimeData, math::Vector3, misc::AABB};
+
+/// System which triggers trigger volumes touched by characters
+pub fn trigger_volume_system_update(time: &TimeData, world: &mut World) {
+    // gather character bounds
+    let character_bounds = world.query_mut::<(&CharacterController, &Transform3D)>()
+        .into_iter()
+        .map(|(_, (cc, transform))| {
+            let center = transform.position + Vector3::new(0.0, 0.0, cc.height_offset);
+            AABB::center_extents(center, Vector3::new(cc.radius, cc.radius, cc.height_offset))
+        })
+        .collect::<Vec<_>>();
+
+    for (_, (volume, state)) in world.query_mut::<(&mut TriggerVolume, &mut TriggerState)>() {
+        // volumes stay triggered until their wait time elapses
+        if volume.wait_timer > 0.0 {
+            volume.wait_timer -= time.delta_time;
+            if volume.wait_timer > 0.0 {
+                continue;
+            }
+
+            state.triggered = false;
+        }
+
+        // trigger_once volumes stay triggered forever once fired
+        if volume.fired {
+            continue;
+        }
+
+        if character_bounds.iter().any(|x| aabb_aabb_intersects(x, &volume.bounds)) {
+            state.triggered = true;
+
+            if volume.wait < 0.0 {
+                volume.fired = true;
+            }
+            else {
+                volume.wait_timer = volume.wait;
+            }
+        }
+    }
+}
 
 /// System which propagates state of triggerable entities to linked targets, if any
-pub fn trigger_link_system_update(world: &mut World) {
+pub fn trigger_link_system_update(time: &TimeData, world: &mut World) {
+    let mut target_states = HashMap::new();
+    let mut killed = Vec::new();
+
+    for (_, (triggerable, link)) in world.query_mut::<(&TriggerState, &mut TriggerLink)>() {
+        // changes in state are held back for the link's delay before being passed on
+        if triggerable.triggered != link.pending {
+            link.pending = triggerable.triggered;
+            link.delay_timer = link.delay;
+        }
+
+        if link.output != link.pending {
+            link.delay_timer -= time.delta_time;
+
+            if link.delay_timer <= 0.0 {
+                link.output = link.pending;
+
+                if link.output {
+                    killed.extend(link.killtargets.iter().copied());
+                }
+            }
+        }
+
+        // a target is triggered if any of the entities targeting it are
+        for target in &link.targets {
+            let target_state = target_states.entry(*target).or_insert(false);
+            *target_state |= link.output;
+        }
+    }
+
     let mut cmd_buf = CommandBuffer::new();
-    for (_, (triggerable, link)) in world.query_mut::<(&mut TriggerState, &TriggerLink)>() {
-        cmd_buf.insert_one(link.target, TriggerState {
-            triggered: triggerable.triggered
+
+    for (target, triggered) in target_states {
+        cmd_buf.insert_one(target, TriggerState {
+            triggered
         });
     }
 
+    for e in killed {
+        cmd_buf.despawn(e);
+    }
+
     cmd_buf.run_on(world);
-}
\ No newline at end of file
+}